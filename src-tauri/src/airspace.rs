@@ -0,0 +1,347 @@
+use std::{fs, path::Path, str::FromStr};
+use anyhow::{Result, bail};
+use chrono::NaiveDateTime;
+
+use crate::logbook::FlightPoint;
+use crate::terrain::Terrain;
+
+pub mod openair;
+pub mod openaip;
+
+const EARTH_RADIUS: f64 = 6371000.0;// m
+const NAUTICAL_MILE: f64 = 1852.0;// m
+const FOOT: f64 = 0.3048;// m
+const ARC_STEP: f64 = 5.0;// deg between two points of an arc
+
+// Reference of an airspace limit
+//  Amsl        : above mean sea level
+//  Agl         : above the ground under the aircraft
+//  FlightLevel : pressure altitude, compared to the pressure altitude recorded by the logger (standard QNH)
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Reference {
+    Amsl,
+    Agl,
+    FlightLevel,
+}
+
+#[derive(Clone,Copy,Debug)]
+pub struct AltitudeLimit {
+    pub value: f64,// m, infinite when unlimited
+    pub reference: Reference,
+}
+
+// Lateral limits as a closed polygon of (lat, long), arcs and circles included
+#[derive(Clone,Debug)]
+pub struct Airspace {
+    pub name: String,
+    pub class: String,
+    pub floor: AltitudeLimit,
+    pub ceiling: AltitudeLimit,
+    pub polygon: Vec<(f64, f64)>,
+}
+
+// Consecutive fixes inside the same airspace
+#[derive(Clone,Debug)]
+pub struct Violation {
+    pub airspace: String,
+    pub class: String,
+    pub limits: String,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub alt: u32,// highest altitude inside
+    pub lat: f32,// first fix inside
+    pub long: f32,
+}
+
+impl AltitudeLimit {
+    pub fn ground() -> Self
+    {
+        AltitudeLimit { value: 0.0, reference: Reference::Agl }
+    }
+
+    pub fn unlimited() -> Self
+    {
+        AltitudeLimit { value: f64::INFINITY, reference: Reference::Amsl }
+    }
+
+    // Limit above mean sea level at a point whose ground elevation is known or not
+    pub fn amsl(&self, ground: Option<i32>) -> f64
+    {
+        match self.reference {
+            Reference::Agl => self.value + ground.unwrap_or(0) as f64,
+            _ => self.value,
+        }
+    }
+
+    // Altitude of the fix to compare with the limit. The flight altitude is QNH corrected,
+    // flight levels use the raw pressure altitude, the corrected one when the logger has none.
+    pub fn altitude(&self, pt: &FlightPoint) -> f64
+    {
+        match self.reference {
+            Reference::FlightLevel if pt.alt_baro > 0 => pt.alt_baro as f64,
+            _ => pt.alt as f64,
+        }
+    }
+
+    // Height above the ground, it can't be checked without DEM. The ground itself is always below the flight.
+    pub fn needs_ground(&self) -> bool
+    {
+        self.reference == Reference::Agl && self.value > 0.0 && self.value.is_finite()
+    }
+}
+
+impl ToString for AltitudeLimit {
+    fn to_string(&self) -> String {
+        if self.value.is_infinite()
+        {
+            return "UNL".to_string();
+        }
+
+        match self.reference {
+            Reference::Agl if self.value == 0.0 => "GND".to_string(),
+            Reference::Agl => format!("{:.0}m AGL", self.value),
+            Reference::Amsl => format!("{:.0}m AMSL", self.value),
+            Reference::FlightLevel => format!("FL{:.0}", self.value / FOOT / 100.0),
+        }
+    }
+}
+
+impl FromStr for AltitudeLimit {
+    type Err = anyhow::Error;
+
+    // OpenAir style limits : SFC, GND, UNL, FL65, FL 65, 1500ft AMSL, 1500 MSL, 300m AGL, 1000F ASFC
+    fn from_str(s: &str) -> Result<Self> {
+        let limit = s.trim().to_uppercase();
+
+        if limit.is_empty() || limit.starts_with("UNL")
+        {
+            return Ok(AltitudeLimit::unlimited());
+        }
+        if limit == "SFC" || limit == "GND" || limit == "0"
+        {
+            return Ok(AltitudeLimit::ground());
+        }
+        if let Some(level) = limit.strip_prefix("FL")
+        {
+            let level: f64 = level.trim().parse()?;
+            return Ok(AltitudeLimit { value: level * 100.0 * FOOT, reference: Reference::FlightLevel });
+        }
+
+        let digits: String = limit.chars().take_while(|c| c.is_ascii_digit() || *c == '.').collect();
+        if digits.is_empty()
+        {
+            bail!("Invalid altitude limit {}", s);
+        }
+        let unit = limit[digits.len()..].trim_start();
+        let value: f64 = digits.parse()?;
+
+        let value = if unit.starts_with('M') && !unit.starts_with("MSL") { value } else { value * FOOT };
+        let reference = if unit.contains("AGL") || unit.contains("SFC") || unit.contains("GND") { Reference::Agl } else { Reference::Amsl };
+
+        Ok(AltitudeLimit { value, reference })
+    }
+}
+
+impl Airspace {
+    // Ray casting in degrees, good enough at the size of an airspace
+    pub fn contains(&self, lat: f64, long: f64) -> bool
+    {
+        let mut inside = false;
+        let n = self.polygon.len();
+
+        for i in 0..n
+        {
+            let (lat1, long1) = self.polygon[i];
+            let (lat2, long2) = self.polygon[(i + n - 1) % n];
+
+            if (lat1 > lat) != (lat2 > lat) && long < (long2 - long1) * (lat - lat1) / (lat2 - lat1) + long1
+            {
+                inside = !inside;
+            }
+        }
+
+        inside
+    }
+
+    fn bounding_box(&self) -> (f64, f64, f64, f64)
+    {
+        self.polygon.iter().fold(
+            (f64::MAX, f64::MIN, f64::MAX, f64::MIN),
+            |(s, n, w, e), &(lat, long)| (s.min(lat), n.max(lat), w.min(long), e.max(long)),
+        )
+    }
+}
+
+// OpenAir text file or OpenAIP XML file, from the extension
+pub fn load(path: &Path) -> Result<Vec<Airspace>>
+{
+    let content = fs::read_to_string(path)?;
+    let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+
+    let airspaces = match extension.as_deref() {
+        Some("xml") | Some("aip") => openaip::parse(&content)?,
+        _ => openair::parse(&content)?,
+    };
+
+    if airspaces.is_empty()
+    {
+        bail!("No airspace in {}", path.display());
+    }
+
+    Ok(airspaces)
+}
+
+// Every part of the track inside an airspace, AGL limits use the DEM.
+// Without DEM the airspaces with AGL limits can't be checked, the ones crossed are returned by name.
+pub fn check(track: &[FlightPoint], airspaces: &Vec<Airspace>, terrain: &mut Option<Terrain>) -> (Vec<Violation>, Vec<String>)
+{
+    let mut violations: Vec<Violation> = Vec::new();
+    let mut unchecked: Vec<String> = Vec::new();
+
+    for airspace in airspaces
+    {
+        let (south, north, west, east) = airspace.bounding_box();
+        let mut current: Option<Violation> = None;
+        let needs_ground = airspace.floor.needs_ground() || airspace.ceiling.needs_ground();
+
+        if needs_ground && terrain.is_none()
+        {
+            if track.iter().any(|pt| airspace.contains(pt.lat as f64, pt.long as f64))
+            {
+                unchecked.push(airspace.name.clone());
+            }
+            continue;
+        }
+
+        for pt in track
+        {
+            let (lat, long) = (pt.lat as f64, pt.long as f64);
+            let mut inside = south <= lat && lat <= north && west <= long && long <= east && airspace.contains(lat, long);
+
+            if inside
+            {
+                let ground = match needs_ground {
+                    true => terrain.as_mut().and_then(|t| t.elevation(pt.lat, pt.long)),
+                    false => None,
+                };
+                inside = airspace.floor.amsl(ground) <= airspace.floor.altitude(pt) && airspace.ceiling.altitude(pt) <= airspace.ceiling.amsl(ground);
+            }
+
+            current = match (current, inside) {
+                (None, true) => Some(Violation {
+                    airspace: airspace.name.clone(),
+                    class: airspace.class.clone(),
+                    limits: format!("{} - {}", airspace.floor.to_string(), airspace.ceiling.to_string()),
+                    start: pt.time,
+                    end: pt.time,
+                    alt: pt.alt,
+                    lat: pt.lat,
+                    long: pt.long,
+                }),
+                (Some(mut v), true) => {
+                    v.end = pt.time;
+                    v.alt = v.alt.max(pt.alt);
+                    Some(v)
+                },
+                (Some(v), false) => {
+                    violations.push(v);
+                    None
+                },
+                (None, false) => None,
+            };
+        }
+
+        violations.extend(current);
+    }
+
+    violations.sort_by_key(|v| v.start);
+    (violations, unchecked)
+}
+
+// Point at a distance (m) and bearing (deg) from a centre
+pub fn destination(center: (f64, f64), distance: f64, bearing: f64) -> (f64, f64)
+{
+    let bearing = bearing.to_radians();
+    let lat = center.0 + (distance * bearing.cos() / EARTH_RADIUS).to_degrees();
+    let long = center.1 + (distance * bearing.sin() / (EARTH_RADIUS * center.0.to_radians().cos())).to_degrees();
+
+    (lat, long)
+}
+
+// Distance (m) and bearing (deg) of a point from a centre
+pub fn polar(center: (f64, f64), point: (f64, f64)) -> (f64, f64)
+{
+    let north = (point.0 - center.0).to_radians() * EARTH_RADIUS;
+    let east = (point.1 - center.1).to_radians() * EARTH_RADIUS * center.0.to_radians().cos();
+
+    (north.hypot(east), (east.atan2(north).to_degrees() + 360.0) % 360.0)
+}
+
+// Points of an arc from one bearing to the other, clockwise or not
+pub fn arc(center: (f64, f64), radius: f64, from: f64, to: f64, clockwise: bool) -> Vec<(f64, f64)>
+{
+    let sweep = if clockwise { (to - from + 360.0) % 360.0 } else { -((from - to + 360.0) % 360.0) };
+    let sweep = if sweep == 0.0 { if clockwise { 360.0 } else { -360.0 } } else { sweep };
+    let steps = (sweep.abs() / ARC_STEP).ceil().max(1.0) as usize;
+
+    (0..=steps)
+        .map(|i| destination(center, radius, from + sweep * i as f64 / steps as f64))
+        .collect()
+}
+
+pub fn circle(center: (f64, f64), radius: f64) -> Vec<(f64, f64)>
+{
+    let mut points = arc(center, radius, 0.0, 360.0, true);
+    points.pop();
+    points
+}
+
+#[cfg(test)]
+mod tests
+{
+    use chrono::NaiveDate;
+    use super::*;
+
+    fn airspace(floor: &str, ceiling: &str) -> Airspace
+    {
+        Airspace {
+            name: "TEST".to_string(),
+            class: "D".to_string(),
+            floor: floor.parse().unwrap(),
+            ceiling: ceiling.parse().unwrap(),
+            polygon: vec![(45.0, 6.0), (46.0, 6.0), (46.0, 7.0), (45.0, 7.0)],
+        }
+    }
+
+    fn fix(alt: u32, alt_baro: u32) -> FlightPoint
+    {
+        let time = NaiveDate::from_ymd_opt(2024, 8, 12).unwrap().and_hms_opt(12, 0, 0).unwrap();
+        FlightPoint { time, lat: 45.5, long: 6.5, alt, alt_gps: alt, alt_baro }
+    }
+
+    #[test]
+    fn flight_levels_use_the_pressure_altitude()
+    {
+        // FL95 is 2896 m, QNH 1033 puts the corrected altitude 160 m above the pressure one
+        let fl95 = vec![airspace("2000m AMSL", "FL95")];
+
+        let (violations, _) = check(&[fix(3000, 2840)], &fl95, &mut None);
+        assert_eq!(violations.len(), 1);
+
+        let (violations, _) = check(&[fix(2850, 2950)], &fl95, &mut None);
+        assert!(violations.is_empty());
+    }
+
+    #[test]
+    fn agl_limits_need_a_dem()
+    {
+        let (violations, unchecked) = check(&[fix(2500, 2500)], &vec![airspace("300m AGL", "3000m AMSL")], &mut None);
+        assert!(violations.is_empty());
+        assert_eq!(unchecked, vec!["TEST"]);
+
+        // Ground floor is always below the flight
+        let (violations, unchecked) = check(&[fix(2500, 2500)], &vec![airspace("GND", "3000m AMSL")], &mut None);
+        assert_eq!(violations.len(), 1);
+        assert!(unchecked.is_empty());
+    }
+}
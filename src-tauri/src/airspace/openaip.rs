@@ -0,0 +1,119 @@
+use anyhow::{Result, bail};
+
+use super::{Airspace, AltitudeLimit, Reference, FOOT};
+
+// OpenAIP XML export : one ASP element per airspace with its limits and a POLYGON of "long lat" pairs
+pub fn parse(content: &str) -> Result<Vec<Airspace>>
+{
+    let mut airspaces: Vec<Airspace> = Vec::new();
+
+    for (attributes, body) in elements(content, "ASP")
+    {
+        let class = attribute(attributes, "CATEGORY").unwrap_or_default();
+        let name = match elements(body, "NAME").first() {
+            Some((_, n)) => n.trim().to_string(),
+            None => String::new(),
+        };
+
+        let polygon: Vec<(f64, f64)> = match elements(body, "POLYGON").first() {
+            None => bail!("Airspace {} without polygon", name),
+            Some((_, p)) => p.split(',')
+                .filter_map(|pair| {
+                    let mut values = pair.split_whitespace().map(|v| v.parse::<f64>());
+                    match (values.next(), values.next()) {
+                        (Some(Ok(long)), Some(Ok(lat))) => Some((lat, long)),
+                        _ => None,
+                    }
+                })
+                .collect(),
+        };
+
+        if polygon.len() < 3
+        {
+            continue;
+        }
+
+        airspaces.push(Airspace {
+            floor: limit(body, "ALTLIMIT_BOTTOM")?,
+            ceiling: limit(body, "ALTLIMIT_TOP")?,
+            name,
+            class,
+            polygon,
+        });
+    }
+
+    Ok(airspaces)
+}
+
+// <ALTLIMIT_TOP REFERENCE="STD"><ALT UNIT="FL">65</ALT></ALTLIMIT_TOP>, references MSL, GND or STD, units F, M or FL
+fn limit(body: &str, tag: &str) -> Result<AltitudeLimit>
+{
+    let (attributes, content) = match elements(body, tag).first() {
+        None => bail!("Missing {}", tag),
+        Some(e) => *e,
+    };
+    let (alt_attributes, alt) = match elements(content, "ALT").first() {
+        None => bail!("Missing {} altitude", tag),
+        Some(e) => *e,
+    };
+
+    let value: f64 = alt.trim().parse()?;
+    let unit = attribute(alt_attributes, "UNIT").unwrap_or_default();
+
+    let value = match unit.as_str() {
+        "M" => value,
+        "FL" => value * 100.0 * FOOT,
+        _ => value * FOOT,
+    };
+    let reference = match attribute(attributes, "REFERENCE").as_deref() {
+        Some("GND") => Reference::Agl,
+        Some("STD") => Reference::FlightLevel,
+        _ => Reference::Amsl,
+    };
+
+    Ok(AltitudeLimit { value, reference })
+}
+
+// Attributes and content of every <tag ...>...</tag>, enough for the flat structure of the export
+fn elements<'a>(xml: &'a str, tag: &str) -> Vec<(&'a str, &'a str)>
+{
+    let mut found: Vec<(&str, &str)> = Vec::new();
+    let open = format!("<{}", tag);
+    let close = format!("</{}>", tag);
+    let mut rest = xml;
+
+    while let Some(start) = rest.find(&open)
+    {
+        let after = &rest[start + open.len()..];
+
+        // <ASP must not match <ASPACE
+        if !after.starts_with(|c: char| c == '>' || c.is_whitespace())
+        {
+            rest = after;
+            continue;
+        }
+
+        let (attributes, body) = match after.split_once('>') {
+            None => break,
+            Some(s) => s,
+        };
+        let end = match body.find(&close) {
+            None => break,
+            Some(e) => e,
+        };
+
+        found.push((attributes, &body[..end]));
+        rest = &body[end + close.len()..];
+    }
+
+    found
+}
+
+fn attribute(attributes: &str, name: &str) -> Option<String>
+{
+    let key = format!("{}=\"", name);
+    let start = attributes.find(&key)? + key.len();
+    let end = attributes[start..].find('"')?;
+
+    Some(attributes[start..start + end].to_string())
+}
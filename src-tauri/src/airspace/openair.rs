@@ -0,0 +1,143 @@
+use anyhow::{Result, bail};
+
+use super::{Airspace, AltitudeLimit, NAUTICAL_MILE};
+
+// Airspace being read, the direction and centre stay set until the next V record
+struct Builder {
+    airspace: Option<Airspace>,
+    center: (f64, f64),
+    clockwise: bool,
+}
+
+impl Builder {
+    fn finish(&mut self, airspaces: &mut Vec<Airspace>)
+    {
+        if let Some(a) = self.airspace.take() {
+            if a.polygon.len() >= 3 {
+                airspaces.push(a);
+            }
+        }
+        self.clockwise = true;
+    }
+
+    fn read(&mut self, record: &str, value: &str) -> Result<()>
+    {
+        let airspace = match self.airspace.as_mut() {
+            None => return Ok(()),// records before the first AC
+            Some(a) => a,
+        };
+
+        match record {
+            "AN" => airspace.name = value.to_string(),
+            "AL" => airspace.floor = value.parse()?,
+            "AH" => airspace.ceiling = value.parse()?,
+            "V" => match value.split_once('=') {
+                Some((k, v)) if k.trim().eq_ignore_ascii_case("X") => self.center = coordinate(v)?,
+                Some((k, v)) if k.trim().eq_ignore_ascii_case("D") => self.clockwise = v.trim() != "-",
+                _ => {},
+            },
+            "DP" => airspace.polygon.push(coordinate(value)?),
+            "DA" => {
+                let values: Vec<f64> = value.split(',').map(|v| v.trim().parse()).collect::<Result<_, _>>()?;
+                if values.len() != 3
+                {
+                    bail!("Invalid arc {}", value);
+                }
+                airspace.polygon.extend(super::arc(self.center, values[0] * NAUTICAL_MILE, values[1], values[2], self.clockwise));
+            },
+            "DB" => {
+                let (from, to) = match value.split_once(',') {
+                    Some((f, t)) => (coordinate(f)?, coordinate(t)?),
+                    None => bail!("Invalid arc {}", value),
+                };
+                let (radius, start) = super::polar(self.center, from);
+                let (_, end) = super::polar(self.center, to);
+                airspace.polygon.extend(super::arc(self.center, radius, start, end, self.clockwise));
+            },
+            "DC" => airspace.polygon.extend(super::circle(self.center, value.parse::<f64>()? * NAUTICAL_MILE)),
+            _ => {},
+        }
+
+        Ok(())
+    }
+}
+
+// OpenAir format : AC class, AN name, AL/AH floor and ceiling, DP points, V X=/D= arc centre and direction,
+// DA/DB arcs and DC circles, radius in nautical miles
+pub fn parse(content: &str) -> Result<Vec<Airspace>>
+{
+    let mut airspaces: Vec<Airspace> = Vec::new();
+    let mut builder = Builder { airspace: None, center: (0.0, 0.0), clockwise: true };
+
+    for (nb, line) in content.lines().enumerate()
+    {
+        let line = line.split('*').next().unwrap_or("").trim();
+        if line.len() < 2
+        {
+            continue;
+        }
+
+        let (record, value) = match line.split_once(char::is_whitespace) {
+            Some((r, v)) => (r.to_uppercase(), v.trim()),
+            None => (line.to_uppercase(), ""),
+        };
+
+        if record == "AC"
+        {
+            builder.finish(&mut airspaces);
+            builder.airspace = Some(Airspace {
+                name: String::new(),
+                class: value.to_string(),
+                floor: AltitudeLimit::ground(),
+                ceiling: AltitudeLimit::unlimited(),
+                polygon: Vec::new(),
+            });
+            continue;
+        }
+
+        if let Err(e) = builder.read(&record, value)
+        {
+            bail!("OpenAir line {} : {}", nb + 1, e);
+        }
+    }
+
+    builder.finish(&mut airspaces);
+
+    Ok(airspaces)
+}
+
+// 45:30:00 N 006:15:00 E or 45:30.5N 6:15.2E
+fn coordinate(value: &str) -> Result<(f64, f64)>
+{
+    let value = value.trim().to_uppercase();
+    let split = match value.find(|c| c == 'N' || c == 'S') {
+        Some(i) => i,
+        None => bail!("Invalid coordinate {}", value),
+    };
+
+    let lat = degrees(&value[..split])?;
+    let lat = if &value[split..split + 1] == "S" { -lat } else { lat };
+
+    let long_part = value[split + 1..].trim();
+    let long = match long_part.strip_suffix('W') {
+        Some(l) => -degrees(l)?,
+        None => match long_part.strip_suffix('E') {
+            Some(l) => degrees(l)?,
+            None => bail!("Invalid coordinate {}", value),
+        },
+    };
+
+    Ok((lat, long))
+}
+
+fn degrees(value: &str) -> Result<f64>
+{
+    let parts: Vec<f64> = value.trim().split(':').map(|p| p.trim().parse()).collect::<Result<_, _>>()?;
+
+    match parts.as_slice() {
+        [d] => Ok(*d),
+        [d, m] => Ok(d + m / 60.0),
+        [d, m, s] => Ok(d + m / 60.0 + s / 3600.0),
+        _ => bail!("Invalid coordinate {}", value),
+    }
+}
@@ -0,0 +1,34 @@
+use serde::Serialize;
+
+#[derive(Debug,Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    NotFound,
+    Database,
+    File,
+    Invalid,
+}
+
+// Error returned by the commands, the frontend gets { kind, message }
+#[derive(Debug,Serialize)]
+pub struct CommandError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl From<anyhow::Error> for CommandError {
+    fn from(err: anyhow::Error) -> Self {
+        let kind = if let Some(e) = err.downcast_ref::<rusqlite::Error>() {
+            match e {
+                rusqlite::Error::QueryReturnedNoRows => ErrorKind::NotFound,
+                _ => ErrorKind::Database,
+            }
+        } else if err.downcast_ref::<std::io::Error>().is_some() {
+            ErrorKind::File
+        } else {
+            ErrorKind::Invalid
+        };
+
+        CommandError { kind, message: err.to_string() }
+    }
+}
@@ -0,0 +1,87 @@
+use anyhow::{Result, bail};
+use rusqlite::Connection;
+
+use crate::{flight_track::FlightTrack, logbook::{Logbook, FlightPoint, flight_table::FlightTable}};
+
+pub mod kml;
+pub mod gpx;
+pub mod geojson;
+
+// Stored flight with its full resolution track rebuilt from the raw IGC
+pub fn load_flight(db_conn: &Connection, flight_id: u32) -> Result<(FlightTable,FlightTrack)>
+{
+    let flight = FlightTable::get(db_conn, flight_id)?;
+
+    let track = match Logbook::stored_track(db_conn, flight_id)? {
+        None => bail!("Flight {} has no track", flight_id),
+        Some(t) => t,
+    };
+
+    Ok((flight,track))
+}
+
+// Flights of a selection that have a track, the ones entered by hand are left out
+pub fn load_flights(db_conn: &Connection, flight_ids: &Vec<u32>) -> Result<Vec<(FlightTable,FlightTrack)>>
+{
+    let mut flights = Vec::new();
+
+    for id in flight_ids
+    {
+        let flight = FlightTable::get(db_conn, *id)?;
+
+        if let Some(track) = Logbook::stored_track(db_conn, *id)? {
+            flights.push((flight, track));
+        }
+    }
+
+    if flights.is_empty()
+    {
+        bail!("No flight with a track in the selection");
+    }
+
+    Ok(flights)
+}
+
+// Absolute altitude, GPS one when the logger recorded it
+pub fn altitude(pt: &FlightPoint) -> u32
+{
+    if pt.alt_gps > 0 { pt.alt_gps } else { pt.alt }
+}
+
+pub fn escape(text: &str) -> String
+{
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+pub mod tests
+{
+    use std::path::Path;
+    use crate::logbook::{database::Database, duplicate::DuplicatePolicy, flight_table::{ManualFlight, SiteChoice}};
+    use super::*;
+
+    // Logbook with a flight entered by hand and a flight from a track file, with the number of fixes of its track
+    pub fn logbook() -> (Database, Vec<u32>, usize)
+    {
+        let db = Database::in_memory().unwrap();
+        let mut db_conn = db.connection().unwrap();
+
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/top_landing.igc");
+        let (raw_igc, tracks) = Logbook::read(Path::new(path)).unwrap();
+        let parsed = Logbook::parse(raw_igc, tracks, &mut None, &Vec::new()).unwrap();
+        let flight_id = Logbook::store(&db_conn, path, parsed, DuplicatePolicy::Skip).unwrap().flight_ids[0];
+
+        let takeoff_id = FlightTable::get(&db_conn, flight_id).unwrap().takeoff_id;
+        let manual = ManualFlight { date: "2024-08-11".to_string(), duration: 45, distance: 8000, takeoff: SiteChoice::Existing(takeoff_id), landing: None, wing_id: None };
+        let (manual_id, _) = Logbook::add_manual_flight(&mut db_conn, manual).unwrap();
+
+        let nb_fix = Logbook::stored_track(&db_conn, flight_id).unwrap().unwrap().track.len();
+        drop(db_conn);
+
+        (db, vec![manual_id, flight_id], nb_fix)
+    }
+}
@@ -0,0 +1,74 @@
+use std::{fs, path::Path};
+use anyhow::Result;
+use rusqlite::Connection;
+use serde_json::{json, Value};
+
+use super::{altitude, load_flights};
+
+pub struct GeoJsonExport;
+
+impl GeoJsonExport {
+    pub fn export(db_conn: &Connection, flight_ids: &Vec<u32>, path: &Path) -> Result<()>
+    {
+        fs::write(path, Self::build(db_conn, flight_ids)?.to_string())?;
+        Ok(())
+    }
+
+    // FeatureCollection with one LineString per flight with a track, fix times are in the coordTimes property
+    pub fn build(db_conn: &Connection, flight_ids: &Vec<u32>) -> Result<Value>
+    {
+        let mut features: Vec<Value> = Vec::new();
+
+        for (flight, track) in load_flights(db_conn, flight_ids)?
+        {
+            let coordinates: Vec<Value> = track.track.iter().map(|pt| json!([pt.long, pt.lat, altitude(pt)])).collect();
+            let times: Vec<String> = track.track.iter().map(|pt| pt.time.format("%Y-%m-%dT%H:%M:%SZ").to_string()).collect();
+
+            features.push(json!({
+                "type": "Feature",
+                "id": flight.flight_id,
+                "properties": {
+                    "date": flight.date,
+                    "duration": flight.duration,
+                    "distance": flight.distance,
+                    "score": flight.score,
+                    "code": flight.code,
+                    "coordTimes": times,
+                },
+                "geometry": {
+                    "type": "LineString",
+                    "coordinates": coordinates,
+                },
+            }));
+        }
+
+        Ok(json!({
+            "type": "FeatureCollection",
+            "features": features,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::export::tests::logbook;
+
+    #[test]
+    fn flights_with_track()
+    {
+        let (db, flight_ids, nb_fix) = logbook();
+        let db_conn = db.connection().unwrap();
+
+        // The flight entered by hand is left out
+        let geojson = GeoJsonExport::build(&db_conn, &flight_ids).unwrap();
+        let features = geojson["features"].as_array().unwrap();
+        assert_eq!(features.len(), 1);
+        assert_eq!(features[0]["id"], flight_ids[1]);
+        assert_eq!(features[0]["geometry"]["coordinates"].as_array().unwrap().len(), nb_fix);
+        assert_eq!(features[0]["properties"]["coordTimes"].as_array().unwrap().len(), nb_fix);
+
+        assert!(GeoJsonExport::build(&db_conn, &flight_ids[..1].to_vec()).is_err());
+    }
+}
@@ -0,0 +1,69 @@
+use std::{fs, path::Path};
+use anyhow::Result;
+use rusqlite::Connection;
+
+use super::{altitude, escape, load_flights};
+
+const GPX_CREATOR: &str = "Flight Journal";
+
+pub struct GpxExport;
+
+impl GpxExport {
+    pub fn export(db_conn: &Connection, flight_ids: &Vec<u32>, path: &Path) -> Result<()>
+    {
+        fs::write(path, Self::build(db_conn, flight_ids)?)?;
+        Ok(())
+    }
+
+    // GPX 1.1 document with one track per flight, flights without track are left out
+    pub fn build(db_conn: &Connection, flight_ids: &Vec<u32>) -> Result<String>
+    {
+        let mut gpx = String::new();
+
+        gpx.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        gpx.push_str(&format!(
+            "<gpx version=\"1.1\" creator=\"{}\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n",
+            GPX_CREATOR
+        ));
+
+        for (flight, track) in load_flights(db_conn, flight_ids)?
+        {
+            gpx.push_str(&format!("<trk>\n<name>{}</name>\n<trkseg>\n", escape(&flight.date)));
+
+            for pt in &track.track
+            {
+                gpx.push_str(&format!(
+                    "<trkpt lat=\"{}\" lon=\"{}\"><ele>{}</ele><time>{}</time></trkpt>\n",
+                    pt.lat, pt.long, altitude(pt), pt.time.format("%Y-%m-%dT%H:%M:%SZ")
+                ));
+            }
+
+            gpx.push_str("</trkseg>\n</trk>\n");
+        }
+
+        gpx.push_str("</gpx>\n");
+
+        Ok(gpx)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::export::tests::logbook;
+
+    #[test]
+    fn flights_with_track()
+    {
+        let (db, flight_ids, nb_fix) = logbook();
+        let db_conn = db.connection().unwrap();
+
+        // The flight entered by hand is left out
+        let gpx = GpxExport::build(&db_conn, &flight_ids).unwrap();
+        assert_eq!(gpx.matches("<trk>").count(), 1);
+        assert_eq!(gpx.matches("<trkpt ").count(), nb_fix);
+
+        assert!(GpxExport::build(&db_conn, &flight_ids[..1].to_vec()).is_err());
+    }
+}
@@ -0,0 +1,344 @@
+use std::{fs, path::Path, str::FromStr};
+use anyhow::Result;
+use rusqlite::Connection;
+use serde_json::Value;
+
+use crate::{flight_track::{FlightProfile, FlightProfilePoint}, logbook::site_table::SiteTable};
+use super::{escape, load_flight};
+
+const KMZ_EXTENSION: &str = "kmz";
+const KMZ_DOCUMENT: &str = "doc.kml";
+
+// Vario classes (m/s) and their colour (aabbggrr) from strong sink to strong climb
+const VARIO_CLASSES: [f32; 6] = [-3.0, -1.5, -0.5, 0.5, 1.5, 3.0];
+const VARIO_COLORS: [&str; 7] = ["ff8b0000", "ffff0000", "ffffff00", "ff00ff00", "ff00ffff", "ff0080ff", "ff0000ff"];
+
+const TRACK_COLOR: &str = "ffe5003a";
+const ROUTE_COLOR: &str = "ff0078ff";
+
+pub struct KmlExport;
+
+impl KmlExport {
+    pub fn export(db_conn: &Connection, flight_id: u32, path: &Path) -> Result<()>
+    {
+        let kml = Self::build(db_conn, flight_id)?;
+
+        let is_kmz = path.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase() == KMZ_EXTENSION)
+            .unwrap_or(false);
+
+        if is_kmz
+        {
+            fs::write(path, Self::kmz(&kml))?;
+        } else {
+            fs::write(path, kml)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn build(db_conn: &Connection, flight_id: u32) -> Result<String>
+    {
+        let (flight, track) = load_flight(db_conn, flight_id)?;
+        let mut kml = String::new();
+
+        kml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        kml.push_str("<kml xmlns=\"http://www.opengis.net/kml/2.2\">\n<Document>\n");
+        kml.push_str(&format!("<name>{}</name>\n", escape(&flight.date)));
+        kml.push_str(&Self::styles());
+
+        // 3D track extruded to the ground, at the altitude chosen for the flight like the vario segments of the profile
+        kml.push_str("<Placemark>\n<name>Track</name>\n<styleUrl>#track</styleUrl>\n");
+        kml.push_str("<LineString>\n<extrude>1</extrude>\n<altitudeMode>absolute</altitudeMode>\n<coordinates>\n");
+        for pt in &track.track
+        {
+            kml.push_str(&format!("{},{},{}\n", pt.long, pt.lat, pt.alt));
+        }
+        kml.push_str("</coordinates>\n</LineString>\n</Placemark>\n");
+
+        if let Some(profile) = flight.profile.as_ref().and_then(|p| FlightProfile::from_str(p).ok())
+        {
+            kml.push_str(&Self::vario(&profile));
+        }
+
+        for (name, site_id) in [("Takeoff", flight.takeoff_id), ("Landing", flight.landing_id)]
+        {
+            if let Ok(site) = SiteTable::get(db_conn, site_id)
+            {
+                kml.push_str(&format!(
+                    "<Placemark>\n<name>{}: {}</name>\n<Point>\n<altitudeMode>absolute</altitudeMode>\n<coordinates>{},{},{}</coordinates>\n</Point>\n</Placemark>\n",
+                    name, escape(&site.name), site.long, site.lat, site.alt
+                ));
+            }
+        }
+
+        if let Some(route) = flight.track.as_ref().and_then(|t| serde_json::from_str::<Value>(t).ok())
+        {
+            kml.push_str(&Self::route(&route));
+        }
+
+        kml.push_str("</Document>\n</kml>\n");
+
+        Ok(kml)
+    }
+
+    fn vario_class(vario: f32) -> usize
+    {
+        VARIO_CLASSES.iter().filter(|&&v| vario > v).count()
+    }
+
+    fn styles() -> String
+    {
+        let mut styles = String::new();
+
+        styles.push_str(&format!(
+            "<Style id=\"track\">\n<LineStyle><color>{}</color><width>2</width></LineStyle>\n<PolyStyle><color>40{}</color></PolyStyle>\n</Style>\n",
+            TRACK_COLOR, &TRACK_COLOR[2..]
+        ));
+        styles.push_str(&format!(
+            "<Style id=\"route\">\n<LineStyle><color>{}</color><width>3</width></LineStyle>\n</Style>\n",
+            ROUTE_COLOR
+        ));
+
+        for (i, color) in VARIO_COLORS.iter().enumerate()
+        {
+            styles.push_str(&format!(
+                "<Style id=\"vario{}\">\n<LineStyle><color>{}</color><width>4</width></LineStyle>\n</Style>\n",
+                i, color
+            ));
+        }
+
+        styles
+    }
+
+    // One line per run of points in the same vario class
+    fn vario(profile: &FlightProfile) -> String
+    {
+        let mut kml = String::new();
+        let mut segment: Vec<&FlightProfilePoint> = Vec::new();
+        let mut class = 0;
+
+        kml.push_str("<Folder>\n<name>Vario</name>\n");
+
+        for pt in &profile.points
+        {
+            let pt_class = Self::vario_class(pt.vario);
+
+            if !segment.is_empty() && pt_class != class
+            {
+                segment.push(pt);
+                kml.push_str(&Self::vario_segment(&segment, class));
+                segment.clear();
+            }
+
+            class = pt_class;
+            segment.push(pt);
+        }
+
+        if segment.len() > 1
+        {
+            kml.push_str(&Self::vario_segment(&segment, class));
+        }
+
+        kml.push_str("</Folder>\n");
+
+        kml
+    }
+
+    fn vario_segment(segment: &Vec<&FlightProfilePoint>, class: usize) -> String
+    {
+        let mut kml = format!(
+            "<Placemark>\n<styleUrl>#vario{}</styleUrl>\n<LineString>\n<altitudeMode>absolute</altitudeMode>\n<coordinates>\n",
+            class
+        );
+
+        for pt in segment
+        {
+            kml.push_str(&format!("{},{},{}\n", pt.lng, pt.lat, pt.alt));
+        }
+
+        kml.push_str("</coordinates>\n</LineString>\n</Placemark>\n");
+
+        kml
+    }
+
+    // Scored route from the GeoJSON stored with the flight
+    fn route(geojson: &Value) -> String
+    {
+        let mut kml = String::new();
+        let features = match geojson["features"].as_array() {
+            Some(f) => f,
+            None => return kml,
+        };
+
+        kml.push_str(&format!(
+            "<Folder>\n<name>{}</name>\n",
+            escape(geojson["properties"]["name"].as_str().unwrap_or("Route"))
+        ));
+
+        for feature in features
+        {
+            let id = feature["id"].as_str().unwrap_or("");
+            let coordinates = &feature["geometry"]["coordinates"];
+
+            if id.starts_with("tp")
+            {
+                kml.push_str(&format!(
+                    "<Placemark>\n<name>{}</name>\n<Point>\n<coordinates>{},{}</coordinates>\n</Point>\n</Placemark>\n",
+                    id.to_uppercase(), coordinates[0], coordinates[1]
+                ));
+            } else if id.starts_with("seg") || id == "closing" {
+                let mut line = String::new();
+                for pt in coordinates.as_array().unwrap_or(&Vec::new())
+                {
+                    line.push_str(&format!("{},{}\n", pt[0], pt[1]));
+                }
+
+                kml.push_str(&format!(
+                    "<Placemark>\n<name>{}</name>\n<styleUrl>#route</styleUrl>\n<LineString>\n<tessellate>1</tessellate>\n<coordinates>\n{}</coordinates>\n</LineString>\n</Placemark>\n",
+                    id, line
+                ));
+            }
+        }
+
+        kml.push_str("</Folder>\n");
+
+        kml
+    }
+
+    // KMZ is a zip archive with the KML as doc.kml, stored without compression
+    fn kmz(kml: &String) -> Vec<u8>
+    {
+        let data = kml.as_bytes();
+        let name = KMZ_DOCUMENT.as_bytes();
+        let crc = Self::crc32(data);
+        let mut zip: Vec<u8> = Vec::new();
+
+        // Local file header
+        zip.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        zip.extend_from_slice(&20u16.to_le_bytes());// version needed
+        zip.extend_from_slice(&0u16.to_le_bytes());// flags
+        zip.extend_from_slice(&0u16.to_le_bytes());// stored
+        zip.extend_from_slice(&0u16.to_le_bytes());// time
+        zip.extend_from_slice(&0x21u16.to_le_bytes());// date 1980-01-01
+        zip.extend_from_slice(&crc.to_le_bytes());
+        zip.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        zip.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        zip.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        zip.extend_from_slice(&0u16.to_le_bytes());// extra
+        zip.extend_from_slice(name);
+        zip.extend_from_slice(data);
+
+        let central_directory = zip.len();
+
+        // Central directory
+        zip.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        zip.extend_from_slice(&20u16.to_le_bytes());// version made by
+        zip.extend_from_slice(&20u16.to_le_bytes());// version needed
+        zip.extend_from_slice(&0u16.to_le_bytes());
+        zip.extend_from_slice(&0u16.to_le_bytes());
+        zip.extend_from_slice(&0u16.to_le_bytes());
+        zip.extend_from_slice(&0x21u16.to_le_bytes());
+        zip.extend_from_slice(&crc.to_le_bytes());
+        zip.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        zip.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        zip.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        zip.extend_from_slice(&0u16.to_le_bytes());// extra
+        zip.extend_from_slice(&0u16.to_le_bytes());// comment
+        zip.extend_from_slice(&0u16.to_le_bytes());// disk
+        zip.extend_from_slice(&0u16.to_le_bytes());// internal attributes
+        zip.extend_from_slice(&0u32.to_le_bytes());// external attributes
+        zip.extend_from_slice(&0u32.to_le_bytes());// local header offset
+        zip.extend_from_slice(name);
+
+        let central_directory_size = zip.len() - central_directory;
+
+        // End of central directory
+        zip.extend_from_slice(&0x06054b50u32.to_le_bytes());
+        zip.extend_from_slice(&0u16.to_le_bytes());
+        zip.extend_from_slice(&0u16.to_le_bytes());
+        zip.extend_from_slice(&1u16.to_le_bytes());
+        zip.extend_from_slice(&1u16.to_le_bytes());
+        zip.extend_from_slice(&(central_directory_size as u32).to_le_bytes());
+        zip.extend_from_slice(&(central_directory as u32).to_le_bytes());
+        zip.extend_from_slice(&0u16.to_le_bytes());
+
+        zip
+    }
+
+    fn crc32(data: &[u8]) -> u32
+    {
+        let mut crc: u32 = 0xffffffff;
+
+        for byte in data
+        {
+            crc ^= *byte as u32;
+            for _ in 0..8
+            {
+                crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+            }
+        }
+
+        !crc
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn u16_at(zip: &[u8], offset: usize) -> u16
+    {
+        u16::from_le_bytes([zip[offset], zip[offset + 1]])
+    }
+
+    fn u32_at(zip: &[u8], offset: usize) -> u32
+    {
+        u32::from_le_bytes([zip[offset], zip[offset + 1], zip[offset + 2], zip[offset + 3]])
+    }
+
+    #[test]
+    fn crc32()
+    {
+        assert_eq!(KmlExport::crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(KmlExport::crc32(b""), 0);
+    }
+
+    #[test]
+    fn kmz_structure()
+    {
+        let kml = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<kml><Document><name>Vol à Planfait</name></Document></kml>\n".to_string();
+        let zip = KmlExport::kmz(&kml);
+        let (data, name) = (kml.as_bytes(), KMZ_DOCUMENT.as_bytes());
+        let crc = KmlExport::crc32(data);
+
+        // Local file header, stored without compression, then the document as is
+        assert_eq!(u32_at(&zip, 0), 0x04034b50);
+        assert_eq!(u16_at(&zip, 8), 0);
+        assert_eq!(u32_at(&zip, 14), crc);
+        assert_eq!((u32_at(&zip, 18), u32_at(&zip, 22)), (data.len() as u32, data.len() as u32));
+        assert_eq!((u16_at(&zip, 26), u16_at(&zip, 28)), (name.len() as u16, 0));
+        assert_eq!(&zip[30..30 + name.len()], name);
+        let start = 30 + name.len();
+        assert_eq!(&zip[start..start + data.len()], data);
+
+        // End of central directory, the last 22 bytes
+        let end = zip.len() - 22;
+        assert_eq!(u32_at(&zip, end), 0x06054b50);
+        assert_eq!((u16_at(&zip, end + 8), u16_at(&zip, end + 10)), (1, 1));
+        let (size, offset) = (u32_at(&zip, end + 12) as usize, u32_at(&zip, end + 16) as usize);
+        assert_eq!(offset, start + data.len());
+        assert_eq!(offset + size, end);
+
+        // Central directory entry pointing at the local header
+        assert_eq!(u32_at(&zip, offset), 0x02014b50);
+        assert_eq!(u32_at(&zip, offset + 16), crc);
+        assert_eq!((u32_at(&zip, offset + 20), u32_at(&zip, offset + 24)), (data.len() as u32, data.len() as u32));
+        assert_eq!(u16_at(&zip, offset + 28), name.len() as u16);
+        assert_eq!(u32_at(&zip, offset + 42), 0);
+        assert_eq!(&zip[offset + 46..offset + 46 + name.len()], name);
+        assert_eq!(size, 46 + name.len());
+    }
+}
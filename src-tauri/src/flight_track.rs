@@ -1,46 +1,117 @@
-use std::{io::Write, fs};
+use std::str::FromStr;
 
 use crate::logbook::FlightPoint;
-use self::igc_reader::IgcReader;
+use self::{igc_reader::{IgcReader, IgcHeader}, gpx_reader::GpxReader, thermal::Thermal, glide::GlideAnalysis, wind::WindEstimate, simplify::Simplification, altitude::AltitudeSource};
 use anyhow::{Result, bail};
 use chrono::{NaiveDate, NaiveDateTime};
 use geoutils::{Location, Distance};
 
-mod igc_reader;
-
-const EPSILON: f32 = 0.00001;
-
-const NB_POINT:usize = 15;
+pub mod igc_reader;
+pub mod gpx_reader;
+pub mod igc_validator;
+pub mod scoring;
+pub mod thermal;
+pub mod glide;
+pub mod wind;
+pub mod simplify;
+pub mod altitude;
+
+const DETECTION_WINDOW:i64 = 15;// s
 const HSPEED_THR:f64 = 3.0;// m/s - 11km/h
 const VSPEED_THR:f64 = 0.6;// m/s
+const CONFIRM_TIME:i64 = 60;// s - flight must be sustained this long after takeoff
+const CONFIRM_RATIO:f32 = 0.8;
+const LOW_CONFIDENCE:f32 = 0.5;
+const MIN_ALT_CHANGE:u32 = 100;// m - climb or descent that makes a later flight of the file real
+const VARIO_RATIO:f32 = 0.5;// or part of it climbing or sinking faster than the vertical speed threshold
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+// Thresholds of the takeoff and landing detection
+//  window        : speeds are averaged over this duration (s)
+//  hspeed_thr    : horizontal speed above which the pilot is flying (m/s)
+//  vspeed_thr    : climb or sink rate above which the pilot is flying (m/s)
+//  confirm_time  : flying must be sustained this long, filters ground handling and running (s)
+//  confirm_ratio : part of the confirm time that must be classified as flying
+#[derive(Clone,Debug)]
+pub struct DetectionConfig
+{
+    pub window: i64,
+    pub hspeed_thr: f64,
+    pub vspeed_thr: f64,
+    pub confirm_time: i64,
+    pub confirm_ratio: f32,
+}
+
+// Index of the takeoff (or landing on a reversed trace) and confidence between 0 and 1
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Detection
+{
+    pub index: usize,
+    pub confidence: f32,
+}
 
 pub struct FlightProfile
 {
     pub points: Vec<FlightProfilePoint>,
+    pub simplification: Option<Simplification>,// None for profiles stored before it was recorded
 }
 
 pub struct FlightProfilePoint
 {
-    time: NaiveDateTime,
-    alt: u32,
-    speed: u32, // m/s
-    vario: f32, // m/s
-    lat: f32,
-    lng: f32,
+    pub time: NaiveDateTime,
+    pub alt: u32,
+    pub speed: u32, // m/s
+    pub airspeed: u32, // m/s, ground speed corrected by the estimated wind
+    pub vario: f32, // m/s
+    pub lat: f32,
+    pub lng: f32,
+    pub ground: Option<i32>, // m, from the DEM when one covers the point
 }
 
 pub struct FlightTrack
 {
-    // track: Vec<FlightPoint>,
+    pub track: Vec<FlightPoint>,
     // simplified_track: Vec<FlightPoint>,
     // pub geojson: String,
     pub profile: FlightProfile,
+    pub thermals: Vec<Thermal>,
+    pub glide: GlideAnalysis,
+    pub wind: Vec<WindEstimate>,
     pub duration: u32,
     pub distance: u32,
     pub date: NaiveDate,
+    pub header: IgcHeader,
     pub takeoff: FlightPoint,
     pub landing: FlightPoint,
     pub hash: String,
+    pub fix_start: usize,// index of the takeoff and landing fixes in the file
+    pub fix_end: usize,
+    pub takeoff_confidence: f32,
+    pub landing_confidence: f32,
+    pub altitude_source: AltitudeSource,
+    pub alt_max: u32,
+    pub warnings: Vec<String>,
+}
+
+impl Default for DetectionConfig {
+    fn default() -> Self {
+        DetectionConfig {
+            window: DETECTION_WINDOW,
+            hspeed_thr: HSPEED_THR,
+            vspeed_thr: VSPEED_THR,
+            confirm_time: CONFIRM_TIME,
+            confirm_ratio: CONFIRM_RATIO,
+        }
+    }
+}
+
+impl FlightProfilePoint {
+    // Height above ground
+    pub fn agl(&self) -> Option<i32>
+    {
+        self.ground.map(|g| self.alt as i32 - g)
+    }
 }
 
 impl ToString for FlightProfile {
@@ -52,6 +123,8 @@ impl ToString for FlightProfile {
         let mut vario_col = String::new();
         let mut lat_col = String::new();
         let mut lng_col = String::new();
+        let mut airspeed_col = String::new();
+        let mut ground_col = String::new();
 
         for pt in &self.points
         {
@@ -61,6 +134,9 @@ impl ToString for FlightProfile {
             vario_col.push_str(format!("{},",pt.vario).as_str());
             lat_col.push_str(format!("{},",pt.lat).as_str());
             lng_col.push_str(format!("{},",pt.lng).as_str());
+            airspeed_col.push_str(format!("{},",pt.airspeed).as_str());
+            ground_col.push_str(&pt.ground.map(|g| g.to_string()).unwrap_or_default());
+            ground_col.push(',');
             // csv.push_str(format!("{},{},{},{}\n",pt.time.timestamp(),pt.alt,pt.speed,pt.vario).as_str());
         }
 
@@ -70,6 +146,7 @@ impl ToString for FlightProfile {
         vario_col.push_str("\n");
         lat_col.push_str("\n");
         lng_col.push_str("\n");
+        airspeed_col.push_str("\n");
 
         csv.push_str(&ts_col);
         csv.push_str(&alt_col);
@@ -77,43 +154,277 @@ impl ToString for FlightProfile {
         csv.push_str(&vario_col);
         csv.push_str(&lat_col);
         csv.push_str(&lng_col);
+        csv.push_str(&airspeed_col);
+
+        // Ground row only when a DEM covered the flight, empty values where it didn't
+        let has_ground = self.points.iter().any(|pt| pt.ground.is_some());
+
+        if let Some(s) = self.simplification
+        {
+            csv.push_str(&s.to_string());
+        }
+        if self.simplification.is_some() || has_ground
+        {
+            csv.push_str("\n");
+        }
+        if has_ground
+        {
+            ground_col.push_str("\n");
+            csv.push_str(&ground_col);
+        }
 
         csv
     }
 }
 
+impl FromStr for FlightProfile {
+    type Err = anyhow::Error;
+
+    // Read back the columns written by to_string
+    fn from_str(csv: &str) -> Result<Self> {
+        let cols: Vec<Vec<&str>> = csv
+            .lines()
+            .map(|l| l.split(',').filter(|v| !v.is_empty()).collect())
+            .collect();
+
+        // Empty ground values are kept so the row stays aligned with the others
+        let ground: Vec<Option<i32>> = csv.lines()
+            .nth(8)
+            .map(|l| l.split(',').map(|v| v.parse().ok()).collect())
+            .unwrap_or_default();
+
+        if cols.len() < 6
+        {
+            bail!("Invalid flight profile");
+        }
+
+        let mut profile = FlightProfile {
+            points: Vec::new(),
+            simplification: cols.get(7).and_then(|c| c.first()).and_then(|s| s.parse().ok()),
+        };
+
+        for i in 0..cols[0].len()
+        {
+            let time = match cols[0][i].parse::<i64>().ok().and_then(|ts| NaiveDateTime::from_timestamp_opt(ts, 0)) {
+                Some(t) => t,
+                None => bail!("Invalid flight profile time"),
+            };
+
+            let speed = cols[2].get(i).and_then(|v| v.parse().ok()).unwrap_or(0);
+
+            // Profiles stored before wind estimation have no airspeed row
+            profile.points.push(FlightProfilePoint {
+                time,
+                alt: cols[1].get(i).and_then(|v| v.parse().ok()).unwrap_or(0),
+                speed,
+                airspeed: cols.get(6).and_then(|c| c.get(i)).and_then(|v| v.parse().ok()).unwrap_or(speed),
+                vario: cols[3].get(i).and_then(|v| v.parse().ok()).unwrap_or(0.0),
+                lat: cols[4].get(i).and_then(|v| v.parse().ok()).unwrap_or(0.0),
+                lng: cols[5].get(i).and_then(|v| v.parse().ok()).unwrap_or(0.0),
+                ground: ground.get(i).copied().flatten(),
+            });
+        }
+
+        Ok(profile)
+    }
+}
+
 impl FlightTrack {
-    pub fn new(raw_igc: &String) -> Result<Self>
+    pub fn new(raw_igc: &String, fallback_date: Option<NaiveDate>) -> Result<Self>
     {
-        let igc = IgcReader::read(raw_igc)?;
+        Self::with_config(raw_igc, fallback_date, None, &DetectionConfig::default())
+    }
 
-        let takeoff_index = Self::flight_detection(&igc.track);
-        let mut reversed_trace = igc.track.clone();
-        reversed_trace.reverse();
-        let landing_index = (&igc.track.len() - Self::flight_detection(&reversed_trace)).saturating_sub(1);
+    pub fn with_config(raw_igc: &String, fallback_date: Option<NaiveDate>, source: Option<AltitudeSource>, config: &DetectionConfig) -> Result<Self>
+    {
+        let (igc, source, warnings) = Self::read(raw_igc, fallback_date, source)?;
+        let (takeoff, landing) = Self::takeoff_landing(&igc.track, config);
+        let hashed = (0, igc.track.len() - 1);
+
+        Ok(Self::build(&igc, source, takeoff, landing, hashed, warnings, config))
+    }
+
+    // One flight track per takeoff found in the file, for loggers left on between flights
+    pub fn split(raw_igc: &String, fallback_date: Option<NaiveDate>, config: &DetectionConfig) -> Result<Vec<Self>>
+    {
+        let (igc, source, warnings) = Self::read(raw_igc, fallback_date, None)?;
+        let detections = Self::detect_flights(&igc.track, config);
+        let ranges = Self::hash_ranges(&detections, igc.track.len());
+
+        Ok(detections.into_iter()
+            .zip(ranges)
+            .map(|((takeoff, landing), hashed)| Self::build(&igc, source, takeoff, landing, hashed, warnings.clone(), config))
+            .collect())
+    }
+
+    // Flight already detected, from its range of fixes in the file, with the altitude source chosen for it (None to detect it)
+    pub fn from_range(raw_igc: &String, fallback_date: Option<NaiveDate>, source: Option<AltitudeSource>, fix_start: usize, fix_end: usize, config: &DetectionConfig) -> Result<Self>
+    {
+        let (igc, source, warnings) = Self::read(raw_igc, fallback_date, source)?;
+
+        if fix_start > fix_end || fix_end >= igc.track.len()
+        {
+            bail!("Fix range {}-{} out of the track ({} fixes)", fix_start, fix_end, igc.track.len());
+        }
+
+        let takeoff = Detection { index: fix_start, confidence: 1.0 };
+        let landing = Detection { index: fix_end, confidence: 1.0 };
+
+        // Same fixes hashed as by the split, found by the same detection
+        let detections = Self::detect_flights(&igc.track, config);
+        let hashed = detections.iter()
+            .zip(Self::hash_ranges(&detections, igc.track.len()))
+            .find(|((t, l), _)| t.index == fix_start && l.index == fix_end)
+            .map(|(_, range)| range)
+            .unwrap_or((fix_start, fix_end));
+
+        Ok(Self::build(&igc, source, takeoff, landing, hashed, warnings, config))
+    }
 
-        let duration = igc.track[landing_index].time - igc.track[takeoff_index].time;
+    // Takeoff and landing of every flight of the trace, the whole trace is one flight when none is found
+    fn detect_flights(trace: &Vec<FlightPoint>, config: &DetectionConfig) -> Vec<(Detection, Detection)>
+    {
+        let mut detections: Vec<(Detection, Detection)> = Vec::new();
+        let mut from = 0;
+
+        while from + 1 < trace.len()
+        {
+            let mut takeoff = Self::flight_detection(&trace[from..], config);
+            if takeoff.confidence == 0.0
+            {
+                break;
+            }
+            takeoff.index += from;
+
+            let landing = Self::landing_detection(trace, takeoff.index, config);
+            from = landing.index + 1;
+
+            // After a landing the logger may still be on in a car, only the altitude tells a drive from a flight
+            if !detections.is_empty() && !Self::has_flight_profile(&trace[takeoff.index..=landing.index], config)
+            {
+                continue;
+            }
+
+            detections.push((takeoff, landing));
+        }
+
+        if detections.is_empty()
+        {
+            detections.push(Self::takeoff_landing(trace, config));
+        }
+
+        detections
+    }
+
+    // Fixes of the file that belong to each flight for the content hash : from the fix after the previous landing
+    // to the landing, up to the end of the file for the last one. A single flight hashes the whole file.
+    fn hash_ranges(detections: &[(Detection, Detection)], nb_fixes: usize) -> Vec<(usize, usize)>
+    {
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        let mut start = 0;
+
+        for (i, (_, landing)) in detections.iter().enumerate()
+        {
+            let end = if i + 1 == detections.len() { nb_fixes - 1 } else { landing.index };
+            ranges.push((start, end));
+            start = end + 1;
+        }
 
-        let simplified_track: Vec<FlightPoint> = Self::simplify(&igc.track[takeoff_index..landing_index].to_vec(), &EPSILON);
-        let distance: u32 = Self::total_distance(&simplified_track);
+        ranges
+    }
 
-        Self::_to_file(&simplified_track);
-        println!("{} {}",simplified_track.len(),igc.track.len());
+    fn read(raw_igc: &String, fallback_date: Option<NaiveDate>, source: Option<AltitudeSource>) -> Result<(IgcReader, AltitudeSource, Vec<String>)>
+    {
+        let mut igc: IgcReader = if GpxReader::is_gpx(raw_igc) {
+            GpxReader::read(raw_igc)?.into()
+        } else {
+            IgcReader::read(raw_igc, fallback_date)?
+        };
+        let mut warnings: Vec<String> = Vec::new();
 
-        Ok(FlightTrack { 
-            profile: Self::flight_profile(&simplified_track),
+        if igc.date_inferred
+        {
+            match fallback_date {
+                Some(d) => warnings.push(format!("No date in IGC header, date inferred as {}", d.format("%Y-%m-%d"))),
+                None => warnings.push("No date in IGC header and none could be inferred".to_string()),
+            }
+        }
+
+        if igc.track.is_empty()
+        {
+            bail!("No fix in track");
+        }
+
+        let source = match source {
+            Some(s) => s,
+            None => {
+                let (s, warning) = altitude::detect_source(&igc.track);
+                warnings.extend(warning);
+                s
+            },
+        };
+
+        // Takeoff detection needs a varying altitude, the QNH correction waits for the takeoff
+        if source == AltitudeSource::Gps
+        {
+            altitude::apply(&mut igc.track, source, 0);
+        }
+
+        Ok((igc, source, warnings))
+    }
+
+    fn build(igc: &IgcReader, source: AltitudeSource, takeoff: Detection, landing: Detection, hashed: (usize, usize), mut warnings: Vec<String>, config: &DetectionConfig) -> Self
+    {
+        let (takeoff_index, landing_index) = (takeoff.index, landing.index);
+        let mut fixes = igc.track.clone();
+        altitude::apply(&mut fixes, source, takeoff_index);
+
+        if takeoff.confidence < LOW_CONFIDENCE
+        {
+            warnings.push(format!("Uncertain takeoff detection (confidence {:.2})", takeoff.confidence));
+        }
+        if landing.confidence < LOW_CONFIDENCE
+        {
+            warnings.push(format!("Uncertain landing detection (confidence {:.2})", landing.confidence));
+        }
+
+        let duration = fixes[landing_index].time - fixes[takeoff_index].time;
+
+        let simplification = Simplification::default();
+        let simplified_track: Vec<FlightPoint> = simplify::simplify(&fixes[takeoff_index..=landing_index], &simplification);
+        let distance: u32 = Self::total_distance(&fixes[takeoff_index..=landing_index]);
+
+        let track = fixes[takeoff_index..=landing_index].to_vec();
+        let thermals = thermal::detect(&track);
+        let wind = wind::estimate(&track, &thermals);
+        let alt_max = track.iter().map(|p| p.alt).max().unwrap_or(0);
+
+        FlightTrack { 
+            glide: glide::analyse(&track, &thermals, config),
+            profile: Self::flight_profile(&simplified_track, &wind, simplification),
+            thermals,
+            wind,
+            track,
             duration: duration.num_minutes() as u32,
             distance, 
             date: igc.date, 
-            takeoff: igc.track[takeoff_index].clone(),
-            landing: igc.track[landing_index].clone(),
-            hash: igc.check
-        })
+            header: igc.header.clone(),
+            takeoff: fixes[takeoff_index].clone(),
+            landing: fixes[landing_index].clone(),
+            hash: Self::content_hash(&igc.track[hashed.0..=hashed.1]),
+            fix_start: takeoff_index,
+            fix_end: landing_index,
+            takeoff_confidence: takeoff.confidence,
+            landing_confidence: landing.confidence,
+            altitude_source: source,
+            alt_max,
+            warnings,
+        }
     }
 
-    fn flight_profile(trace: &Vec<FlightPoint>) -> FlightProfile
+    fn flight_profile(trace: &Vec<FlightPoint>, wind: &Vec<WindEstimate>, simplification: Simplification) -> FlightProfile
     {
-        let mut profile: FlightProfile = FlightProfile { points: Vec::new() };
+        let mut profile: FlightProfile = FlightProfile { points: Vec::new(), simplification: Some(simplification) };
 
         for i in 1..trace.len()
         {
@@ -124,54 +435,204 @@ impl FlightTrack {
             let pt2 = Location::new(trace[i - 1].lat,trace[i - 1].long);
 
             let speed = pt1.distance_to(&pt2).unwrap().meters() / delta as f64;
+
+            // Closest wind estimate in time, without any the airspeed is the ground speed
+            let airspeed = match wind.iter().min_by_key(|w| (w.time - trace[i].time).num_seconds().abs()) {
+                None => speed,
+                Some(w) => {
+                    let (east, north) = wind::velocity(&trace[i - 1], &trace[i]);
+                    let (wind_east, wind_north) = w.vector();
+                    (east - wind_east).hypot(north - wind_north)
+                },
+            };
+
             profile.points.push(FlightProfilePoint { 
                 time: trace[i].time, 
                 alt: trace[i].alt, 
                 speed: speed as u32, 
+                airspeed: airspeed as u32,
                 vario,
                 lat: pt1.latitude() as f32,
                 lng: pt1.longitude() as f32,
+                ground: None,
             });
         }
 
         profile
     }
 
-    fn flight_detection(trace: &Vec<FlightPoint>) -> usize {
-        let mut index: usize = 0;
+    // Takeoff and landing detections, the landing is the takeoff of the reversed trace
+    pub fn takeoff_landing(trace: &Vec<FlightPoint>, config: &DetectionConfig) -> (Detection, Detection)
+    {
+        let takeoff = Self::flight_detection(trace, config);
+        let mut reversed_trace = trace.clone();
+        reversed_trace.reverse();
+        let mut landing = Self::flight_detection(&reversed_trace, config);
+        landing.index = (trace.len() - landing.index).saturating_sub(1);
 
-        if trace.len() < 5
+        // No flight found, keep the whole trace
+        if trace.is_empty() || landing.index <= takeoff.index
         {
-            return index;
+            return (
+                Detection { index: 0, confidence: 0.0 },
+                Detection { index: trace.len().saturating_sub(1), confidence: 0.0 },
+            );
         }
-        
-        for i in 0..(trace.len() - 2)
+
+        (takeoff, landing)
+    }
+
+    // First fix after the takeoff from which the pilot stays on the ground, else the end of the flight in the trace
+    fn landing_detection(trace: &Vec<FlightPoint>, takeoff: usize, config: &DetectionConfig) -> Detection
+    {
+        for i in takeoff + 1..trace.len()
         {
-            let mut vspeed: f64 = 0.0;
-            let mut hspeed: f64 = 0.0;
-            for j in 0..NB_POINT
+            if Self::is_flying(trace, i, config)
             {
-                vspeed += trace[i + j].alt as f64 - trace[i + j + 1].alt as f64;
-                let loc1 = Location::new(trace[i + j].lat,trace[i + j].long);
-                let loc2 = Location::new(trace[i + j + 1].lat,trace[i + j + 1].long);
-                hspeed += loc1.distance_to(&loc2).unwrap().meters();
+                continue;
             }
 
-            vspeed = vspeed/(NB_POINT as f64);
-            hspeed = hspeed/(NB_POINT as f64);
-
-            // println!("{} {}",vspeed,hspeed);
+            let mut end = i;
+            while end + 1 < trace.len() && (trace[end].time - trace[i].time).num_seconds() < config.confirm_time
+            {
+                end += 1;
+            }
 
-            if vspeed < -VSPEED_THR || vspeed > VSPEED_THR || hspeed > HSPEED_THR {
-                index = i;
+            // Landing at the end of the file
+            if end + 1 == trace.len()
+            {
                 break;
             }
+
+            let ratio = (i..=end).filter(|&j| !Self::is_flying(trace, j, config)).count() as f32 / (end - i + 1) as f32;
+            if ratio >= config.confirm_ratio
+            {
+                return Detection { index: i, confidence: ratio };
+            }
+        }
+
+        let mut reversed_trace = trace[takeoff..].to_vec();
+        reversed_trace.reverse();
+        let mut landing = Self::flight_detection(&reversed_trace, config);
+        landing.index = (trace.len() - landing.index).saturating_sub(1).max(takeoff);
+
+        landing
+    }
+
+    // First fix from which flying is sustained, works on reversed traces as durations are absolute
+    fn flight_detection(trace: &[FlightPoint], config: &DetectionConfig) -> Detection
+    {
+        let flying: Vec<bool> = (0..trace.len()).map(|i| Self::is_flying(trace, i, config)).collect();
+
+        for i in 0..trace.len()
+        {
+            if !flying[i]
+            {
+                continue;
+            }
+
+            let mut end = i;
+            while end + 1 < trace.len() && (trace[end].time - trace[i].time).num_seconds().abs() < config.confirm_time
+            {
+                end += 1;
+            }
+
+            let ratio = flying[i..=end].iter().filter(|&&f| f).count() as f32 / (end - i + 1) as f32;
+            if ratio < config.confirm_ratio
+            {
+                continue;
+            }
+
+            // A flight ending before the confirm time is less certain
+            let covered = (trace[end].time - trace[i].time).num_seconds().abs() as f32 / config.confirm_time.max(1) as f32;
+
+            return Detection { index: Self::first_move(trace, i, config), confidence: ratio * covered.min(1.0) };
+        }
+
+        Detection { index: 0, confidence: 0.0 }
+    }
+
+    // The window starting at index is flying, the takeoff is its first fix moving fast enough
+    fn first_move(trace: &[FlightPoint], index: usize, config: &DetectionConfig) -> usize
+    {
+        let mut i = index;
+
+        while i + 1 < trace.len() && (trace[i].time - trace[index].time).num_seconds().abs() < config.window
+        {
+            let dt = (trace[i + 1].time - trace[i].time).num_seconds().abs().max(1) as f64;
+            let loc1 = Location::new(trace[i].lat, trace[i].long);
+            let loc2 = Location::new(trace[i + 1].lat, trace[i + 1].long);
+
+            let hspeed = loc1.haversine_distance_to(&loc2).meters() / dt;
+            let vspeed = (trace[i + 1].alt as f64 - trace[i].alt as f64) / dt;
+
+            if vspeed.abs() > config.vspeed_thr || hspeed > config.hspeed_thr
+            {
+                return i;
+            }
+            i += 1;
         }
 
         index
     }
 
-    fn total_distance(track: &Vec<FlightPoint>) -> u32 {
+    // Real gain or loss of altitude, or a vario that moves like in flight, which a road rarely gives
+    fn has_flight_profile(trace: &[FlightPoint], config: &DetectionConfig) -> bool
+    {
+        let start = trace[0].alt;
+        let max = trace.iter().map(|pt| pt.alt).max().unwrap_or(start);
+        let min = trace.iter().map(|pt| pt.alt).min().unwrap_or(start);
+
+        if max - start >= MIN_ALT_CHANGE || start - min >= MIN_ALT_CHANGE
+        {
+            return true;
+        }
+
+        let mut moving = 0;
+        for i in 0..trace.len()
+        {
+            let mut end = i;
+            while end + 1 < trace.len() && (trace[end].time - trace[i].time).num_seconds() < config.window
+            {
+                end += 1;
+            }
+
+            let dt = (trace[end].time - trace[i].time).num_seconds();
+            if dt > 0 && (trace[end].alt as f64 - trace[i].alt as f64).abs() / dt as f64 > config.vspeed_thr
+            {
+                moving += 1;
+            }
+        }
+
+        moving as f32 / trace.len() as f32 >= VARIO_RATIO
+    }
+
+    fn is_flying(trace: &[FlightPoint], index: usize, config: &DetectionConfig) -> bool
+    {
+        let mut end = index;
+        let mut hdist: f64 = 0.0;
+
+        while end + 1 < trace.len() && (trace[end].time - trace[index].time).num_seconds().abs() < config.window
+        {
+            let loc1 = Location::new(trace[end].lat, trace[end].long);
+            let loc2 = Location::new(trace[end + 1].lat, trace[end + 1].long);
+            hdist += loc1.haversine_distance_to(&loc2).meters();
+            end += 1;
+        }
+
+        let dt = (trace[end].time - trace[index].time).num_seconds().abs();
+        if dt == 0
+        {
+            return false;
+        }
+
+        let hspeed = hdist / dt as f64;
+        let vspeed = (trace[end].alt as f64 - trace[index].alt as f64) / dt as f64;
+
+        vspeed.abs() > config.vspeed_thr || hspeed > config.hspeed_thr
+    }
+
+    fn total_distance(track: &[FlightPoint]) -> u32 {
         let mut dist: f64 = 0.0;
 
         if track.len() < 2
@@ -192,96 +653,138 @@ impl FlightTrack {
         dist as u32
     }
 
-    fn magnitude(p1: &FlightPoint, p2: &FlightPoint) -> f32 {
-        let line = FlightPoint {
-            lat: p2.lat - p1.lat,
-            long: p2.long - p1.long,
-            time: p2.time,
-            alt: 0,
-            alt_gps: 0,
-        };
+    // Stable hash (FNV-1a) of the fixes, identifies a flight whatever the headers or the security record of the file.
+    // Only the time, position and GPS altitude are used, they are kept as recorded by every export.
+    // All the fixes of the file (or of its share of a multi-flight file) are hashed, not only the detected flight.
+    pub fn content_hash(fixes: &[FlightPoint]) -> String
+    {
+        let mut hash = FNV_OFFSET;
 
-        f32::sqrt(line.lat * line.lat + line.long * line.long)
+        for pt in fixes
+        {
+            let line = format!("{}{:.5}{:.5}{}", pt.time.format("%Y%m%d%H%M%S"), pt.lat, pt.long, pt.alt_gps);
+            for byte in line.bytes()
+            {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(FNV_PRIME);
+            }
+        }
+
+        format!("{:016X}", hash)
     }
+}
 
-    fn distance_point_line(
-        p: &FlightPoint,
-        line_start: &FlightPoint,
-        line_end: &FlightPoint,
-    ) -> f32 {
-        let linemag = Self::magnitude(line_start, line_end);
-
-        let u = (((p.lat - line_start.lat) * (line_end.lat - line_start.lat))
-            + ((p.long - line_start.long) * (line_end.long - line_start.long)))
-            / (linemag * linemag);
-
-        // if !(0.0..=1.0).contains(&u) {
-        //     return 0.0;
-        // }
-
-        let intersection = FlightPoint {
-            lat: line_start.lat + u * (line_end.lat - line_start.lat),
-            long: line_start.long + u * (line_end.long - line_start.long),
-            time: line_start.time,
-            alt: 0,
-            alt_gps: 0,
-        };
 
-        Self::magnitude(p, &intersection)
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveTime;
+
+    use super::*;
+
+    fn detect(raw_igc: &str) -> (Vec<FlightPoint>, Detection, Detection)
+    {
+        let igc = IgcReader::read(&raw_igc.to_string(), None).unwrap();
+        let (takeoff, landing) = FlightTrack::takeoff_landing(&igc.track, &DetectionConfig::default());
+
+        (igc.track, takeoff, landing)
     }
 
-    pub fn simplify(pointlist: &Vec<FlightPoint>, epsilon: &f32) -> Vec<FlightPoint> {
-        let mut dmax: f32 = 0.0;
-        let mut index: u32 = 0;
-        let mut cpt: u32 = 1;
-        let end: usize = pointlist.len();
-        let mut result = Vec::new();
+    fn assert_time(track: &Vec<FlightPoint>, detection: &Detection, expected: &str, tolerance: i64)
+    {
+        let expected = NaiveTime::parse_from_str(expected, "%H:%M:%S").unwrap();
+        let delta = (track[detection.index].time.time() - expected).num_seconds().abs();
 
-        if end < 3 {
-            return pointlist.to_vec();
-        }
+        assert!(delta <= tolerance, "detected at {}, expected {}", track[detection.index].time.time(), expected);
+    }
 
-        for pt in match pointlist.get(1..end) {
-            None => return pointlist.to_vec(),
-            Some(p) => p.to_vec(),
-        } {
-            let d = Self::distance_point_line(&pt, &pointlist[0], pointlist.last().unwrap());
+    #[test]
+    fn ground_handling_is_not_a_takeoff()
+    {
+        let (track, takeoff, landing) = detect(include_str!("../tests/fixtures/ground_handling.igc"));
 
-            if d > dmax {
-                dmax = d;
-                index = cpt;
-            }
-            cpt += 1;
-        }
+        assert_time(&track, &takeoff, "10:05:00", 5);
+        assert_time(&track, &landing, "10:15:15", 20);
+        assert!(takeoff.confidence > 0.9);
+        assert!(landing.confidence > 0.9);
+    }
 
-        if (index as usize) > end {
-            return pointlist.to_vec();
-        }
+    #[test]
+    fn top_landing()
+    {
+        let (track, takeoff, landing) = detect(include_str!("../tests/fixtures/top_landing.igc"));
 
-        if dmax > *epsilon {
-            let res1 =
-                Self::simplify(&pointlist.get(..index as usize).unwrap().to_vec(), epsilon);
-            let mut res2 =
-                Self::simplify(&pointlist.get(index as usize..).unwrap().to_vec(), epsilon);
+        assert_time(&track, &takeoff, "10:01:00", 5);
+        assert_time(&track, &landing, "10:10:00", 20);
+        assert!(landing.confidence > 0.9);
+    }
 
-            result = res1;
-            result.append(&mut res2);
-        } else {
-            result.push(pointlist[0].clone());
-            result.push(pointlist[end - 1].clone());
-        }
+    #[test]
+    fn hike_and_slow_launch()
+    {
+        let (track, takeoff, landing) = detect(include_str!("../tests/fixtures/slow_launch.igc"));
 
-        result
+        assert_time(&track, &takeoff, "10:16:00", 15);
+        assert_time(&track, &landing, "10:30:40", 20);
+        assert!(takeoff.confidence > 0.8);
     }
 
-    fn _to_file(trace: &Vec<FlightPoint>) {
-        let path = "./trace";
-    
-        let mut output = fs::File::create(path).unwrap();
-    
-        for pt in trace {
-            writeln!(output, "{},{}", pt.lat, pt.long).unwrap();
+    #[test]
+    fn no_flight()
+    {
+        let (track, takeoff, landing) = detect(include_str!("../tests/fixtures/no_flight.igc"));
+
+        assert_eq!(takeoff.index, 0);
+        assert_eq!(landing.index, track.len() - 1);
+        assert_eq!(takeoff.confidence, 0.0);
+        assert_eq!(landing.confidence, 0.0);
+    }
+
+    #[test]
+    fn two_flights_in_one_file()
+    {
+        let raw_igc = include_str!("../tests/fixtures/two_flights.igc").to_string();
+        let flights = FlightTrack::split(&raw_igc, None, &DetectionConfig::default()).unwrap();
+
+        assert_eq!(flights.len(), 2);
+        assert_time(&flights[0].track, &Detection { index: 0, confidence: 1.0 }, "10:02:00", 5);
+        assert_time(&flights[1].track, &Detection { index: 0, confidence: 1.0 }, "10:38:00", 5);
+        assert_time(&flights[1].track, &Detection { index: flights[1].track.len() - 1, confidence: 1.0 }, "10:50:00", 20);
+        assert!(flights[0].fix_end < flights[1].fix_start);
+        assert!((9..=11).contains(&flights[0].duration));
+        assert!((11..=13).contains(&flights[1].duration));
+
+        // A stored flight is rebuilt from its fix range
+        let stored = FlightTrack::from_range(&raw_igc, None, None, flights[1].fix_start, flights[1].fix_end, &DetectionConfig::default()).unwrap();
+        assert_eq!(stored.track.len(), flights[1].track.len());
+        assert_eq!(stored.hash, flights[1].hash);
+        assert_ne!(flights[0].hash, flights[1].hash);
+    }
+
+    #[test]
+    fn drive_after_landing_is_not_a_flight()
+    {
+        let raw_igc = include_str!("../tests/fixtures/drive_away.igc").to_string();
+        let flights = FlightTrack::split(&raw_igc, None, &DetectionConfig::default()).unwrap();
+
+        // Landed at 10:12, then 10 minutes on the valley road at 15 m/s
+        assert_eq!(flights.len(), 1);
+        assert_time(&flights[0].track, &Detection { index: 0, confidence: 1.0 }, "10:02:00", 5);
+        assert_time(&flights[0].track, &Detection { index: flights[0].track.len() - 1, confidence: 1.0 }, "10:12:00", 20);
+    }
+
+    #[test]
+    fn short_traces()
+    {
+        let (track, _, _) = detect(include_str!("../tests/fixtures/ground_handling.igc"));
+
+        // Any length, including the ones shorter than the detection window
+        for len in 0..40
+        {
+            let trace = track[300..300 + len].to_vec();
+            let (takeoff, landing) = FlightTrack::takeoff_landing(&trace, &DetectionConfig::default());
+
+            assert!(takeoff.index <= landing.index || trace.is_empty());
+            assert!(landing.index < trace.len().max(1));
         }
     }
 }
-
@@ -0,0 +1,198 @@
+use std::str::FromStr;
+use anyhow::{Result, bail};
+
+use crate::logbook::FlightPoint;
+
+const MAX_ZERO_RATIO: f32 = 0.5;
+const MIN_RANGE: u32 = 1;// m - a source stuck on one value while the other varies is not recording
+const MIN_OTHER_RANGE: u32 = 20;// m
+const QNH_FIXES: usize = 30;// fixes before takeoff used for the QNH correction
+const FUSION_GAIN: f64 = 0.01;// how fast the fused altitude drifts towards the GPS one
+
+// Altitude written in FlightPoint.alt for the analysis
+//  Baro  : pressure altitude corrected to the GPS altitude at takeoff (QNH)
+//  Gps   : GPS altitude, for loggers without pressure sensor
+//  Fused : pressure altitude for the variations, GPS altitude for the long term drift
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum AltitudeSource {
+    Baro,
+    Gps,
+    Fused,
+}
+
+impl ToString for AltitudeSource {
+    fn to_string(&self) -> String {
+        match self {
+            AltitudeSource::Baro => "baro",
+            AltitudeSource::Gps => "gps",
+            AltitudeSource::Fused => "fused",
+        }.to_string()
+    }
+}
+
+impl FromStr for AltitudeSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "baro" => Ok(AltitudeSource::Baro),
+            "gps" => Ok(AltitudeSource::Gps),
+            "fused" => Ok(AltitudeSource::Fused),
+            _ => bail!("Invalid altitude source {}", s),
+        }
+    }
+}
+
+// Baro unless the logger didn't record it, many phone loggers write 0
+pub fn detect_source(track: &Vec<FlightPoint>) -> (AltitudeSource, Option<String>)
+{
+    let baro: Vec<u32> = track.iter().map(|p| p.alt).collect();
+    let gps: Vec<u32> = track.iter().map(|p| p.alt_gps).collect();
+
+    let baro_valid = is_valid(&baro, &gps);
+    let gps_valid = is_valid(&gps, &baro);
+
+    match (baro_valid, gps_valid) {
+        (true, _) => (AltitudeSource::Baro, None),
+        (false, true) => (AltitudeSource::Gps, Some("Invalid barometric altitude, GPS altitude used".to_string())),
+        (false, false) => (AltitudeSource::Baro, Some("No valid altitude in track".to_string())),
+    }
+}
+
+fn is_valid(altitudes: &Vec<u32>, other: &Vec<u32>) -> bool
+{
+    if altitudes.is_empty()
+    {
+        return false;
+    }
+
+    let zeros = altitudes.iter().filter(|&&a| a == 0).count() as f32 / altitudes.len() as f32;
+
+    zeros <= MAX_ZERO_RATIO && !(range(altitudes) < MIN_RANGE && range(other) >= MIN_OTHER_RANGE)
+}
+
+fn range(altitudes: &Vec<u32>) -> u32
+{
+    altitudes.iter().max().unwrap_or(&0) - altitudes.iter().min().unwrap_or(&0)
+}
+
+// Write the chosen altitude in alt, the GPS one stays in alt_gps
+pub fn apply(track: &mut Vec<FlightPoint>, source: AltitudeSource, takeoff: usize)
+{
+    match source {
+        AltitudeSource::Baro => {
+            let offset = qnh_offset(track, takeoff);
+            for pt in track.iter_mut()
+            {
+                pt.alt = (pt.alt as i32 + offset).max(0) as u32;
+            }
+        },
+        AltitudeSource::Gps => {
+            for pt in track.iter_mut()
+            {
+                pt.alt = pt.alt_gps;
+            }
+        },
+        AltitudeSource::Fused => {
+            // Complementary filter starting from the QNH correction
+            let mut offset = qnh_offset(track, takeoff) as f64;
+            for pt in track.iter_mut()
+            {
+                if pt.alt_gps > 0
+                {
+                    offset += FUSION_GAIN * ((pt.alt_gps as f64 - pt.alt as f64) - offset);
+                }
+                pt.alt = (pt.alt as f64 + offset).max(0.0) as u32;
+            }
+        },
+    }
+}
+
+// Difference between GPS and pressure altitude on the ground before takeoff
+pub fn qnh_offset(track: &Vec<FlightPoint>, takeoff: usize) -> i32
+{
+    let end = takeoff.min(track.len().saturating_sub(1));
+    let start = end.saturating_sub(QNH_FIXES);
+
+    let deltas: Vec<i32> = track[start..=end].iter()
+        .filter(|p| p.alt_gps > 0 && p.alt > 0)
+        .map(|p| p.alt_gps as i32 - p.alt as i32)
+        .collect();
+
+    if deltas.is_empty()
+    {
+        return 0;
+    }
+
+    deltas.iter().sum::<i32>() / deltas.len() as i32
+}
+
+#[cfg(test)]
+mod tests
+{
+    use chrono::{Duration, NaiveDate};
+    use super::*;
+
+    fn track(altitudes: &[(u32, u32)]) -> Vec<FlightPoint>
+    {
+        let start = NaiveDate::from_ymd_opt(2024, 8, 12).unwrap().and_hms_opt(12, 0, 0).unwrap();
+
+        altitudes.iter()
+            .enumerate()
+            .map(|(i, &(alt, alt_gps))| FlightPoint { time: start + Duration::seconds(i as i64), lat: 45.85, long: 6.25, alt, alt_gps, alt_baro: alt })
+            .collect()
+    }
+
+    // Pressure and GPS altitudes climbing together from the ground
+    fn climb(baro: impl Fn(u32) -> u32, gps: impl Fn(u32) -> u32) -> Vec<FlightPoint>
+    {
+        let altitudes: Vec<(u32, u32)> = (0..100).map(|i| (baro(1500 + i * 5), gps(1660 + i * 5))).collect();
+        track(&altitudes)
+    }
+
+    #[test]
+    fn source_detection()
+    {
+        assert_eq!(detect_source(&climb(|a| a, |a| a)), (AltitudeSource::Baro, None));
+
+        // Phone loggers writing 0, or a sensor stuck on one value
+        let gps_used = (AltitudeSource::Gps, Some("Invalid barometric altitude, GPS altitude used".to_string()));
+        assert_eq!(detect_source(&climb(|_| 0, |a| a)), gps_used);
+        assert_eq!(detect_source(&climb(|_| 1000, |a| a)), gps_used);
+
+        // A few missing values don't disqualify the pressure altitude
+        let mut gaps = climb(|a| a, |a| a);
+        gaps.iter_mut().step_by(3).for_each(|p| p.alt = 0);
+        assert_eq!(detect_source(&gaps).0, AltitudeSource::Baro);
+
+        // Logger left on the ground, both flat
+        assert_eq!(detect_source(&climb(|_| 1500, |_| 1660)), (AltitudeSource::Baro, None));
+
+        assert_eq!(detect_source(&climb(|_| 0, |_| 0)), (AltitudeSource::Baro, Some("No valid altitude in track".to_string())));
+    }
+
+    #[test]
+    fn qnh_correction()
+    {
+        // 40 fixes on the ground, then the flight
+        let mut altitudes: Vec<(u32, u32)> = (0..40).map(|i| if i % 2 == 0 { (1500, 1650) } else { (1500, 1670) }).collect();
+        altitudes.extend((0..20).map(|i| (1600 + i * 10, 1700 + i * 10)));
+        let mut track = track(&altitudes);
+
+        // Average of the fixes before the takeoff
+        assert_eq!(qnh_offset(&track, 39), 160);
+
+        // Fixes without one of the altitudes are left out
+        track[38].alt_gps = 0;
+        track[39].alt = 0;
+        assert_eq!(qnh_offset(&track, 39), 160);
+
+        // Takeoff past the end of the track, the last fixes are used
+        assert_eq!(qnh_offset(&track, 1000), qnh_offset(&track, track.len() - 1));
+
+        assert_eq!(qnh_offset(&climb(|_| 0, |a| a), 10), 0);
+
+        apply(&mut track, AltitudeSource::Baro, 39);
+        assert_eq!((track[0].alt, track[0].alt_gps), (1660, 1650));
+    }
+}
@@ -0,0 +1,268 @@
+use chrono::NaiveDateTime;
+use geoutils::Location;
+
+use crate::logbook::FlightPoint;
+use super::{thermal::Thermal, DetectionConfig};
+
+const PHASE_WINDOW: i64 = 20;// s - straight flight is classified by windows of this length
+const RIDGE_VARIO: f64 = -0.2;// m/s - straight flight holding altitude above this is ridge soaring
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum FlightPhase {
+    Climb,
+    Glide,
+    Ridge,
+    Ground,
+}
+
+#[derive(Clone,Debug)]
+pub struct PhaseSegment {
+    pub phase: FlightPhase,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub start_alt: u32,
+    pub end_alt: u32,
+    pub distance: u32,// m
+    pub glide_ratio: f32,// only for glides, 0 when no altitude was lost
+    pub speed: f32,// m/s
+    pub sink: f32,// m/s
+}
+
+// Per flight summary
+//  circling    : % of flight time spent circling
+//  glide_ratio : total glide distance over total glide altitude loss
+//  climb_rate  : altitude gained over time spent circling (m/s)
+#[derive(Clone,Debug,Default)]
+pub struct GlideAnalysis {
+    pub segments: Vec<PhaseSegment>,
+    pub circling: f32,
+    pub glide_ratio: f32,
+    pub glide_speed: f32,
+    pub sink: f32,
+    pub climb_rate: f32,
+}
+
+impl ToString for FlightPhase {
+    fn to_string(&self) -> String {
+        match self {
+            FlightPhase::Climb => "climb",
+            FlightPhase::Glide => "glide",
+            FlightPhase::Ridge => "ridge",
+            FlightPhase::Ground => "ground",
+        }.to_string()
+    }
+}
+
+pub fn analyse(track: &Vec<FlightPoint>, thermals: &Vec<Thermal>, config: &DetectionConfig) -> GlideAnalysis
+{
+    let segments = segments(track, thermals, config);
+    let mut analysis = GlideAnalysis::default();
+
+    if track.len() < 2
+    {
+        return analysis;
+    }
+
+    let flight_time = (track[track.len() - 1].time - track[0].time).num_seconds().max(1) as f32;
+    let (mut climb_time, mut climb_gain) = (0.0, 0.0);
+    let (mut glide_time, mut glide_distance, mut glide_loss) = (0.0, 0.0, 0.0);
+
+    for segment in &segments
+    {
+        let duration = (segment.end - segment.start).num_seconds() as f32;
+        let gain = segment.end_alt as f32 - segment.start_alt as f32;
+
+        match segment.phase {
+            FlightPhase::Climb => {
+                climb_time += duration;
+                climb_gain += gain;
+            },
+            FlightPhase::Glide => {
+                glide_time += duration;
+                glide_distance += segment.distance as f32;
+                glide_loss -= gain;
+            },
+            _ => (),
+        }
+    }
+
+    analysis.circling = climb_time / flight_time * 100.0;
+    if climb_time > 0.0 {
+        analysis.climb_rate = climb_gain / climb_time;
+    }
+    if glide_time > 0.0 {
+        analysis.glide_speed = glide_distance / glide_time;
+        analysis.sink = glide_loss / glide_time;
+    }
+    if glide_loss > 0.0 {
+        analysis.glide_ratio = glide_distance / glide_loss;
+    }
+    analysis.segments = segments;
+
+    analysis
+}
+
+// Circling phases come from the thermals, the rest is classified window by window
+fn segments(track: &Vec<FlightPoint>, thermals: &Vec<Thermal>, config: &DetectionConfig) -> Vec<PhaseSegment>
+{
+    let mut segments: Vec<PhaseSegment> = Vec::new();
+    let mut current: Option<(FlightPhase, usize, usize)> = None;
+    let mut i = 0;
+
+    while i + 1 < track.len()
+    {
+        let (phase, end) = match thermals.iter().find(|t| t.entry <= track[i].time && track[i].time < t.exit) {
+            Some(t) => {
+                let mut end = i;
+                while end + 1 < track.len() && track[end].time < t.exit {
+                    end += 1;
+                }
+                (FlightPhase::Climb, end)
+            },
+            None => {
+                let mut end = i + 1;
+                while end + 1 < track.len()
+                    && (track[end].time - track[i].time).num_seconds() < PHASE_WINDOW
+                    && !thermals.iter().any(|t| t.entry <= track[end].time && track[end].time < t.exit)
+                {
+                    end += 1;
+                }
+                (classify(&track[i..=end], config), end)
+            },
+        };
+
+        current = match current {
+            Some((p, start, _)) if p == phase => Some((p, start, end)),
+            Some((p, start, e)) => {
+                segments.push(segment(p, &track[start..=e]));
+                Some((phase, i, end))
+            },
+            None => Some((phase, i, end)),
+        };
+
+        i = end;
+    }
+
+    if let Some((p, start, end)) = current {
+        segments.push(segment(p, &track[start..=end]));
+    }
+
+    segments
+}
+
+// Ground under the speeds of the takeoff and landing detection, so both agree on when the pilot flies
+fn classify(points: &[FlightPoint], config: &DetectionConfig) -> FlightPhase
+{
+    let first = &points[0];
+    let last = &points[points.len() - 1];
+    let dt = (last.time - first.time).num_seconds().max(1) as f64;
+
+    let hspeed = distance(points) / dt;
+    let vario = (last.alt as f64 - first.alt as f64) / dt;
+
+    if hspeed < config.hspeed_thr && vario.abs() < config.vspeed_thr
+    {
+        FlightPhase::Ground
+    } else if vario > RIDGE_VARIO {
+        FlightPhase::Ridge
+    } else {
+        FlightPhase::Glide
+    }
+}
+
+fn distance(points: &[FlightPoint]) -> f64
+{
+    points.windows(2)
+        .map(|w| Location::new(w[0].lat, w[0].long).haversine_distance_to(&Location::new(w[1].lat, w[1].long)).meters())
+        .sum()
+}
+
+fn segment(phase: FlightPhase, points: &[FlightPoint]) -> PhaseSegment
+{
+    let first = &points[0];
+    let last = &points[points.len() - 1];
+    let duration = (last.time - first.time).num_seconds().max(1) as f32;
+    let distance = distance(points) as f32;
+    let loss = first.alt as f32 - last.alt as f32;
+
+    PhaseSegment {
+        phase,
+        start: first.time,
+        end: last.time,
+        start_alt: first.alt,
+        end_alt: last.alt,
+        distance: distance as u32,
+        glide_ratio: if phase == FlightPhase::Glide && loss > 0.0 { distance / loss } else { 0.0 },
+        speed: distance / duration,
+        sink: loss / duration,
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use chrono::{Duration, NaiveDate};
+    use super::*;
+    use crate::flight_track::thermal::TurnDirection;
+
+    // Fix at a distance north of the start (m), one per second
+    fn fix(second: i64, north: f64, alt: u32) -> FlightPoint
+    {
+        let time = NaiveDate::from_ymd_opt(2024, 8, 12).unwrap().and_hms_opt(12, 0, 0).unwrap() + Duration::seconds(second);
+        FlightPoint { time, lat: (45.0 + north / 111195.0) as f32, long: 6.0, alt, alt_gps: alt, alt_baro: alt }
+    }
+
+    fn thermal(entry: &FlightPoint, exit: &FlightPoint) -> Thermal
+    {
+        Thermal {
+            entry: entry.time,
+            exit: exit.time,
+            entry_alt: entry.alt,
+            exit_alt: exit.alt,
+            alt_gain: exit.alt as i32 - entry.alt as i32,
+            avg_climb: 2.0,
+            max_climb: 2.0,
+            direction: TurnDirection::Left,
+            lat: entry.lat,
+            long: entry.long,
+        }
+    }
+
+    #[test]
+    fn climb_then_straight_descent()
+    {
+        // 60 s circling at 2 m/s, then 60 s at 10 m/s sinking 1 m/s
+        let mut track: Vec<FlightPoint> = (0..=60).map(|s| fix(s, 0.0, 1000 + 2 * s as u32)).collect();
+        track.extend((1..=60).map(|s| fix(60 + s, 10.0 * s as f64, 1120 - s as u32)));
+        let thermals = vec![thermal(&track[0], &track[60])];
+
+        let analysis = analyse(&track, &thermals, &DetectionConfig::default());
+
+        let phases: Vec<FlightPhase> = analysis.segments.iter().map(|s| s.phase).collect();
+        assert_eq!(phases, vec![FlightPhase::Climb, FlightPhase::Glide]);
+        assert_eq!((analysis.segments[1].start, analysis.segments[1].end), (track[60].time, track[120].time));
+
+        assert!((analysis.circling - 50.0).abs() < 1e-3);
+        assert!((analysis.climb_rate - 2.0).abs() < 1e-3);
+        assert!((analysis.sink - 1.0).abs() < 1e-3);
+        assert!((analysis.glide_speed - 10.0).abs() < 0.05);
+        assert!((analysis.glide_ratio - 10.0).abs() < 0.05);
+        assert!((analysis.segments[1].glide_ratio - 10.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn ground_uses_the_detection_config()
+    {
+        let stationary: Vec<FlightPoint> = (0..=20).map(|s| fix(s, 0.0, 1000)).collect();
+        let walking: Vec<FlightPoint> = (0..=20).map(|s| fix(s, 4.0 * s as f64, 1000)).collect();
+        let slow = DetectionConfig { hspeed_thr: 5.0, ..DetectionConfig::default() };
+
+        assert_eq!(classify(&stationary, &slow), FlightPhase::Ground);
+        assert_eq!(classify(&stationary, &DetectionConfig::default()), FlightPhase::Ground);
+
+        // Level at 4 m/s : flying for the default 3 m/s threshold, on the ground under 5 m/s
+        assert_eq!(classify(&walking, &DetectionConfig::default()), FlightPhase::Ridge);
+        assert_eq!(classify(&walking, &slow), FlightPhase::Ground);
+        assert_eq!(analyse(&walking, &Vec::new(), &slow).segments[0].phase, FlightPhase::Ground);
+    }
+}
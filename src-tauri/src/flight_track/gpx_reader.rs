@@ -0,0 +1,216 @@
+use chrono::{DateTime, NaiveDate};
+use crate::logbook::FlightPoint;
+use anyhow::{Result, bail};
+
+use super::{FlightTrack, igc_reader::{IgcReader, IgcHeader}};
+
+const GPX_ROOT: &str = "<gpx";
+const GPX_POINT: &str = "<trkpt";
+const GPX_POINT_END: &str = "</trkpt>";
+const GPX_ELEVATION: &str = "ele";
+const GPX_TIME: &str = "time";
+const GPX_NAME: &str = "name";
+const GPX_METADATA: &str = "metadata";
+const GPX_AUTHOR: &str = "author";
+
+pub struct GpxReader{
+    pub date        :NaiveDate,
+    pub track       :Vec<FlightPoint>,
+    pub check       :String,
+    pub pilot       :String,// author of the file, the other names are the ones of the track or the application
+}
+
+impl From<GpxReader> for IgcReader {
+    fn from(gpx: GpxReader) -> Self {
+        let fix_extensions = vec![Default::default(); gpx.track.len()];
+
+        IgcReader {
+            date: gpx.date,
+            date_inferred: false,
+            header: IgcHeader {
+                pilot: gpx.pilot,
+                ..Default::default()
+            },
+            track: gpx.track,
+            check: gpx.check,
+            b_extensions: Vec::new(),
+            k_extensions: Vec::new(),
+            fix_extensions,
+            k_records: Vec::new(),
+        }
+    }
+}
+
+impl GpxReader {
+    // Root element is <gpx>, after the XML declaration and comments
+    pub fn is_gpx(raw: &String) -> bool
+    {
+        let mut rest = raw.trim_start_matches('\u{feff}').trim_start();
+
+        loop {
+            let skipped = if rest.starts_with("<?") {
+                rest.find("?>").map(|e| &rest[e + 2..])
+            } else if rest.starts_with("<!--") {
+                rest.find("-->").map(|e| &rest[e + 3..])
+            } else {
+                break;
+            };
+
+            match skipped {
+                Some(r) => rest = r.trim_start(),
+                None => return false,
+            }
+        }
+
+        match rest.strip_prefix(GPX_ROOT) {
+            Some(r) => r.starts_with(|c: char| c.is_whitespace() || c == '>'),
+            None => false,
+        }
+    }
+
+    pub fn read(raw_gpx: &String) -> Result<Self>
+    {
+        let mut track: Vec<FlightPoint> = Vec::new();
+        let mut rest = raw_gpx.as_str();
+
+        // Only track points are used, routes and waypoints are not a flight
+        while let Some(start) = rest.find(GPX_POINT) {
+            rest = &rest[start..];
+            let open_end = match rest.find('>') {
+                Some(i) => i,
+                None => bail!("Unterminated GPX track point"),
+            };
+
+            // Self-closing <trkpt lat=".." lon=".."/>
+            let end = if rest[..open_end].ends_with('/') {
+                open_end + 1
+            } else {
+                match rest.find(GPX_POINT_END) {
+                    Some(e) => e + GPX_POINT_END.len(),
+                    None => bail!("Unterminated GPX track point"),
+                }
+            };
+
+            track.push(Self::process_point(&rest[..end])?);
+            rest = &rest[end..];
+        }
+
+        if track.is_empty()
+        {
+            bail!("No track point in GPX file");
+        }
+
+        let pilot = Self::element(raw_gpx, GPX_METADATA)
+            .and_then(|m| Self::element(m, GPX_AUTHOR))
+            .and_then(|a| Self::element(a, GPX_NAME))
+            .unwrap_or("")
+            .trim()
+            .to_string();
+
+        Ok(GpxReader {
+            date: track[0].time.date(),
+            // GPX files have no security record, the track is their identifier
+            check: format!("GPX{}", FlightTrack::content_hash(&track)),
+            track,
+            pilot,
+        })
+    }
+
+    fn process_point(point: &str) -> Result<FlightPoint>
+    {
+        let lat: f32 = match Self::attribute(point, "lat").and_then(|v| v.parse().ok()) {
+            Some(l) => l,
+            None => bail!("GPX track point without latitude"),
+        };
+        let long: f32 = match Self::attribute(point, "lon").and_then(|v| v.parse().ok()) {
+            Some(l) => l,
+            None => bail!("GPX track point without longitude"),
+        };
+        let time = match Self::element(point, GPX_TIME).and_then(|v| DateTime::parse_from_rfc3339(v.trim()).ok()) {
+            Some(t) => t.naive_utc(),
+            None => bail!("GPX track point without time"),
+        };
+        let ele: f32 = Self::element(point, GPX_ELEVATION)
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(0.0);
+
+        // GPX has only the GPS altitude
+        Ok(FlightPoint {
+            time,
+            lat,
+            long,
+            alt: ele.max(0.0) as u32,
+            alt_gps: ele.max(0.0) as u32,
+            alt_baro: 0,
+        })
+    }
+
+    fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str>
+    {
+        let open = &tag[..tag.find('>').unwrap_or(tag.len())];
+
+        let pattern = format!("{}=", name);
+
+        for (i, _) in open.match_indices(&pattern)
+        {
+            if !open[..i].ends_with(char::is_whitespace) {
+                continue;
+            }
+
+            let value = &open[i + pattern.len()..];
+            let quote = value.chars().next()?;
+            if quote != '"' && quote != '\'' {
+                return None;
+            }
+            return value[1..].find(quote).map(|e| &value[1..e + 1]);
+        }
+
+        None
+    }
+
+    fn element<'a>(xml: &'a str, name: &str) -> Option<&'a str>
+    {
+        let open = format!("<{}>", name);
+        let close = format!("</{}>", name);
+
+        let start = xml.find(&open)? + open.len();
+        let end = xml[start..].find(&close)?;
+
+        Some(&xml[start..start + end])
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const GPX: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!-- exported -->\n<gpx version=\"1.1\" creator=\"App\">\n\
+        <metadata><name>Morning flight</name><author><name> Test Pilot </name></author></metadata>\n\
+        <trk><name>Track 1</name><trkseg>\n\
+        <trkpt lat=\"45.85\" lon=\"6.25\"><ele>1515</ele><time>2024-08-12T10:00:00Z</time></trkpt>\n\
+        <trkpt lat=\"45.86\" lon=\"6.26\"><ele>1520</ele><time>2024-08-12T10:00:01Z</time></trkpt>\n\
+        </trkseg></trk>\n</gpx>\n";
+
+    #[test]
+    fn root_element()
+    {
+        assert!(GpxReader::is_gpx(&GPX.to_string()));
+        assert!(GpxReader::is_gpx(&"\u{feff}<gpx>".to_string()));
+
+        // Mentions of gpx in an IGC file
+        assert!(!GpxReader::is_gpx(&"AXXXFIX\nLXXXexported from <gpx> file\n".to_string()));
+        assert!(!GpxReader::is_gpx(&"<gpxdata>".to_string()));
+    }
+
+    #[test]
+    fn pilot_is_the_author()
+    {
+        let gpx = GpxReader::read(&GPX.to_string()).unwrap();
+        assert_eq!(gpx.pilot, "Test Pilot");
+        assert_eq!(gpx.track.len(), 2);
+
+        let no_author = GPX.replace("<author><name> Test Pilot </name></author>", "");
+        assert_eq!(GpxReader::read(&no_author).unwrap().pilot, "");
+    }
+}
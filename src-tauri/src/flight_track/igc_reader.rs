@@ -1,45 +1,265 @@
-use chrono::NaiveDate;
+use std::{collections::HashMap, fmt, ops::Range, str::FromStr};
+
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime};
+use serde::{Serialize, Deserialize};
 use crate::logbook::FlightPoint;
 use anyhow::{Result, bail};
 
 const IGC_HEADER: &str = "H";
-const IGC_DATE: &str = "FDTE";
+const IGC_DATE: &str = "DTE";
+const IGC_PILOT: &str = "PLT";
+const IGC_COPILOT: &str = "CM2";
+const IGC_GLIDER_TYPE: &str = "GTY";
+const IGC_GLIDER_ID: &str = "GID";
+const IGC_COMPETITION_ID: &str = "CID";
+const IGC_COMPETITION_CLASS: &str = "CCL";
+const IGC_LOGGER_TYPE: &str = "FTY";
+const IGC_FIRMWARE: &str = "RFW";
+const IGC_HARDWARE: &str = "RHW";
+const IGC_GPS_DATUM: &str = "DTM";
+const IGC_PRESSURE_SENSOR: &str = "PRS";
+// H + source (F, O or P) + 3 letters code
+const HEADER_CODE: Range<usize> = 2..5;
 
 const IGC_RECORD: &str = "B";
 const IGC_CHECK: &str = "G";
+const IGC_B_EXTENSION: &str = "I";
+const IGC_K_EXTENSION: &str = "J";
+const IGC_K_RECORD: &str = "K";
+
+// Fixed part of a B record : B HHMMSS DDMMmmmN DDDMMmmmE V PPPPP GGGGG
+const B_TIME: Range<usize> = 1..7;
+const B_LAT_DEGREE: Range<usize> = 7..9;
+const B_LAT_MINUTE: Range<usize> = 9..14;
+const B_LAT_HEMISPHERE: usize = 14;
+const B_LONG_DEGREE: Range<usize> = 15..18;
+const B_LONG_MINUTE: Range<usize> = 18..23;
+const B_LONG_HEMISPHERE: usize = 23;
+const B_VALIDITY: usize = 24;
+const B_ALT: Range<usize> = 25..30;
+const B_ALT_GPS: Range<usize> = 30..35;
+const B_LENGTH: usize = 35;
+
+// K record fixed part : K HHMMSS
+const K_TIME: Range<usize> = 1..7;
+const K_LENGTH: usize = 7;
+
+// A fix earlier than the previous one by more than this means the flight crossed UTC midnight
+const MIDNIGHT_ROLLOVER: i64 = 12;// hours
+
+// Long file name : YYYY-MM-DD-MMM-SSSSS-FF.IGC, short file name : YMDMSSSF.IGC
+const LONG_NAME_DATE: Range<usize> = 0..10;
+const SHORT_NAME_LENGTH: usize = 8;
+
+// I/J record : I NN (SS FF CCC)*NN
+const EXTENSION_COUNT: Range<usize> = 1..3;
+const EXTENSION_FIRST: usize = 3;
+const EXTENSION_LENGTH: usize = 7;
+
+#[derive(Debug)]
+pub struct IgcError {
+    pub line: usize,
+    pub record: char,
+    pub reason: String,
+}
+
+// Extension declared in a I or J record, start and end are 1-based inclusive byte positions
+#[derive(Clone,Debug)]
+pub struct IgcExtension {
+    pub code: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Clone,Debug,Default)]
+pub struct FixExtension {
+    pub values: HashMap<String,String>,
+}
+
+#[derive(Clone,Debug,Default,Serialize,Deserialize)]
+pub struct IgcHeader {
+    pub pilot: String,
+    pub copilot: String,
+    pub glider_type: String,
+    pub glider_id: String,
+    pub competition_id: String,
+    pub competition_class: String,
+    pub logger_type: String,
+    pub firmware: String,
+    pub hardware: String,
+    pub gps_datum: String,
+    pub pressure_sensor: String,
+}
+
+#[derive(Clone,Debug)]
+pub struct KRecord {
+    pub time: NaiveDateTime,
+    pub values: HashMap<String,String>,
+}
+
+// Rebuild monotonic timestamps from the HHMMSS time of the records
+struct Clock {
+    date: NaiveDate,
+    day_offset: i64,
+    last: Option<NaiveDateTime>,
+}
 
 pub struct IgcReader{
     pub date        :NaiveDate,
+    pub date_inferred:bool,
+    pub header      :IgcHeader,
     pub track       :Vec<FlightPoint>,
     pub check       :String,
+    pub b_extensions:Vec<IgcExtension>,
+    pub k_extensions:Vec<IgcExtension>,
+    pub fix_extensions:Vec<FixExtension>,// one entry per point of track
+    pub k_records   :Vec<KRecord>,
+}
+
+impl fmt::Display for IgcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "IGC line {}: invalid {} record ({})", self.line, self.record, self.reason)
+    }
+}
+
+impl std::error::Error for IgcError {}
+
+impl FixExtension {
+    pub fn get(&self, code: &str) -> Option<i32>
+    {
+        self.values.get(code).and_then(|v| v.trim().parse().ok())
+    }
+
+    // Fix accuracy (m)
+    pub fn fxa(&self) -> Option<i32>
+    {
+        self.get("FXA")
+    }
+
+    // Satellites in use
+    pub fn siu(&self) -> Option<i32>
+    {
+        self.get("SIU")
+    }
+
+    // Engine noise level
+    pub fn enl(&self) -> Option<i32>
+    {
+        self.get("ENL")
+    }
+
+    // True airspeed (km/h)
+    pub fn tas(&self) -> Option<i32>
+    {
+        self.get("TAS")
+    }
+}
+
+impl IgcError {
+    pub(super) fn new(line: usize, record: &str, reason: &str) -> Self
+    {
+        IgcError {
+            line,
+            record: record.chars().next().unwrap_or(' '),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl Clock {
+    fn timestamp(&mut self, time: NaiveTime) -> NaiveDateTime
+    {
+        let mut timestamp = self.date.and_time(time) + Duration::days(self.day_offset);
+
+        if let Some(last) = self.last {
+            if timestamp < last - Duration::hours(MIDNIGHT_ROLLOVER) {
+                self.day_offset += 1;
+                timestamp += Duration::days(1);
+            }
+        }
+
+        self.last = Some(timestamp);
+        timestamp
+    }
+}
+
+pub fn date_from_file_name(file_name: &str) -> Option<NaiveDate>
+{
+    let name = file_name.to_uppercase();
+
+    if let Some(date) = name.get(LONG_NAME_DATE).and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok()) {
+        return Some(date);
+    }
+
+    // Short name : last digit of the year, month and day in base 36
+    let stem = name.split('.').next().unwrap_or("");
+    if stem.len() != SHORT_NAME_LENGTH {
+        return None;
+    }
+
+    let mut c = stem.chars();
+    let year_digit = c.next()?.to_digit(10)? as i32;
+    let month = c.next()?.to_digit(36)?;
+    let day = c.next()?.to_digit(36)?;
+
+    // Most recent year ending with this digit
+    let current_year = Local::now().year();
+    let year = current_year - (current_year % 10 - year_digit).rem_euclid(10);
+
+    NaiveDate::from_ymd_opt(year, month, day)
 }
 
 impl IgcReader {
-    pub fn read(raw_igc: &String) -> Result<Self>
+    pub fn read(raw_igc: &String, fallback_date: Option<NaiveDate>) -> Result<Self>
     {
         let mut track: Vec<FlightPoint> = Vec::new();
-        let mut date: NaiveDate = NaiveDate::from_ymd_opt(0, 1, 1).unwrap();
+        let header_date = raw_igc.lines()
+            .find(|l| l.starts_with(IGC_HEADER) && l.get(HEADER_CODE) == Some(IGC_DATE))
+            .and_then(|l| Self::process_date(l).ok());
+        let date_inferred = header_date.is_none();
+        let date: NaiveDate = header_date.or(fallback_date).unwrap_or(NaiveDate::from_ymd_opt(0, 1, 1).unwrap());
+        let mut clock = Clock { date, day_offset: 0, last: None };
+        let mut header: IgcHeader = IgcHeader::default();
         let igc_lines = raw_igc.lines();
         let mut check = "".to_string();
+        let mut b_extensions: Vec<IgcExtension> = Vec::new();
+        let mut k_extensions: Vec<IgcExtension> = Vec::new();
+        let mut fix_extensions: Vec<FixExtension> = Vec::new();
+        let mut k_records: Vec<KRecord> = Vec::new();
+
+        for (index, line) in igc_lines.enumerate() {
+            let line_nb = index + 1;
 
-        for line in igc_lines {
-            if line.chars().nth(0) == IGC_RECORD.chars().nth(0) {
-                track.push(Self::process_record(line, &date));
-            } else if line.chars().nth(0) == IGC_HEADER.chars().nth(0) {
-                if line[IGC_HEADER.len()..IGC_HEADER.len() + IGC_DATE.len()] == *IGC_DATE {
-                    if let Ok(d) = Self::process_date(line) {
-                        date = d
-                    }
-                }
-            } else if line.chars().nth(0) == IGC_CHECK.chars().nth(0) {
+            if line.starts_with(IGC_RECORD) {
+                track.push(Self::process_record(line, line_nb, &mut clock)?);
+                fix_extensions.push(FixExtension { values: Self::process_extension_values(line, &b_extensions) });
+            } else if line.starts_with(IGC_HEADER) {
+                Self::process_header(line, &mut header);
+            } else if line.starts_with(IGC_B_EXTENSION) {
+                b_extensions = Self::process_extension(line, line_nb, IGC_B_EXTENSION, B_LENGTH)?;
+            } else if line.starts_with(IGC_K_EXTENSION) {
+                k_extensions = Self::process_extension(line, line_nb, IGC_K_EXTENSION, K_LENGTH)?;
+            } else if line.starts_with(IGC_K_RECORD) {
+                let time = Self::process_time(line, line_nb, IGC_K_RECORD, K_TIME)?;
+                k_records.push(KRecord {
+                    time: clock.timestamp(time),
+                    values: Self::process_extension_values(line, &k_extensions),
+                });
+            } else if line.starts_with(IGC_CHECK) {
                 check = line[IGC_CHECK.len()..].to_string();
             }
         }
-        
-        Ok(IgcReader { 
-                    date, 
+
+        Ok(IgcReader {
+                    date,
+                    date_inferred,
+                    header,
                     track,
-                    check 
+                    check,
+                    b_extensions,
+                    k_extensions,
+                    fix_extensions,
+                    k_records,
                 })
     }
 
@@ -66,6 +286,35 @@ impl IgcReader {
         }
     }
 
+    fn process_header(line: &str, header: &mut IgcHeader)
+    {
+        // New format is "HFPLTPILOTINCHARGE:John Doe", old one "HFPLTJohn Doe"
+        let value = match line.find(':') {
+            Some(i) => &line[i + 1..],
+            None => line.get(HEADER_CODE.end..).unwrap_or(""),
+        }.trim().to_string();
+
+        let field = match line.get(HEADER_CODE) {
+            Some(IGC_PILOT) => &mut header.pilot,
+            Some(IGC_COPILOT) => &mut header.copilot,
+            Some(IGC_GLIDER_TYPE) => &mut header.glider_type,
+            Some(IGC_GLIDER_ID) => &mut header.glider_id,
+            Some(IGC_COMPETITION_ID) => &mut header.competition_id,
+            Some(IGC_COMPETITION_CLASS) => &mut header.competition_class,
+            Some(IGC_LOGGER_TYPE) => &mut header.logger_type,
+            Some(IGC_FIRMWARE) => &mut header.firmware,
+            Some(IGC_HARDWARE) => &mut header.hardware,
+            Some(IGC_GPS_DATUM) => &mut header.gps_datum,
+            Some(IGC_PRESSURE_SENSOR) => &mut header.pressure_sensor,
+            _ => return,
+        };
+
+        // Pilot entered (HP) records must not override the recorder ones (HF)
+        if field.is_empty() || line.starts_with("HF") {
+            *field = value;
+        }
+    }
+
     fn to_decimal(degree: u32, minute: f32, snew: char) -> f32 {
         let mut decimal: f32 = minute / 60.0;
         decimal += degree as f32;
@@ -77,71 +326,213 @@ impl IgcReader {
         decimal
     }
 
-    fn process_record(line: &str, date: &NaiveDate) -> FlightPoint {
-        let mut c = line[IGC_RECORD.len()..].chars();
-        let hour: u32 = c.next().unwrap_or('0').to_digit(10).unwrap_or(0) * 10
-            + c.next().unwrap_or('0').to_digit(10).unwrap_or(0);
-        let min: u32 = c.next().unwrap_or('0').to_digit(10).unwrap_or(0) * 10
-            + c.next().unwrap_or('0').to_digit(10).unwrap_or(0);
-        let sec: u32 = c.next().unwrap_or('0').to_digit(10).unwrap_or(0) * 10
-            + c.next().unwrap_or('0').to_digit(10).unwrap_or(0);
+    fn field<T: FromStr>(line: &str, line_nb: usize, record: &str, range: Range<usize>, name: &str) -> Result<T, IgcError>
+    {
+        line.get(range)
+            .and_then(|f| f.trim().parse::<T>().ok())
+            .ok_or_else(|| IgcError::new(line_nb, record, &format!("bad {}", name)))
+    }
+
+    fn process_time(line: &str, line_nb: usize, record: &str, range: Range<usize>) -> Result<NaiveTime, IgcError>
+    {
+        let hour: u32 = Self::field(line, line_nb, record, range.start..range.start + 2, "hour")?;
+        let min: u32 = Self::field(line, line_nb, record, range.start + 2..range.start + 4, "minute")?;
+        let sec: u32 = Self::field(line, line_nb, record, range.start + 4..range.end, "second")?;
+
+        NaiveTime::from_hms_opt(hour, min, sec)
+            .ok_or_else(|| IgcError::new(line_nb, record, "time out of range"))
+    }
+
+    fn process_record(line: &str, line_nb: usize, clock: &mut Clock) -> Result<FlightPoint, IgcError> {
+        if line.len() < B_LENGTH || !line.is_ascii() {
+            return Err(IgcError::new(line_nb, IGC_RECORD, "record too short"));
+        }
+
+        let time = Self::process_time(line, line_nb, IGC_RECORD, B_TIME)?;
 
         let lat: f32 = {
-            let degree: u32 = c.next().unwrap_or('0').to_digit(10).unwrap_or(0) * 10
-                + c.next().unwrap_or('0').to_digit(10).unwrap_or(0);
-            let minute: u32 = c.next().unwrap_or('0').to_digit(10).unwrap_or(0) * 10
-                + c.next().unwrap_or('0').to_digit(10).unwrap_or(0);
-            let minute: f32 = (minute as f32)
-                + (c.next().unwrap_or('0').to_digit(10).unwrap_or(0) as f32) / 10.0
-                + (c.next().unwrap_or('0').to_digit(10).unwrap_or(0) as f32) / 100.0
-                + (c.next().unwrap_or('0').to_digit(10).unwrap_or(0) as f32) / 1000.0;
-
-            let snew: char = c.next().unwrap_or('0');
-
-            Self::to_decimal(degree, minute, snew)
+            let degree: u32 = Self::field(line, line_nb, IGC_RECORD, B_LAT_DEGREE, "latitude")?;
+            let minute: u32 = Self::field(line, line_nb, IGC_RECORD, B_LAT_MINUTE, "latitude")?;
+            let snew = line.as_bytes()[B_LAT_HEMISPHERE] as char;
+
+            if degree > 90 || minute >= 60000 || (snew != 'N' && snew != 'S') {
+                return Err(IgcError::new(line_nb, IGC_RECORD, "latitude out of range"));
+            }
+
+            Self::to_decimal(degree, minute as f32 / 1000.0, snew)
         };
 
-        let long = {
-            let degree = c.next().unwrap_or('0').to_digit(10).unwrap_or(0) * 100
-                + c.next().unwrap_or('0').to_digit(10).unwrap_or(0) * 10
-                + c.next().unwrap_or('0').to_digit(10).unwrap_or(0);
-            let minute = c.next().unwrap_or('0').to_digit(10).unwrap_or(0) * 10
-                + c.next().unwrap_or('0').to_digit(10).unwrap_or(0);
-            let minute = (minute as f32)
-                + (c.next().unwrap_or('0').to_digit(10).unwrap_or(0) as f32) / 10.0
-                + (c.next().unwrap_or('0').to_digit(10).unwrap_or(0) as f32) / 100.0
-                + (c.next().unwrap_or('0').to_digit(10).unwrap_or(0) as f32) / 1000.0;
+        let long: f32 = {
+            let degree: u32 = Self::field(line, line_nb, IGC_RECORD, B_LONG_DEGREE, "longitude")?;
+            let minute: u32 = Self::field(line, line_nb, IGC_RECORD, B_LONG_MINUTE, "longitude")?;
+            let snew = line.as_bytes()[B_LONG_HEMISPHERE] as char;
 
-            let snew = c.next().unwrap_or('0');
+            if degree > 180 || minute >= 60000 || (snew != 'E' && snew != 'W') {
+                return Err(IgcError::new(line_nb, IGC_RECORD, "longitude out of range"));
+            }
 
-            Self::to_decimal(degree, minute, snew)
+            Self::to_decimal(degree, minute as f32 / 1000.0, snew)
         };
 
-        let _validity = c.next().unwrap_or('0') == 'A';
-        let alt = {
-            c.next().unwrap_or('0').to_digit(10).unwrap_or(0) * 10000
-                + c.next().unwrap_or('0').to_digit(10).unwrap_or(0) * 1000
-                + c.next().unwrap_or('0').to_digit(10).unwrap_or(0) * 100
-                + c.next().unwrap_or('0').to_digit(10).unwrap_or(0) * 10
-                + c.next().unwrap_or('0').to_digit(10).unwrap_or(0)
-        }; //Barometric
-
-        let alt_gps = {
-            c.next().unwrap_or('0').to_digit(10).unwrap_or(0) * 10000
-                + c.next().unwrap_or('0').to_digit(10).unwrap_or(0) * 1000
-                + c.next().unwrap_or('0').to_digit(10).unwrap_or(0) * 100
-                + c.next().unwrap_or('0').to_digit(10).unwrap_or(0) * 10
-                + c.next().unwrap_or('0').to_digit(10).unwrap_or(0)
-        };
+        let validity = line.as_bytes()[B_VALIDITY] as char;
+        if validity != 'A' && validity != 'V' {
+            return Err(IgcError::new(line_nb, IGC_RECORD, "bad fix validity"));
+        }
 
-        let time = date.and_hms_opt(hour, min, sec).unwrap_or_default();
+        //Barometric
+        let alt: i32 = Self::field(line, line_nb, IGC_RECORD, B_ALT, "pressure altitude")?;
+        let alt_gps: i32 = Self::field(line, line_nb, IGC_RECORD, B_ALT_GPS, "GPS altitude")?;
 
-        FlightPoint {
-            time,
+        Ok(FlightPoint {
+            time: clock.timestamp(time),
             long,
             lat,
-            alt,
-            alt_gps,
+            alt: alt.max(0) as u32,
+            alt_gps: alt_gps.max(0) as u32,
+            alt_baro: alt.max(0) as u32,
+        })
+    }
+
+    fn process_extension(line: &str, line_nb: usize, record: &str, fixed_length: usize) -> Result<Vec<IgcExtension>, IgcError>
+    {
+        let count: usize = Self::field(line, line_nb, record, EXTENSION_COUNT, "extension count")?;
+        let mut extensions: Vec<IgcExtension> = Vec::new();
+
+        for i in 0..count
+        {
+            let offset = EXTENSION_FIRST + i * EXTENSION_LENGTH;
+            let start: usize = Self::field(line, line_nb, record, offset..offset + 2, "extension start")?;
+            let end: usize = Self::field(line, line_nb, record, offset + 2..offset + 4, "extension end")?;
+            let code = line.get(offset + 4..offset + 7)
+                .ok_or_else(|| IgcError::new(line_nb, record, "extension code"))?;
+
+            if start <= fixed_length || end < start {
+                return Err(IgcError::new(line_nb, record, &format!("bad {} extension position", code)));
+            }
+
+            extensions.push(IgcExtension {
+                code: code.to_string(),
+                start,
+                end,
+            });
         }
+
+        Ok(extensions)
+    }
+
+    fn process_extension_values(line: &str, extensions: &Vec<IgcExtension>) -> HashMap<String,String>
+    {
+        let mut values: HashMap<String,String> = HashMap::new();
+
+        for ext in extensions
+        {
+            // Loggers sometimes omit trailing extensions, the value is then missing
+            if let Some(v) = line.get(ext.start - 1..ext.end) {
+                values.insert(ext.code.clone(), v.to_string());
+            }
+        }
+
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const HEADER: &str = "AXXXABC FLIGHT:1\nHFDTEDATE:120824,01\nHFPLTPILOTINCHARGE:Jane Doe\n";
+
+    fn read(records: &str) -> Result<IgcReader>
+    {
+        IgcReader::read(&format!("{}{}", HEADER, records), None)
+    }
+
+    fn error(records: &str) -> (usize, char, String)
+    {
+        let err = read(records).err().unwrap();
+        let err = err.downcast_ref::<IgcError>().unwrap();
+
+        (err.line, err.record, err.reason.clone())
+    }
+
+    #[test]
+    fn b_extensions()
+    {
+        let igc = read("I023638FXA3940SIU\nB1101355206343N00006198WA0058700558032 9\nB1101365206343N00006198WA0058700558\n").unwrap();
+
+        assert_eq!(igc.b_extensions.len(), 2);
+        assert_eq!((igc.b_extensions[1].code.as_str(), igc.b_extensions[1].start, igc.b_extensions[1].end), ("SIU", 39, 40));
+        assert_eq!(igc.header.pilot, "Jane Doe");
+
+        // One entry per fix, trailing extensions left out by the logger are missing
+        assert_eq!(igc.fix_extensions.len(), igc.track.len());
+        assert_eq!(igc.fix_extensions[0].fxa(), Some(32));
+        assert_eq!(igc.fix_extensions[0].siu(), Some(9));
+        assert_eq!(igc.fix_extensions[1].fxa(), None);
+
+        // The fixed part is read as without extension
+        assert_eq!(igc.track[0].alt, 587);
+        assert_eq!(igc.track[0].alt_gps, 558);
+        assert!((igc.track[0].lat - 52.10572).abs() < 1e-5);
+        assert!((igc.track[0].long + 0.10330).abs() < 1e-5);
+    }
+
+    #[test]
+    fn k_records()
+    {
+        let igc = read("J010812WDI\nB1101355206343N00006198WA0058700558\nK11014027015\n").unwrap();
+
+        assert_eq!(igc.k_extensions[0].code, "WDI");
+        assert_eq!(igc.k_records.len(), 1);
+        assert_eq!(igc.k_records[0].time, NaiveDate::from_ymd_opt(2024, 8, 12).unwrap().and_hms_opt(11, 1, 40).unwrap());
+        assert_eq!(igc.k_records[0].values.get("WDI").map(|v| v.as_str()), Some("27015"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn errors_give_the_line()
+    {
+        let fix = "B1101355206343N00006198WA0058700558\n";
+
+        assert_eq!(error(&format!("{}B1101365206343N00006198W\n", fix)), (5, 'B', "record too short".to_string()));
+        assert_eq!(error(&format!("{}B1101369206343N00006198WA0058700558\n", fix)), (5, 'B', "latitude out of range".to_string()));
+        assert_eq!(error(&format!("{}B1161365206343N00006198WA0058700558\n", fix)), (5, 'B', "time out of range".to_string()));
+        assert_eq!(error(&format!("{}B1101365206343N00006198WX0058700558\n", fix)), (5, 'B', "bad fix validity".to_string()));
+        assert_eq!(error(&format!("{}B1101365206343N00006198WA00587ABCDE\n", fix)), (5, 'B', "bad GPS altitude".to_string()));
+        assert_eq!(error("I013034FXA\n"), (4, 'I', "bad FXA extension position".to_string()));
+        assert_eq!(error(&format!("{}K1101\n", fix)), (5, 'K', "bad second".to_string()));
+
+        let err = read(&format!("{}B1101369206343N00006198WA0058700558\n", fix)).err().unwrap();
+        assert_eq!(err.to_string(), "IGC line 5: invalid B record (latitude out of range)");
+    }
+
+    #[test]
+    fn midnight_rollover()
+    {
+        let igc = read("B2359585206343N00006198WA0058700558\nB2359565206343N00006198WA0058700558\nB0000025206343N00006198WA0058700558\nK000010\n").unwrap();
+        let day = NaiveDate::from_ymd_opt(2024, 8, 12).unwrap();
+
+        // A fix a few seconds late stays on the same day
+        assert_eq!(igc.track[1].time, day.and_hms_opt(23, 59, 56).unwrap());
+        assert_eq!(igc.track[2].time, day.succ_opt().unwrap().and_hms_opt(0, 0, 2).unwrap());
+        assert_eq!(igc.k_records[0].time, day.succ_opt().unwrap().and_hms_opt(0, 0, 10).unwrap());
+        assert_eq!(igc.date, day);
+    }
+
+    #[test]
+    fn date_from_long_and_short_file_names()
+    {
+        assert_eq!(date_from_file_name("2024-08-12-XCT-ABC-01.igc"), NaiveDate::from_ymd_opt(2024, 8, 12));
+        assert_eq!(date_from_file_name("2024-08-12-xct-abc-01.IGC"), NaiveDate::from_ymd_opt(2024, 8, 12));
+
+        // Year from its last digit, the most recent one
+        let date = date_from_file_name("48cxabc1.igc").unwrap();
+        let current_year = Local::now().year();
+        assert_eq!((date.month(), date.day(), date.year() % 10), (8, 12, 4));
+        assert!(date.year() <= current_year && date.year() > current_year - 10);
+
+        assert_eq!(date_from_file_name("4DCXABC1.IGC"), None);// month 13
+        assert_eq!(date_from_file_name("48CXABC.IGC"), None);
+        assert_eq!(date_from_file_name("flight.igc"), None);
+    }
+}
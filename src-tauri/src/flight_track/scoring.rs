@@ -0,0 +1,680 @@
+use anyhow::{Result, bail};
+use serde_json::{json, Value};
+
+use crate::logbook::FlightPoint;
+
+const EARTH_RADIUS: f64 = 6371.0;// km - FAI sphere
+
+const MAX_POINTS_DISTANCE: usize = 1000;
+const MAX_POINTS_TRIANGLE: usize = 200;
+const MAX_REFINE_ITERATION: usize = 50;
+
+const FAI_MIN_LEG_RATIO: f64 = 0.28;
+
+const CFD_CLOSING_RATIO: f64 = 0.05;
+const XCONTEST_CLOSING_RATIO: f64 = 0.2;
+const WXC_CLOSING_RATIO: f64 = 0.2;
+const LEONARDO_CLOSING_RATIO: f64 = 0.2;
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum RouteType {
+    FreeDistance,
+    FlatTriangle,
+    FaiTriangle,
+}
+
+pub trait ScoringRules {
+    fn id(&self) -> &'static str;
+    fn name(&self) -> &'static str;
+    fn multiplier(&self, route: RouteType) -> f64;
+    fn closing_ratio(&self) -> f64;
+
+    fn fai_min_leg_ratio(&self) -> f64 {
+        FAI_MIN_LEG_RATIO
+    }
+
+    // Maximum closing distance (km) allowed for a triangle
+    fn closing_limit(&self, perimeter: f64) -> f64 {
+        self.closing_ratio() * perimeter
+    }
+}
+
+// Coupe Fédérale de Distance (FFVL)
+pub struct Cfd;
+pub struct XContest;
+// World XContest (FAI)
+pub struct Wxc;
+pub struct Leonardo;
+
+#[derive(Clone,Debug)]
+pub struct FlightScore {
+    pub rules: &'static str,
+    pub route: RouteType,
+    pub score: f64,     // points
+    pub distance: f64,  // km
+    pub multiplier: f64,
+    pub closing: f64,   // km
+    pub geojson: Value,
+}
+
+#[derive(Clone,Copy)]
+struct ScorePoint {
+    lat: f64,// rad
+    long: f64,// rad
+    cos_lat: f64,
+}
+
+// Candidate route as indexes in the track
+// Free distance : [start, tp0, tp1, tp2, finish]
+// Triangle      : [closing_in, tp0, tp1, tp2, closing_out]
+struct Route {
+    route: RouteType,
+    index: [usize; 5],
+    points: f64,
+}
+
+impl RouteType {
+    pub fn code(&self) -> &'static str {
+        match self {
+            RouteType::FreeDistance => "od",
+            RouteType::FlatTriangle => "tri",
+            RouteType::FaiTriangle => "fai",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RouteType::FreeDistance => "Free Distance",
+            RouteType::FlatTriangle => "Flat Triangle",
+            RouteType::FaiTriangle => "FAI Triangle",
+        }
+    }
+}
+
+impl ScoringRules for Cfd {
+    fn id(&self) -> &'static str {
+        "cfd"
+    }
+
+    fn name(&self) -> &'static str {
+        "FFVL CFD"
+    }
+
+    fn multiplier(&self, route: RouteType) -> f64 {
+        match route {
+            RouteType::FreeDistance => 1.0,
+            RouteType::FlatTriangle => 1.2,
+            RouteType::FaiTriangle => 1.4,
+        }
+    }
+
+    fn closing_ratio(&self) -> f64 {
+        CFD_CLOSING_RATIO
+    }
+}
+
+impl ScoringRules for XContest {
+    fn id(&self) -> &'static str {
+        "xcontest"
+    }
+
+    fn name(&self) -> &'static str {
+        "XContest"
+    }
+
+    fn multiplier(&self, route: RouteType) -> f64 {
+        match route {
+            RouteType::FreeDistance => 1.0,
+            RouteType::FlatTriangle => 1.2,
+            RouteType::FaiTriangle => 1.4,
+        }
+    }
+
+    fn closing_ratio(&self) -> f64 {
+        XCONTEST_CLOSING_RATIO
+    }
+}
+
+impl ScoringRules for Wxc {
+    fn id(&self) -> &'static str {
+        "wxc"
+    }
+
+    fn name(&self) -> &'static str {
+        "World XContest"
+    }
+
+    fn multiplier(&self, route: RouteType) -> f64 {
+        match route {
+            RouteType::FreeDistance => 1.0,
+            RouteType::FlatTriangle => 1.2,
+            RouteType::FaiTriangle => 1.4,
+        }
+    }
+
+    fn closing_ratio(&self) -> f64 {
+        WXC_CLOSING_RATIO
+    }
+}
+
+impl ScoringRules for Leonardo {
+    fn id(&self) -> &'static str {
+        "leonardo"
+    }
+
+    fn name(&self) -> &'static str {
+        "Leonardo"
+    }
+
+    fn multiplier(&self, route: RouteType) -> f64 {
+        match route {
+            RouteType::FreeDistance => 1.5,
+            RouteType::FlatTriangle => 1.75,
+            RouteType::FaiTriangle => 2.0,
+        }
+    }
+
+    fn closing_ratio(&self) -> f64 {
+        LEONARDO_CLOSING_RATIO
+    }
+}
+
+pub fn rule_sets() -> Vec<Box<dyn ScoringRules>>
+{
+    vec![Box::new(Cfd), Box::new(XContest), Box::new(Wxc), Box::new(Leonardo)]
+}
+
+pub fn rules(id: &str) -> Option<Box<dyn ScoringRules>>
+{
+    rule_sets().into_iter().find(|r| r.id() == id)
+}
+
+impl ScorePoint {
+    fn new(pt: &FlightPoint) -> Self
+    {
+        let lat = (pt.lat as f64).to_radians();
+
+        ScorePoint {
+            lat,
+            long: (pt.long as f64).to_radians(),
+            cos_lat: lat.cos(),
+        }
+    }
+
+    fn distance(&self, other: &ScorePoint) -> f64
+    {
+        let dlat = (other.lat - self.lat) / 2.0;
+        let dlong = (other.long - self.long) / 2.0;
+        let a = dlat.sin() * dlat.sin() + self.cos_lat * other.cos_lat * dlong.sin() * dlong.sin();
+
+        2.0 * EARTH_RADIUS * a.sqrt().asin()
+    }
+}
+
+impl FlightScore {
+    pub fn code(&self) -> &'static str
+    {
+        self.route.code()
+    }
+}
+
+pub fn score(track: &Vec<FlightPoint>, rules: &dyn ScoringRules) -> Result<FlightScore>
+{
+    if track.len() < 2
+    {
+        bail!("Not enough points to score the flight");
+    }
+
+    let points: Vec<ScorePoint> = track.iter().map(ScorePoint::new).collect();
+
+    let mut best = free_distance(&points, rules);
+
+    for route in triangles(&points, rules)
+    {
+        if route.points > best.points
+        {
+            best = route;
+        }
+    }
+
+    Ok(to_flight_score(&best, &points, track, rules))
+}
+
+fn sample(len: usize, max: usize) -> Vec<usize>
+{
+    if len <= max
+    {
+        return (0..len).collect();
+    }
+
+    let mut index: Vec<usize> = (0..max).map(|i| i * (len - 1) / (max - 1)).collect();
+    index.dedup();
+    index
+}
+
+fn free_distance_points(points: &Vec<ScorePoint>, index: &[usize], rules: &dyn ScoringRules) -> Option<f64>
+{
+    let mut dist = 0.0;
+
+    for i in 0..index.len() - 1
+    {
+        dist += points[index[i]].distance(&points[index[i + 1]]);
+    }
+
+    Some(dist * rules.multiplier(RouteType::FreeDistance))
+}
+
+fn triangle_points(points: &Vec<ScorePoint>, index: &[usize], route: RouteType, rules: &dyn ScoringRules) -> Option<f64>
+{
+    let leg0 = points[index[1]].distance(&points[index[2]]);
+    let leg1 = points[index[2]].distance(&points[index[3]]);
+    let leg2 = points[index[3]].distance(&points[index[1]]);
+    let perimeter = leg0 + leg1 + leg2;
+    let closing = points[index[0]].distance(&points[index[4]]);
+
+    if perimeter <= 0.0 || closing > rules.closing_limit(perimeter)
+    {
+        return None;
+    }
+
+    if route == RouteType::FaiTriangle && leg0.min(leg1).min(leg2) < rules.fai_min_leg_ratio() * perimeter
+    {
+        return None;
+    }
+
+    Some((perimeter - closing) * rules.multiplier(route))
+}
+
+fn route_points(points: &Vec<ScorePoint>, index: &[usize], route: RouteType, rules: &dyn ScoringRules) -> Option<f64>
+{
+    match route {
+        RouteType::FreeDistance => free_distance_points(points, index, rules),
+        _ => triangle_points(points, index, route, rules),
+    }
+}
+
+fn free_distance(points: &Vec<ScorePoint>, rules: &dyn ScoringRules) -> Route
+{
+    // Longest path through 5 ordered points (start, 3 turnpoints, finish) on a sampled track
+    let sampled = sample(points.len(), MAX_POINTS_DISTANCE);
+    let n = sampled.len();
+    let legs = 4;
+
+    let mut best: Vec<Vec<f64>> = vec![vec![0.0; n]; legs + 1];
+    let mut prev: Vec<Vec<usize>> = vec![vec![0; n]; legs + 1];
+
+    for leg in 1..=legs
+    {
+        for i in 0..n
+        {
+            best[leg][i] = best[leg - 1][i];
+            prev[leg][i] = i;
+
+            for j in 0..i
+            {
+                let d = best[leg - 1][j] + points[sampled[j]].distance(&points[sampled[i]]);
+                if d > best[leg][i]
+                {
+                    best[leg][i] = d;
+                    prev[leg][i] = j;
+                }
+            }
+        }
+    }
+
+    let mut last = 0;
+    for i in 0..n
+    {
+        if best[legs][i] > best[legs][last]
+        {
+            last = i;
+        }
+    }
+
+    let mut index = [0; 5];
+    index[legs] = sampled[last];
+    for leg in (1..=legs).rev()
+    {
+        last = prev[leg][last];
+        index[leg - 1] = sampled[last];
+    }
+
+    let stride = points.len() / n + 1;
+    refine(points, Route { route: RouteType::FreeDistance, index, points: 0.0 }, stride, rules)
+}
+
+fn triangles(points: &Vec<ScorePoint>, rules: &dyn ScoringRules) -> Vec<Route>
+{
+    let sampled = sample(points.len(), MAX_POINTS_TRIANGLE);
+    let n = sampled.len();
+    let mut routes: Vec<Route> = Vec::new();
+
+    if n < 3
+    {
+        return routes;
+    }
+
+    // closing[a][c] : best closing pair (in <= a, out >= c) and its distance
+    let mut closing: Vec<Vec<(f64, usize, usize)>> = vec![vec![(f64::MAX, 0, 0); n]; n];
+    for a in 0..n
+    {
+        for c in (a..n).rev()
+        {
+            let mut best = (points[sampled[a]].distance(&points[sampled[c]]), a, c);
+            if a > 0 && closing[a - 1][c].0 < best.0
+            {
+                best = closing[a - 1][c];
+            }
+            if c < n - 1 && closing[a][c + 1].0 < best.0
+            {
+                best = closing[a][c + 1];
+            }
+            closing[a][c] = best;
+        }
+    }
+
+    let stride = points.len() / n + 1;
+
+    for route in [RouteType::FlatTriangle, RouteType::FaiTriangle]
+    {
+        let mut best: Option<Route> = None;
+
+        for a in 0..n
+        {
+            for c in a + 2..n
+            {
+                let (_, cin, cout) = closing[a][c];
+
+                for b in a + 1..c
+                {
+                    let index = [sampled[cin], sampled[a], sampled[b], sampled[c], sampled[cout]];
+                    if let Some(p) = triangle_points(points, &index, route, rules)
+                    {
+                        if best.as_ref().map(|r| p > r.points).unwrap_or(true)
+                        {
+                            best = Some(Route { route, index, points: p });
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(r) = best
+        {
+            routes.push(refine(points, r, stride, rules));
+        }
+    }
+
+    routes
+}
+
+fn refine(points: &Vec<ScorePoint>, mut route: Route, window: usize, rules: &dyn ScoringRules) -> Route
+{
+    // Local search on the full resolution track around the sampled solution
+    route.points = route_points(points, &route.index, route.route, rules).unwrap_or(0.0);
+
+    for _ in 0..MAX_REFINE_ITERATION
+    {
+        let mut improved = false;
+
+        for i in 0..route.index.len()
+        {
+            let low = if i == 0 { 0 } else { route.index[i - 1] }.max(route.index[i].saturating_sub(window));
+            let high = if i == route.index.len() - 1 { points.len() - 1 } else { route.index[i + 1] }.min(route.index[i] + window);
+
+            let mut index = route.index;
+            for candidate in low..=high
+            {
+                index[i] = candidate;
+                if let Some(p) = route_points(points, &index, route.route, rules)
+                {
+                    if p > route.points
+                    {
+                        route.points = p;
+                        route.index[i] = candidate;
+                        improved = true;
+                    }
+                }
+            }
+        }
+
+        if !improved
+        {
+            break;
+        }
+    }
+
+    route
+}
+
+fn to_flight_score(route: &Route, points: &Vec<ScorePoint>, track: &Vec<FlightPoint>, rules: &dyn ScoringRules) -> FlightScore
+{
+    let multiplier = rules.multiplier(route.route);
+    let index = &route.index;
+
+    let closing = match route.route {
+        RouteType::FreeDistance => 0.0,
+        _ => points[index[0]].distance(&points[index[4]]),
+    };
+
+    let distance = route.points / multiplier;
+    let score = (route.points * 100.0).round() / 100.0;
+
+    let mut features: Vec<Value> = Vec::new();
+
+    features.push(line_feature("flight", track.iter().collect()));
+    features.push(point_feature("launch0", &track[0]));
+    features.push(point_feature("land0", &track[track.len() - 1]));
+
+    for tp in 0..3
+    {
+        features.push(point_feature(&format!("tp{}", tp), &track[index[tp + 1]]));
+    }
+
+    match route.route {
+        RouteType::FreeDistance => {
+            features.push(point_feature("ep_start", &track[index[0]]));
+            features.push(point_feature("ep_finish", &track[index[4]]));
+            features.push(line_feature("seg_in", vec![&track[index[0]], &track[index[1]]]));
+            features.push(line_feature("seg0", vec![&track[index[1]], &track[index[2]]]));
+            features.push(line_feature("seg1", vec![&track[index[2]], &track[index[3]]]));
+            features.push(line_feature("seg_out", vec![&track[index[3]], &track[index[4]]]));
+        },
+        _ => {
+            features.push(point_feature("cp_in", &track[index[0]]));
+            features.push(point_feature("cp_out", &track[index[4]]));
+            features.push(line_feature("seg0", vec![&track[index[1]], &track[index[2]]]));
+            features.push(line_feature("seg1", vec![&track[index[2]], &track[index[3]]]));
+            features.push(line_feature("seg2", vec![&track[index[3]], &track[index[1]]]));
+            features.push(line_feature("closing", vec![&track[index[0]], &track[index[4]]]));
+        },
+    }
+
+    let geojson = json!({
+        "type": "FeatureCollection",
+        "features": features,
+        "properties": {
+            "name": route.route.name(),
+            "rules": rules.id(),
+            "code": route.route.code(),
+            "score": score,
+            "distance": (distance * 1000.0).round() / 1000.0,
+            "multiplier": multiplier,
+            "closing": (closing * 1000.0).round() / 1000.0,
+        },
+    });
+
+    FlightScore {
+        rules: rules.id(),
+        route: route.route,
+        score,
+        distance,
+        multiplier,
+        closing,
+        geojson,
+    }
+}
+
+fn point_feature(id: &str, pt: &FlightPoint) -> Value
+{
+    json!({
+        "type": "Feature",
+        "id": id,
+        "properties": {
+            "id": id,
+            "timestamp": pt.time.timestamp() * 1000,
+        },
+        "geometry": {
+            "type": "Point",
+            "coordinates": [pt.long, pt.lat],
+        },
+    })
+}
+
+fn line_feature(id: &str, pts: Vec<&FlightPoint>) -> Value
+{
+    let coordinates: Vec<Value> = pts.iter().map(|pt| json!([pt.long, pt.lat, pt.alt])).collect();
+
+    json!({
+        "type": "Feature",
+        "id": id,
+        "properties": {
+            "id": id,
+        },
+        "geometry": {
+            "type": "LineString",
+            "coordinates": coordinates,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use chrono::{Duration, NaiveDate};
+    use super::*;
+
+    // Distances computed on the FAI sphere from the corners below
+    const STRAIGHT: f64 = 55.5975;// km - 45.0N to 45.5N
+    const FLAT: f64 = 89.0666;// km - legs of 44.48, 22.29 and 22.29, shortest leg 25% of the perimeter
+    const FAI: f64 = 100.2941;// km - legs of 33.36, 33.43 and 33.50
+    const FAI_CLOSING: f64 = 10.2215;// km - from the first corner to 45.0N 5.87E
+
+    const STRAIGHT_CORNERS: [(f32, f32); 2] = [(45.0, 6.0), (45.5, 6.0)];
+    const FLAT_CORNERS: [(f32, f32); 4] = [(45.0, 6.0), (45.4, 6.0), (45.2, 6.02), (45.0, 6.0)];
+    const FAI_CORNERS: [(f32, f32); 4] = [(45.0, 6.0), (45.3, 6.0), (45.15, 6.37), (45.0, 6.0)];
+
+    fn fix(n: usize, lat: f32, long: f32) -> FlightPoint
+    {
+        let time = NaiveDate::from_ymd_opt(2024, 8, 12).unwrap().and_hms_opt(12, 0, 0).unwrap() + Duration::minutes(n as i64);
+        FlightPoint { time, lat, long, alt: 1500, alt_gps: 1500, alt_baro: 1500 }
+    }
+
+    // Straight legs between the corners, 20 fixes per leg
+    fn track(corners: &[(f32, f32)]) -> Vec<FlightPoint>
+    {
+        let mut track: Vec<FlightPoint> = Vec::new();
+
+        for leg in corners.windows(2)
+        {
+            for step in 0..20
+            {
+                let f = step as f32 / 20.0;
+                track.push(fix(track.len(), leg[0].0 + (leg[1].0 - leg[0].0) * f, leg[0].1 + (leg[1].1 - leg[0].1) * f));
+            }
+        }
+        let (lat, long) = corners[corners.len() - 1];
+        track.push(fix(track.len(), lat, long));
+
+        track
+    }
+
+    fn assert_close(value: f64, expected: f64)
+    {
+        assert!((value - expected).abs() < 0.01, "{} instead of {}", value, expected);
+    }
+
+    #[test]
+    fn free_distance()
+    {
+        let s = score(&track(&STRAIGHT_CORNERS), &Cfd).unwrap();
+
+        assert_eq!(s.route, RouteType::FreeDistance);
+        assert_close(s.distance, STRAIGHT);
+        assert_close(s.score, STRAIGHT);
+        assert_eq!(s.closing, 0.0);
+    }
+
+    #[test]
+    fn flat_triangle()
+    {
+        let s = score(&track(&FLAT_CORNERS), &Cfd).unwrap();
+
+        assert_eq!(s.route, RouteType::FlatTriangle);
+        assert_close(s.distance, FLAT);
+        assert_close(s.score, FLAT * 1.2);
+        assert_close(s.closing, 0.0);
+    }
+
+    #[test]
+    fn fai_triangle()
+    {
+        let s = score(&track(&FAI_CORNERS), &Cfd).unwrap();
+
+        assert_eq!(s.route, RouteType::FaiTriangle);
+        assert_close(s.distance, FAI);
+        assert_close(s.score, FAI * 1.4);
+        assert_eq!(s.code(), "fai");
+    }
+
+    #[test]
+    fn closing_distance_limits()
+    {
+        // Triangle left 10% of its perimeter away from where it started
+        let points: Vec<ScorePoint> = [(45.0, 5.87), (45.0, 6.0), (45.3, 6.0), (45.15, 6.37), (45.0, 6.0)]
+            .iter()
+            .map(|&(lat, long)| ScorePoint::new(&fix(0, lat, long)))
+            .collect();
+        let index = [1, 1, 2, 3, 0];
+
+        assert_close(Cfd.closing_limit(FAI), FAI * 0.05);
+        assert_close(XContest.closing_limit(FAI), FAI * 0.2);
+
+        assert_eq!(triangle_points(&points, &index, RouteType::FaiTriangle, &Cfd), None);
+        assert_close(triangle_points(&points, &index, RouteType::FaiTriangle, &XContest).unwrap(), (FAI - FAI_CLOSING) * 1.4);
+        assert_close(triangle_points(&points, &index, RouteType::FaiTriangle, &Leonardo).unwrap(), (FAI - FAI_CLOSING) * 2.0);
+
+        // Closed triangle, nothing taken off
+        assert_close(triangle_points(&points, &[1, 1, 2, 3, 4], RouteType::FaiTriangle, &Cfd).unwrap(), FAI * 1.4);
+    }
+
+    #[test]
+    fn multipliers()
+    {
+        let expected = [
+            ("cfd", [1.0, 1.2, 1.4]),
+            ("xcontest", [1.0, 1.2, 1.4]),
+            ("wxc", [1.0, 1.2, 1.4]),
+            ("leonardo", [1.5, 1.75, 2.0]),
+        ];
+        let tracks = [track(&STRAIGHT_CORNERS), track(&FLAT_CORNERS), track(&FAI_CORNERS)];
+        let routes = [RouteType::FreeDistance, RouteType::FlatTriangle, RouteType::FaiTriangle];
+
+        assert_eq!(rule_sets().len(), expected.len());
+        assert!(rules("unknown").is_none());
+
+        for (id, multipliers) in expected
+        {
+            let rules = rules(id).unwrap();
+
+            for ((route, track), multiplier) in routes.iter().zip(&tracks).zip(multipliers)
+            {
+                let s = score(track, rules.as_ref()).unwrap();
+
+                assert_eq!(s.route, *route, "{}", id);
+                assert_eq!(s.multiplier, multiplier, "{}", id);
+                assert_close(s.score, s.distance * multiplier);
+            }
+        }
+    }
+}
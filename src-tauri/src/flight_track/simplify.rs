@@ -0,0 +1,221 @@
+use std::{cmp::Ordering, collections::BinaryHeap, str::FromStr};
+use anyhow::{Result, bail};
+
+use crate::logbook::FlightPoint;
+
+const EARTH_RADIUS: f64 = 6371000.0;// m
+
+// Douglas-Peucker tolerance
+//  Epsilon   : maximum distance of a removed fix to the simplified track (m)
+//  MaxPoints : keep the given number of fixes, the ones furthest from the simplified track first
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Simplification {
+    Epsilon(f64),
+    MaxPoints(usize),
+}
+
+// Segment of the track between two kept fixes and its furthest fix
+struct Segment {
+    start: usize,
+    end: usize,
+    furthest: usize,
+    distance: f64,
+}
+
+impl Default for Simplification {
+    fn default() -> Self {
+        Simplification::Epsilon(1.0)
+    }
+}
+
+impl ToString for Simplification {
+    fn to_string(&self) -> String {
+        match self {
+            Simplification::Epsilon(e) => format!("epsilon={}", e),
+            Simplification::MaxPoints(n) => format!("max_points={}", n),
+        }
+    }
+}
+
+impl FromStr for Simplification {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().split_once('=') {
+            Some(("epsilon", e)) => Ok(Simplification::Epsilon(e.parse()?)),
+            Some(("max_points", n)) => Ok(Simplification::MaxPoints(n.parse()?)),
+            _ => bail!("Invalid simplification {}", s),
+        }
+    }
+}
+
+impl PartialEq for Segment {
+    fn eq(&self, other: &Self) -> bool {
+        self.distance == other.distance
+    }
+}
+
+impl Eq for Segment {}
+
+impl PartialOrd for Segment {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Segment {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance.total_cmp(&other.distance)
+    }
+}
+
+pub fn simplify(track: &[FlightPoint], mode: &Simplification) -> Vec<FlightPoint>
+{
+    simplify_indexes(track, mode).into_iter().map(|i| track[i]).collect()
+}
+
+// Indexes of the kept fixes, segments are split worst first so both modes share the same loop
+fn simplify_indexes(track: &[FlightPoint], mode: &Simplification) -> Vec<usize>
+{
+    if track.len() < 3
+    {
+        return (0..track.len()).collect();
+    }
+
+    let points = project(track);
+    let mut kept: Vec<usize> = vec![0, track.len() - 1];
+    let mut heap: BinaryHeap<Segment> = BinaryHeap::new();
+    heap.push(segment(&points, 0, track.len() - 1));
+
+    while let Some(s) = heap.pop()
+    {
+        let done = match mode {
+            Simplification::Epsilon(e) => s.distance <= *e,
+            Simplification::MaxPoints(n) => kept.len() >= *n,
+        };
+
+        // Everything left is on the simplified track
+        if done || s.distance <= 0.0
+        {
+            break;
+        }
+
+        kept.push(s.furthest);
+        for (start, end) in [(s.start, s.furthest), (s.furthest, s.end)]
+        {
+            if end - start >= 2
+            {
+                heap.push(segment(&points, start, end));
+            }
+        }
+    }
+
+    kept.sort();
+    kept
+}
+
+// Equirectangular projection around the mean latitude, in metres
+fn project(track: &[FlightPoint]) -> Vec<(f64, f64)>
+{
+    let lat0 = (track.iter().map(|p| p.lat as f64).sum::<f64>() / track.len() as f64).to_radians();
+    let long0 = track[0].long as f64;
+
+    track.iter()
+        .map(|p| (
+            (p.long as f64 - long0).to_radians() * lat0.cos() * EARTH_RADIUS,
+            (p.lat as f64).to_radians() * EARTH_RADIUS,
+        ))
+        .collect()
+}
+
+fn segment(points: &Vec<(f64, f64)>, start: usize, end: usize) -> Segment
+{
+    let mut furthest = start;
+    let mut distance = 0.0;
+
+    for i in start + 1..end
+    {
+        let d = distance_to_segment(points[i], points[start], points[end]);
+        if d > distance
+        {
+            distance = d;
+            furthest = i;
+        }
+    }
+
+    Segment { start, end, furthest, distance }
+}
+
+fn distance_to_segment(p: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64
+{
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length2 = dx * dx + dy * dy;
+
+    let u = if length2 == 0.0 {
+        0.0
+    } else {
+        (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / length2).clamp(0.0, 1.0)
+    };
+
+    (p.0 - a.0 - u * dx).hypot(p.1 - a.1 - u * dy)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use chrono::{Duration, NaiveDate};
+    use super::*;
+
+    // 10 fixes 111 m apart to the north then 10 to the east, the 5th fix is 4.7 m off the line
+    fn corner() -> Vec<FlightPoint>
+    {
+        let start = NaiveDate::from_ymd_opt(2024, 8, 12).unwrap().and_hms_opt(12, 0, 0).unwrap();
+
+        (0..=20)
+            .map(|i| {
+                let (lat, long) = if i <= 10 { (45.0 + i as f32 * 0.001, 6.0) } else { (45.01, 6.0 + (i - 10) as f32 * 0.001) };
+                let long = if i == 4 { long + 0.00006 } else { long };
+                FlightPoint { time: start + Duration::seconds(i), lat, long, alt: 1500, alt_gps: 1500, alt_baro: 1500 }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn epsilon()
+    {
+        let track = corner();
+
+        assert_eq!(simplify_indexes(&track, &Simplification::Epsilon(10.0)), vec![0, 10, 20]);
+
+        let kept = simplify_indexes(&track, &Simplification::Epsilon(1.0));
+        assert!(kept.contains(&4) && kept.contains(&10));
+        assert!(!kept.contains(&15));
+    }
+
+    #[test]
+    fn max_points()
+    {
+        let track = corner();
+
+        // Furthest fixes first
+        assert_eq!(simplify_indexes(&track, &Simplification::MaxPoints(3)), vec![0, 10, 20]);
+        assert_eq!(simplify_indexes(&track, &Simplification::MaxPoints(4)), vec![0, 4, 10, 20]);
+        assert_eq!(simplify(&track, &Simplification::MaxPoints(4))[1].long, track[4].long);
+
+        // Fixes on the simplified track are never kept, even when more are allowed
+        assert!(simplify_indexes(&track, &Simplification::MaxPoints(100)).len() < track.len());
+
+        // Too short to simplify
+        assert_eq!(simplify_indexes(&track[..2], &Simplification::MaxPoints(1)), vec![0, 1]);
+    }
+
+    #[test]
+    fn from_string()
+    {
+        for mode in [Simplification::Epsilon(2.5), Simplification::MaxPoints(500)]
+        {
+            assert_eq!(mode.to_string().parse::<Simplification>().unwrap(), mode);
+        }
+        assert!("tolerance=3".parse::<Simplification>().is_err());
+    }
+}
@@ -0,0 +1,273 @@
+use chrono::NaiveDateTime;
+use geoutils::Location;
+
+use crate::logbook::FlightPoint;
+
+const MIN_TURN_RATE: f64 = 4.0;// deg/s
+const MIN_TURN_ANGLE: f64 = 180.0;// deg - a half turn starts a circling phase
+const MIN_THERMAL_ANGLE: f64 = 360.0;// deg
+const MERGE_GAP: i64 = 20;// s - turns closer than this belong to the same thermal
+const CLIMB_WINDOW: i64 = 10;// s - window for the max climb rate
+const MIN_MOVE: f64 = 1.0;// m - below this the heading can't be computed
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum TurnDirection {
+    Left,
+    Right,
+}
+
+#[derive(Clone,Debug)]
+pub struct Thermal {
+    pub entry: NaiveDateTime,
+    pub exit: NaiveDateTime,
+    pub entry_alt: u32,
+    pub exit_alt: u32,
+    pub alt_gain: i32,
+    pub avg_climb: f32,// m/s
+    pub max_climb: f32,// m/s
+    pub direction: TurnDirection,
+    pub lat: f32,
+    pub long: f32,
+}
+
+// Circling phase as indexes in the track and cumulative turn angle (deg, positive to the right)
+struct Turn {
+    start: usize,
+    end: usize,
+    angle: f64,
+}
+
+impl ToString for TurnDirection {
+    fn to_string(&self) -> String {
+        match self {
+            TurnDirection::Left => "left",
+            TurnDirection::Right => "right",
+        }.to_string()
+    }
+}
+
+pub fn detect(track: &Vec<FlightPoint>) -> Vec<Thermal>
+{
+    let mut thermals: Vec<Thermal> = Vec::new();
+    let mut current: Option<Turn> = None;
+
+    for turn in turns(track)
+    {
+        current = match current {
+            None => Some(turn),
+            Some(mut c) => {
+                if (track[turn.start].time - track[c.end].time).num_seconds() <= MERGE_GAP
+                {
+                    c.end = turn.end;
+                    c.angle += turn.angle;
+                    Some(c)
+                } else {
+                    if let Some(t) = thermal(track, &c) {
+                        thermals.push(t);
+                    }
+                    Some(turn)
+                }
+            },
+        };
+    }
+
+    if let Some(c) = current {
+        if let Some(t) = thermal(track, &c) {
+            thermals.push(t);
+        }
+    }
+
+    thermals
+}
+
+pub(super) fn heading(p1: &FlightPoint, p2: &FlightPoint) -> Option<f64>
+{
+    let loc1 = Location::new(p1.lat, p1.long);
+    let loc2 = Location::new(p2.lat, p2.long);
+
+    if loc1.haversine_distance_to(&loc2).meters() < MIN_MOVE
+    {
+        return None;
+    }
+
+    let lat1 = (p1.lat as f64).to_radians();
+    let lat2 = (p2.lat as f64).to_radians();
+    let dlong = ((p2.long - p1.long) as f64).to_radians();
+
+    let y = dlong.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * dlong.cos();
+
+    Some(y.atan2(x).to_degrees())
+}
+
+// Runs of fixes turning the same way fast enough
+fn turns(track: &Vec<FlightPoint>) -> Vec<Turn>
+{
+    let mut turns: Vec<Turn> = Vec::new();
+    let mut current: Option<Turn> = None;
+    let mut last_heading: Option<f64> = None;
+
+    for i in 1..track.len()
+    {
+        let h = match heading(&track[i - 1], &track[i]) {
+            Some(h) => h,
+            None => continue,
+        };
+
+        let prev = last_heading.replace(h);
+        let delta = match prev {
+            None => continue,
+            Some(prev) => (h - prev + 540.0) % 360.0 - 180.0,
+        };
+
+        let dt = (track[i].time - track[i - 1].time).num_seconds().max(1) as f64;
+        let rate = delta / dt;
+
+        let turning = rate.abs() >= MIN_TURN_RATE;
+        let same_way = current.as_ref().map(|c| c.angle.signum() == delta.signum()).unwrap_or(true);
+
+        if turning && same_way
+        {
+            match current.as_mut() {
+                None => current = Some(Turn { start: i - 1, end: i, angle: delta }),
+                Some(c) => {
+                    c.end = i;
+                    c.angle += delta;
+                },
+            }
+        } else {
+            if let Some(c) = current.take() {
+                if c.angle.abs() >= MIN_TURN_ANGLE {
+                    turns.push(c);
+                }
+            }
+
+            if turning {
+                current = Some(Turn { start: i - 1, end: i, angle: delta });
+            }
+        }
+    }
+
+    if let Some(c) = current {
+        if c.angle.abs() >= MIN_TURN_ANGLE {
+            turns.push(c);
+        }
+    }
+
+    turns
+}
+
+fn thermal(track: &Vec<FlightPoint>, turn: &Turn) -> Option<Thermal>
+{
+    if turn.angle.abs() < MIN_THERMAL_ANGLE
+    {
+        return None;
+    }
+
+    let points = &track[turn.start..=turn.end];
+    let entry = points.first()?;
+    let exit = points.last()?;
+
+    let duration = (exit.time - entry.time).num_seconds().max(1);
+    let alt_gain = exit.alt as i32 - entry.alt as i32;
+
+    let mut max_climb: f32 = alt_gain as f32 / duration as f32;
+    let mut j = 0;
+    for i in 0..points.len()
+    {
+        while j < points.len() - 1 && (points[j].time - points[i].time).num_seconds() < CLIMB_WINDOW
+        {
+            j += 1;
+        }
+
+        let dt = (points[j].time - points[i].time).num_seconds();
+        if dt >= CLIMB_WINDOW
+        {
+            let climb = (points[j].alt as i32 - points[i].alt as i32) as f32 / dt as f32;
+            max_climb = max_climb.max(climb);
+        }
+    }
+
+    let lat = points.iter().map(|p| p.lat as f64).sum::<f64>() / points.len() as f64;
+    let long = points.iter().map(|p| p.long as f64).sum::<f64>() / points.len() as f64;
+
+    Some(Thermal {
+        entry: entry.time,
+        exit: exit.time,
+        entry_alt: entry.alt,
+        exit_alt: exit.alt,
+        alt_gain,
+        avg_climb: alt_gain as f32 / duration as f32,
+        max_climb,
+        direction: if turn.angle > 0.0 { TurnDirection::Right } else { TurnDirection::Left },
+        lat: lat as f32,
+        long: long as f32,
+    })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use chrono::{Duration, NaiveDate};
+    use super::*;
+
+    const CENTRE: (f64, f64) = (45.0, 6.0);
+    const RADIUS: f64 = 100.0;// m
+    const STEP: f64 = 18.0;// deg/s - a turn in 20 s
+
+    // Fix at a distance east and north of the centre (m), one per second
+    fn fix(second: i64, east: f64, north: f64, alt: u32) -> FlightPoint
+    {
+        let time = NaiveDate::from_ymd_opt(2024, 8, 12).unwrap().and_hms_opt(12, 0, 0).unwrap() + Duration::seconds(second);
+        let lat = CENTRE.0 + north / 111195.0;
+        let long = CENTRE.1 + east / (111195.0 * CENTRE.0.to_radians().cos());
+
+        FlightPoint { time, lat: lat as f32, long: long as f32, alt, alt_gps: alt, alt_baro: alt }
+    }
+
+    // Left turns around the centre climbing at 2 m/s, starting east of it
+    fn circles(nb_fixes: i64) -> Vec<FlightPoint>
+    {
+        (0..nb_fixes)
+            .map(|k| {
+                let angle = (STEP * k as f64).to_radians();
+                fix(k, RADIUS * angle.cos(), RADIUS * angle.sin(), 1000 + 2 * k as u32)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn three_turns_then_a_glide()
+    {
+        // 3 turns, then a glide at 10 m/s and -1 m/s on the heading of the last chord
+        let mut track = circles(61);
+        let (heading, last) = (9f64.to_radians(), track.len() as i64 - 1);
+        for j in 1..=60
+        {
+            let d = 10.0 * j as f64;
+            track.push(fix(last + j, RADIUS + d * heading.sin(), d * heading.cos(), 1120 - j as u32));
+        }
+
+        let thermals = detect(&track);
+        assert_eq!(thermals.len(), 1);
+
+        // Turning is seen from the second chord
+        let t = &thermals[0];
+        assert_eq!((t.entry, t.exit), (track[1].time, track[60].time));
+        assert_eq!((t.entry_alt, t.exit_alt, t.alt_gain), (1002, 1120, 118));
+        assert!((t.avg_climb - 2.0).abs() < 1e-6);
+        assert!((t.max_climb - 2.0).abs() < 1e-6);
+        assert_eq!(t.direction, TurnDirection::Left);
+
+        // Fixes spread evenly over 3 turns, their mean is the centre
+        assert!((t.lat as f64 - CENTRE.0).abs() < 1e-5);
+        assert!((t.long as f64 - CENTRE.1).abs() < 1e-5);
+    }
+
+    #[test]
+    fn turn_that_does_not_close()
+    {
+        // 14 heading changes of 18°, a turn of 252°
+        assert!(detect(&circles(16)).is_empty());
+    }
+}
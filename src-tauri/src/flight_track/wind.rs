@@ -0,0 +1,264 @@
+use chrono::NaiveDateTime;
+
+use crate::logbook::FlightPoint;
+use super::thermal::{self, Thermal};
+
+const EARTH_RADIUS: f64 = 6371000.0;// m
+const MIN_FIT_POINTS: usize = 8;
+const MIN_AIRSPEED: f64 = 5.0;// m/s
+const MAX_AIRSPEED: f64 = 30.0;// m/s
+const MAX_FIT_ERROR: f64 = 1.5;// m/s - RMS distance of the velocities to the fitted circle
+
+// Wind estimated from one full turn
+//  speed     : m/s
+//  direction : deg, where the wind comes from
+//  airspeed  : mean airspeed during the turn (m/s)
+#[derive(Clone,Debug)]
+pub struct WindEstimate {
+    pub time: NaiveDateTime,
+    pub alt: u32,
+    pub speed: f32,
+    pub direction: f32,
+    pub airspeed: f32,
+}
+
+impl WindEstimate {
+    // East and north components of the air movement (m/s)
+    pub fn vector(&self) -> (f64, f64)
+    {
+        let to = (self.direction as f64 + 180.0).to_radians();
+
+        (self.speed as f64 * to.sin(), self.speed as f64 * to.cos())
+    }
+}
+
+// Ground speed circle method : while circling at constant airspeed the ground velocities lie on a circle
+// centred on the wind vector, with the airspeed as radius
+pub fn estimate(track: &Vec<FlightPoint>, thermals: &Vec<Thermal>) -> Vec<WindEstimate>
+{
+    let mut winds: Vec<WindEstimate> = Vec::new();
+
+    for t in thermals
+    {
+        let points: Vec<&FlightPoint> = track.iter().filter(|p| t.entry <= p.time && p.time <= t.exit).collect();
+        let mut turn: Vec<(f64, f64)> = Vec::new();
+        let mut turn_start = 0;
+        let mut angle = 0.0;
+        let mut last_heading: Option<f64> = None;
+
+        for i in 1..points.len()
+        {
+            let h = match thermal::heading(points[i - 1], points[i]) {
+                Some(h) => h,
+                None => continue,
+            };
+
+            turn.push(velocity(points[i - 1], points[i]));
+            if let Some(prev) = last_heading.replace(h) {
+                angle += (h - prev + 540.0) % 360.0 - 180.0;
+            }
+
+            if f64::abs(angle) >= 360.0
+            {
+                let middle = points[(turn_start + i) / 2];
+                if let Some(w) = fit(&turn, middle) {
+                    winds.push(w);
+                }
+
+                turn.clear();
+                turn_start = i;
+                angle = 0.0;
+            }
+        }
+    }
+
+    winds
+}
+
+// Ground velocity between two fixes, east and north components (m/s)
+pub fn velocity(p1: &FlightPoint, p2: &FlightPoint) -> (f64, f64)
+{
+    let dt = (p2.time - p1.time).num_seconds().max(1) as f64;
+    let lat = ((p1.lat + p2.lat) as f64 / 2.0).to_radians();
+
+    let east = ((p2.long - p1.long) as f64).to_radians() * lat.cos() * EARTH_RADIUS;
+    let north = ((p2.lat - p1.lat) as f64).to_radians() * EARTH_RADIUS;
+
+    (east / dt, north / dt)
+}
+
+// Least squares circle fit (Kasa) : x² + y² + Dx + Ey + F = 0
+fn fit(velocities: &Vec<(f64, f64)>, at: &FlightPoint) -> Option<WindEstimate>
+{
+    if velocities.len() < MIN_FIT_POINTS
+    {
+        return None;
+    }
+
+    let mut m = [[0.0f64; 4]; 3];
+    for (x, y) in velocities
+    {
+        let row = [*x, *y, 1.0];
+        let z = -(x * x + y * y);
+        for r in 0..3
+        {
+            for c in 0..3
+            {
+                m[r][c] += row[r] * row[c];
+            }
+            m[r][3] += row[r] * z;
+        }
+    }
+
+    let (d, e, f) = solve(m)?;
+    let (cx, cy) = (-d / 2.0, -e / 2.0);
+    let radius2 = cx * cx + cy * cy - f;
+
+    if radius2 <= 0.0
+    {
+        return None;
+    }
+
+    let airspeed = radius2.sqrt();
+    let speed = (cx * cx + cy * cy).sqrt();
+
+    // Turn entry or exit mixed with straight flight doesn't fit a circle
+    let error = velocities.iter()
+        .map(|(x, y)| ((x - cx).hypot(y - cy) - airspeed).powi(2))
+        .sum::<f64>() / velocities.len() as f64;
+    if error.sqrt() > MAX_FIT_ERROR
+    {
+        return None;
+    }
+
+    // A wind faster than the wing means the turn wasn't a circle at constant airspeed
+    if !(MIN_AIRSPEED..=MAX_AIRSPEED).contains(&airspeed) || speed >= airspeed
+    {
+        return None;
+    }
+
+    // Circle centre is the vector the wind blows along, direction is where it comes from
+    let direction = ((-cx).atan2(-cy).to_degrees() + 360.0) % 360.0;
+
+    Some(WindEstimate {
+        time: at.time,
+        alt: at.alt,
+        speed: speed as f32,
+        direction: direction as f32,
+        airspeed: airspeed as f32,
+    })
+}
+
+// Gaussian elimination of a 3x3 augmented matrix
+fn solve(mut m: [[f64; 4]; 3]) -> Option<(f64, f64, f64)>
+{
+    for c in 0..3
+    {
+        let pivot = (c..3).max_by(|&a, &b| m[a][c].abs().total_cmp(&m[b][c].abs()))?;
+        if m[pivot][c].abs() < 1e-9
+        {
+            return None;
+        }
+        m.swap(c, pivot);
+
+        for r in 0..3
+        {
+            if r != c
+            {
+                let factor = m[r][c] / m[c][c];
+                for k in c..4
+                {
+                    m[r][k] -= factor * m[c][k];
+                }
+            }
+        }
+    }
+
+    Some((m[0][3] / m[0][0], m[1][3] / m[1][1], m[2][3] / m[2][2]))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use chrono::{Duration, NaiveDate};
+    use super::*;
+    use crate::flight_track::thermal::TurnDirection;
+
+    // Near the equator the f32 coordinates are precise to the centimetre
+    const ORIGIN: (f64, f64) = (1.0, 1.0);
+
+    fn fix(second: i64, east: f64, north: f64) -> FlightPoint
+    {
+        let time = NaiveDate::from_ymd_opt(2024, 8, 12).unwrap().and_hms_opt(12, 0, 0).unwrap() + Duration::seconds(second);
+        let lat = ORIGIN.0 + (north / EARTH_RADIUS).to_degrees();
+        let long = ORIGIN.1 + (east / (EARTH_RADIUS * ORIGIN.0.to_radians().cos())).to_degrees();
+
+        FlightPoint { time, lat: lat as f32, long: long as f32, alt: 1500, alt_gps: 1500, alt_baro: 1500 }
+    }
+
+    fn thermal(track: &Vec<FlightPoint>) -> Thermal
+    {
+        let (entry, exit) = (&track[0], &track[track.len() - 1]);
+
+        Thermal {
+            entry: entry.time,
+            exit: exit.time,
+            entry_alt: entry.alt,
+            exit_alt: exit.alt,
+            alt_gain: 0,
+            avg_climb: 0.0,
+            max_climb: 0.0,
+            direction: TurnDirection::Left,
+            lat: entry.lat,
+            long: entry.long,
+        }
+    }
+
+    #[test]
+    fn drifting_circles()
+    {
+        // 3 left turns at 10 m/s in 20 s each, drifting east with a 3 m/s west wind
+        let rate = 18f64.to_radians();
+        let radius = 10.0 / rate;
+        let track: Vec<FlightPoint> = (0..=60)
+            .map(|s| {
+                let angle = rate * s as f64;
+                fix(s, 3.0 * s as f64 + radius * angle.cos(), radius * angle.sin())
+            })
+            .collect();
+
+        let winds = estimate(&track, &vec![thermal(&track)]);
+        assert!(winds.len() >= 2);
+
+        // Velocities between fixes are chords of the turn, a bit slower than the airspeed
+        let chord_speed = 10.0 * (rate / 2.0).sin() / (rate / 2.0);
+        for w in &winds
+        {
+            assert!((w.speed as f64 - 3.0).abs() < 0.05, "speed {}", w.speed);
+            assert!((w.direction as f64 - 270.0).abs() < 1.0, "direction {}", w.direction);
+            assert!((w.airspeed as f64 - chord_speed).abs() < 0.05, "airspeed {}", w.airspeed);
+
+            let (east, north) = w.vector();
+            assert!((east - 3.0).abs() < 0.05 && north.abs() < 0.05);
+        }
+
+        let (east, north) = velocity(&track[0], &track[1]);
+        assert!(((east - 3.0).hypot(north) - chord_speed).abs() < 0.01);
+    }
+
+    #[test]
+    fn no_circle()
+    {
+        // Straight flight at 10 m/s, same velocity everywhere
+        let straight: Vec<FlightPoint> = (0..=60).map(|s| fix(s, 10.0 * s as f64, 0.0)).collect();
+        assert!(estimate(&straight, &vec![thermal(&straight)]).is_empty());
+
+        // Velocities on a line or too few of them
+        let accelerating: Vec<(f64, f64)> = (0..10).map(|i| (5.0 + i as f64, 2.0 * i as f64)).collect();
+        assert!(fit(&accelerating, &straight[0]).is_none());
+        assert!(fit(&vec![(10.0, 0.0), (0.0, 10.0)], &straight[0]).is_none());
+
+        assert!(solve([[1.0, 2.0, 3.0, 1.0], [2.0, 4.0, 6.0, 2.0], [0.0, 1.0, 1.0, 3.0]]).is_none());
+        assert_eq!(solve([[2.0, 0.0, 0.0, 4.0], [0.0, 1.0, 0.0, 3.0], [0.0, 0.0, 4.0, 2.0]]), Some((2.0, 3.0, 0.5)));
+    }
+}
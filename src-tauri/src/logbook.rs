@@ -1,25 +1,35 @@
-use std::{path::Path, fs, process::{Command, Child, Stdio}, io::Write};
+use std::{path::Path, fs};
 use anyhow::{Result, bail};
-use chrono::NaiveDateTime;
-use serde_json::Value;
-use crate::flight_track::FlightTrack;
+use rusqlite::{Connection, Transaction};
+use serde::Serialize;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use crate::airspace::{self, Airspace};
+use crate::terrain::{self, Terrain};
+use crate::flight_track::{FlightTrack, FlightProfile, DetectionConfig, altitude::AltitudeSource, scoring::{self, ScoringRules}, igc_reader, igc_validator::{IgcValidator, IgcValidation}};
 
-use self::{flight_table::FlightTable, site_table::SiteTable, tag_table::TagTable, wing_table::WingTable};
+use self::{duplicate::{DuplicatePolicy, Fingerprint, SuspectedDuplicate}, import::{ImportReport, ImportedFile, ImportStatus}, query::{Condition, Changes}, flight_table::{FlightTable, FlightFilter, ManualFlight, SiteChoice}, glide_table::GlideTable, igc_table::IgcTable, score_table::ScoreTable, setting_table::{SettingTable, DEM_FOLDER, AIRSPACE_FILE}, site_table::SiteTable, tag_table::TagTable, thermal_table::ThermalTable, violation_table::ViolationTable, wind_table::{WindTable, WindLayer}, wing_table::WingTable};
 
+pub mod database;
+pub mod duplicate;
 pub mod flight_table;
+pub mod glide_table;
+pub mod igc_table;
+pub mod import;
+pub mod migration;
+pub mod query;
+pub mod score_table;
+pub mod setting_table;
 pub mod site_table;
 pub mod tag_table;
+pub mod thermal_table;
+pub mod violation_table;
+pub mod wind_table;
 pub mod wing_table;
 
-const DATABASE_PATH: &str = "./flight_database.db";
-const IGC_SCORER_PATH: &str = "./igc-xc-score.exe";
-const SCORE_MAX_TIME: &str = "maxtime=5";
-
-#[derive(Debug)]
-pub struct IDListe
-{
-    list: Vec<u32>,
-}
+pub const DATABASE_PATH: &str = "./flight_database.db";
+const TRACK_EXTENSIONS: [&str; 2] = ["igc", "gpx"];
+const MAX_MANUAL_DURATION: u32 = 24 * 60;// min
+const MAX_MANUAL_DISTANCE: u32 = 1_000_000;// m
 
 #[derive(Clone,Copy,Debug)]
 pub struct FlightPoint{
@@ -28,14 +38,48 @@ pub struct FlightPoint{
     pub long: f32,
     pub alt: u32,
     pub alt_gps: u32,
+    pub alt_baro: u32,// pressure altitude as recorded (standard QNH), 0 when the logger has none
 }
 
-#[derive(Debug)]
+#[derive(Debug,Serialize)]
 pub struct FlightStatistic {
     pub duration: u32,
     pub tot_distance: u32,
+    pub alt_max: u32,
     pub best_flight: Vec<FlightTable>,
     pub nb_flight: u32,
+    pub nb_without_track: u32,// flights entered by hand
+}
+
+// Flight that couldn't be scored again
+#[derive(Debug,Serialize)]
+pub struct ScoreFailure {
+    pub flight_id: u32,
+    pub reason: String,
+}
+
+// Results of the track analysis stored alongside a flight
+pub struct FlightAnalysis {
+    pub scores: Vec<ScoreTable>,
+    pub thermals: Vec<ThermalTable>,
+    pub glide: GlideTable,
+    pub wind: Vec<WindTable>,
+    pub violations: Vec<ViolationTable>,
+}
+
+// Flight read from a track file, its sites and wing are found when it is stored
+pub struct ParsedFlight {
+    pub flight: FlightTable,
+    pub analysis: FlightAnalysis,
+    pub takeoff: FlightPoint,
+    pub landing: FlightPoint,
+    pub glider_type: String,
+}
+
+pub struct ParsedFile {
+    pub raw_igc: String,
+    pub flights: Vec<ParsedFlight>,
+    pub warnings: Vec<String>,
 }
 
 pub struct Logbook;
@@ -48,13 +92,19 @@ impl Statistic for Vec<FlightTable> {
     fn statistic(&self) -> FlightStatistic {
         let mut duration: u32 = 0;
         let mut tot_distance: u32 = 0;
+        let mut alt_max: u32 = 0;
         let mut nb_flight: u32 = 0;
+        let mut nb_without_track: u32 = 0;
         let mut best_flight: Vec<FlightTable> = self.clone();
 
         for flight in self {
             duration += flight.duration;
             tot_distance += flight.distance;
+            alt_max = alt_max.max(flight.alt_max);
             nb_flight += 1;
+            if !flight.has_track {
+                nb_without_track += 1;
+            }
         }
 
         best_flight.sort_by(|a, b| b.distance.cmp(&a.distance));
@@ -68,170 +118,960 @@ impl Statistic for Vec<FlightTable> {
         FlightStatistic {
             duration,
             tot_distance,
+            alt_max,
             best_flight: best_flight[..index].to_vec(),
             nb_flight,
+            nb_without_track,
         }
     }
 }
 
-impl ToString for IDListe {
-    fn to_string(&self) -> String {       
-        let mut str_ids = "(".to_string();
+impl Logbook {
+    // Every track file of the folder imported in a single transaction, a file that fails is rolled back alone
+    pub fn load_and_store(db_conn: &mut Connection, path: &Path, policy: DuplicatePolicy) -> Result<ImportReport>
+    {
+        let paths: &mut Vec<String> = &mut Vec::new();
+        Self::search_igc(path, paths);
+
+        let mut tx = db_conn.transaction()?;
+
+        match Self::store_files(&mut tx, paths, policy) {
+            Ok(report) => {
+                tx.commit()?;
+                Ok(report)
+            },
+            Err(err) => {
+                tx.rollback()?;
+                Err(err)
+            },
+        }
+    }
+
+    fn store_files(tx: &mut Transaction, paths: &Vec<String>, policy: DuplicatePolicy) -> Result<ImportReport>
+    {
+        let (mut terrain, dem_warning) = Self::terrain(tx)?;
+        let airspaces = Self::airspaces(tx)?;
+        let mut report = ImportReport { job_id: 0, files: Vec::new(), cancelled: false };
 
-        for id in &self.list
+        for path in paths
         {
-            str_ids.push_str(format!("{},",id).as_str());
+            let mut parsed = match Self::read(Path::new(path)).and_then(|(raw_igc, tracks)| Self::parse(raw_igc, tracks, &mut terrain, &airspaces)) {
+                Ok(p) => p,
+                Err(err) => {
+                    report.files.push(ImportedFile::failed(path, &err));
+                    continue;
+                },
+            };
+            parsed.warnings.extend(dem_warning.clone());
+
+            let mut savepoint = tx.savepoint()?;
+
+            match Self::store(&savepoint, path, parsed, policy) {
+                Ok(file) => {
+                    savepoint.commit()?;
+                    report.files.push(file);
+                },
+                Err(err) => {
+                    savepoint.rollback()?;
+                    report.files.push(ImportedFile::failed(path, &err));
+                },
+            }
         }
 
-        str_ids.pop();//remove last ','
-        str_ids.push(')');      
+        Ok(report)
+    }
 
-        str_ids
+    // Raw file and the flights found in it
+    pub fn read(path: &Path) -> Result<(String,Vec<FlightTrack>)>
+    {
+        let raw_igc: String = fs::read_to_string(path)?;
+        let tracks = FlightTrack::split(&raw_igc, Self::fallback_date(path), &DetectionConfig::default())?;
+
+        Ok((raw_igc,tracks))
     }
-}
 
-impl Logbook {
-    pub fn create() -> Result<()>
+    // Scores and analysis of the flights, nothing is read from the database so files can be parsed in parallel
+    pub fn parse(raw_igc: String, tracks: Vec<FlightTrack>, terrain: &mut Option<Terrain>, airspaces: &Vec<Airspace>) -> Result<ParsedFile>
     {
-        FlightTable::create()?;
-        SiteTable::create()?;
-        TagTable::create()?;
-        WingTable::create()?;
+        let mut flights: Vec<ParsedFlight> = Vec::new();
+        let mut warnings: Vec<String> = Vec::new();
+
+        let validation = IgcValidator::validate(&raw_igc).to_string();
+        let nb_flight = tracks.len();
+
+        if nb_flight > 1
+        {
+            warnings.push(format!("{} flights found in file", nb_flight));
+        }
+
+        for mut t in tracks
+        {
+            if let Some(warning) = Self::ground(&mut t.profile, terrain) {
+                t.warnings.push(warning);
+            }
+
+            for warning in &t.warnings
+            {
+                if !warnings.contains(warning) {
+                    warnings.push(warning.clone());
+                }
+            }
+
+            let mut analysis = Self::analyse(&t)?;
+            // The CFD score is the one of the flight list
+            let (track,score,code) = match analysis.scores.iter().find(|s| s.rules == scoring::Cfd.id()) {
+                None => bail!("No CFD score"),
+                Some(s) => (s.track.clone().unwrap_or_default(), s.score, s.code.clone()),
+            };
+            let (violations, unchecked) = Self::airspace_check(&t, airspaces, terrain);
+            analysis.violations = violations;
+            if !analysis.violations.is_empty()
+            {
+                warnings.push(format!("{} airspace violations on {}", analysis.violations.len(), t.date.format("%Y-%m-%d")));
+            }
+            if !unchecked.is_empty()
+            {
+                warnings.push(format!("Airspaces with AGL limits not checked without DEM: {}", unchecked.join(", ")));
+            }
+
+            let glider_type = t.header.glider_type.clone();
+
+            flights.push(ParsedFlight {
+                flight: FlightTable { 
+                    flight_id: 0, 
+                    wing_id: 0, 
+                    takeoff_id: 0, 
+                    landing_id: 0, 
+                    hash: t.hash.clone(),
+                    date: t.date.format("%Y-%m-%d").to_string(), 
+                    duration: t.duration, 
+                    distance: t.distance, 
+                    score,
+                    code,
+                    track: Some(track), 
+                    raw_igc: None,
+                    profile: Some(t.profile.to_string()),
+                    header: Some(t.header),
+                    validation: validation.clone(),
+                    igc_id: 0,
+                    fix_start: t.fix_start as u32,
+                    fix_end: t.fix_end as u32,
+                    altitude_source: t.altitude_source.to_string(),
+                    alt_max: t.alt_max,
+                    takeoff_time: t.takeoff.time.timestamp(),
+                    landing_time: t.landing.time.timestamp(),
+                    has_track: true,
+                },
+                analysis,
+                takeoff: t.takeoff,
+                landing: t.landing,
+                glider_type,
+            });
+        }
+
+        if flights.is_empty()
+        {
+            bail!("No flight");
+        }
+
+        Ok(ParsedFile { raw_igc, flights, warnings })
+    }
+
+    // Flights of the file with their sites and wing, the policy tells what to do with the ones already in the logbook
+    pub fn store(db_conn: &Connection, path: &str, file: ParsedFile, policy: DuplicatePolicy) -> Result<ImportedFile>
+    {
+        let mut flights: Vec<ParsedFlight> = Vec::new();
+        let mut duplicates: Vec<u32> = Vec::new();
+
+        for parsed in file.flights
+        {
+            let found = Self::duplicates_of(db_conn, &parsed)?;
+
+            if policy == DuplicatePolicy::Replace
+            {
+                for flight_id in &found
+                {
+                    Self::remove_flight(db_conn, *flight_id)?;
+                }
+            }
+
+            if found.is_empty() || policy != DuplicatePolicy::Skip
+            {
+                flights.push(parsed);
+            }
+            duplicates.extend(found);
+        }
+
+        if flights.is_empty()
+        {
+            return Ok(ImportedFile { path: path.to_string(), status: ImportStatus::Duplicate, flight_ids: Vec::new(), duplicates, warnings: file.warnings, reason: None });
+        }
+
+        // The raw file is stored once and shared by all the flights found in it
+        IgcTable::store(db_conn, IgcTable { igc_id: 0, raw_igc: file.raw_igc })?;
+        let igc_id = IgcTable::last_igc_id(db_conn)?;
+        let mut flight_ids: Vec<u32> = Vec::new();
+
+        for parsed in flights
+        {
+            let mut flight = parsed.flight;
+            let sites = SiteTable::site_detection(db_conn, parsed.takeoff, parsed.landing)?;
+            let wing = match WingTable::match_wing(db_conn, &parsed.glider_type)? {
+                Some(w) => w,
+                None => WingTable::get_default_wing(db_conn)?,
+            };
+
+            flight.wing_id = wing.wing_id;
+            flight.takeoff_id = match sites.0 {
+                None => Self::unknown_site(db_conn, &parsed.takeoff)?,
+                Some(s) => s.site_id,
+            };
+            flight.landing_id = match sites.1 {
+                None => Self::unknown_site(db_conn, &parsed.landing)?,
+                Some(s) => s.site_id,
+            };
+            flight.igc_id = igc_id;
+            FlightTable::store(db_conn, flight)?;
+
+            let flight_id = FlightTable::last_flight_id(db_conn)?;
+            Self::store_analysis(db_conn, flight_id, parsed.analysis)?;
+            flight_ids.push(flight_id);
+        }
+
+        Ok(ImportedFile { path: path.to_string(), status: ImportStatus::Stored, flight_ids, duplicates, warnings: file.warnings, reason: None })
+    }
+
+    // Stored flights matching the parsed one
+    fn duplicates_of(db_conn: &Connection, parsed: &ParsedFlight) -> Result<Vec<u32>>
+    {
+        let flight = &parsed.flight;
+        let fingerprint = Fingerprint {
+            flight_id: 0,
+            hash: flight.hash.clone(),
+            date: flight.date.clone(),
+            takeoff_time: flight.takeoff_time,
+            landing_time: flight.landing_time,
+            lat: parsed.takeoff.lat,
+            long: parsed.takeoff.long,
+        };
+        let condition = Condition::all().any(vec![
+            Condition::all().eq("hash", flight.hash.clone()),
+            Condition::all().eq("date", flight.date.clone()),
+        ]);
+
+        Ok(Self::fingerprints(db_conn, &condition)?
+            .iter()
+            .filter(|f| fingerprint.matches(f).is_some())
+            .map(|f| f.flight_id)
+            .collect())
+    }
+
+    // Fingerprints of the stored flights, the ones stored before duplicate detection are computed from their IGC
+    fn fingerprints(db_conn: &Connection, condition: &Condition) -> Result<Vec<Fingerprint>>
+    {
+        let mut fingerprints = FlightTable::fingerprints(db_conn, condition)?;
+
+        for fingerprint in fingerprints.iter_mut().filter(|f| !f.has_track())
+        {
+            let t = match Self::stored_track(db_conn, fingerprint.flight_id) {
+                Ok(Some(t)) => t,
+                _ => continue,// Flight without track or IGC that can't be read anymore
+            };
+
+            fingerprint.hash = t.hash;
+            fingerprint.takeoff_time = t.takeoff.time.timestamp();
+            fingerprint.landing_time = t.landing.time.timestamp();
+            FlightTable::set_fingerprint(db_conn, fingerprint.flight_id, &fingerprint.hash, fingerprint.takeoff_time, fingerprint.landing_time)?;
+        }
+
+        Ok(fingerprints)
+    }
+
+    // Pairs of stored flights that look like the same flight
+    pub fn scan_duplicates(db_conn: &Connection) -> Result<Vec<SuspectedDuplicate>>
+    {
+        let fingerprints = Self::fingerprints(db_conn, &Condition::all().order_by("date, flight_id"))?;
+
+        Ok(duplicate::scan(&fingerprints))
+    }
+
+    fn unknown_site(db_conn: &Connection, point: &FlightPoint) -> Result<u32>
+    {
+        let site = SiteTable { 
+            site_id:0,
+            name: "Unkown".to_string(),
+            lat: point.lat,
+            long: point.long,
+            alt: point.alt,
+            info: "".to_string(),
+        };
+        SiteTable::store(db_conn, site)?;
+
+        SiteTable::last_site_id(db_conn)
+    }
+
+    fn analyse(t: &FlightTrack) -> Result<FlightAnalysis>
+    {
+        let mut scores: Vec<ScoreTable> = Vec::new();
+
+        for rules in scoring::rule_sets()
+        {
+            let (track,score,code) = Logbook::get_score(&t.track, rules.as_ref())?;
+            scores.push(ScoreTable {
+                score_id: 0,
+                flight_id: 0,
+                rules: rules.id().to_string(),
+                score,
+                code,
+                track: Some(track),
+            });
+        }
+
+        Ok(FlightAnalysis {
+            scores,
+            thermals: t.thermals.iter().map(ThermalTable::from).collect(),
+            glide: GlideTable::from(&t.glide),
+            wind: t.wind.iter().map(WindTable::from).collect(),
+            violations: Vec::new(),
+        })
+    }
+
+    fn store_analysis(db_conn: &Connection, flight_id: u32, analysis: FlightAnalysis) -> Result<()>
+    {
+        for mut score in analysis.scores
+        {
+            score.flight_id = flight_id;
+            ScoreTable::store(db_conn, score)?;
+        }
+
+        for mut thermal in analysis.thermals
+        {
+            thermal.flight_id = flight_id;
+            ThermalTable::store(db_conn, thermal)?;
+        }
+
+        let mut glide = analysis.glide;
+        glide.flight_id = flight_id;
+        GlideTable::store(db_conn, glide)?;
+
+        for mut wind in analysis.wind
+        {
+            wind.flight_id = flight_id;
+            WindTable::store(db_conn, wind)?;
+        }
+
+        for mut violation in analysis.violations
+        {
+            violation.flight_id = flight_id;
+            ViolationTable::store(db_conn, violation)?;
+        }
+
+        FlightTable::set_analysed(db_conn, flight_id)
+    }
+
+    // Track of a stored flight, None for flights without IGC
+    pub fn stored_track(db_conn: &Connection, flight_id: u32) -> Result<Option<FlightTrack>>
+    {
+        let flight = FlightTable::get(db_conn, flight_id)?;
+        let raw_igc = match FlightTable::get_igc(db_conn, flight_id)? {
+            None => return Ok(None),
+            Some(i) => i,
+        };
+        let date = NaiveDate::parse_from_str(&flight.date, "%Y-%m-%d").ok();
+        let source: Option<AltitudeSource> = flight.altitude_source.parse().ok();
+
+        // Flights stored before multi-flight files have no fix range
+        if flight.fix_end > 0
+        {
+            return Ok(Some(FlightTrack::from_range(&raw_igc, date, source, flight.fix_start as usize, flight.fix_end as usize, &DetectionConfig::default())?));
+        }
+
+        Ok(Some(FlightTrack::with_config(&raw_igc, date, source, &DetectionConfig::default())?))
+    }
+
+    // Altitude source chosen for the flight, everything computed from the altitude is redone. Returns the warnings.
+    pub fn set_altitude_source(db_conn: &Connection, flight_id: u32, source: AltitudeSource) -> Result<Vec<String>>
+    {
+        FlightTable::set_altitude_source(db_conn, flight_id, &source)?;
+
+        let mut t = match Self::stored_track(db_conn, flight_id)? {
+            None => return Ok(Vec::new()),
+            Some(t) => t,
+        };
+        let (mut terrain, dem_warning) = Self::terrain(db_conn)?;
+        let mut warnings: Vec<String> = dem_warning.into_iter().collect();
+        warnings.extend(Self::ground(&mut t.profile, &mut terrain));
+        FlightTable::set_profile(db_conn, flight_id, &t.profile.to_string(), t.alt_max)?;
+
+        let mut analysis = Self::analyse(&t)?;
+        analysis.violations = Self::airspace_check(&t, &Self::airspaces(db_conn)?, &mut terrain).0;
+
+        ThermalTable::delete(db_conn, &Condition::all().eq("flight_id", flight_id))?;
+        WindTable::delete(db_conn, &Condition::all().eq("flight_id", flight_id))?;
+        ViolationTable::delete(db_conn, &Condition::all().eq("flight_id", flight_id))?;
+        Self::store_analysis(db_conn, flight_id, analysis)?;
+
+        Ok(warnings)
+    }
+
+    // DEM of the folder chosen by the user, None when there is none
+    pub fn terrain(db_conn: &Connection) -> Result<(Option<Terrain>, Option<String>)>
+    {
+        Ok(Self::open_terrain(SettingTable::get(db_conn, DEM_FOLDER)?))
+    }
+
+    // A folder that can't be read anymore (unplugged drive, renamed folder) doesn't stop the caller,
+    // it gets a warning to tell the user why the ground altitudes are missing
+    pub fn open_terrain(folder: Option<String>) -> (Option<Terrain>, Option<String>)
+    {
+        let folder = match folder {
+            None => return (None, None),
+            Some(f) => f,
+        };
+
+        match Terrain::open(Path::new(&folder)) {
+            Ok(t) => (Some(t), None),
+            Err(err) => (None, Some(format!("DEM folder {} can't be read, ground altitudes left out: {}", folder, err))),
+        }
+    }
+
+    // Ground and height above ground of the profile, with a warning when the altitude can't be right
+    fn ground(profile: &mut FlightProfile, terrain: &mut Option<Terrain>) -> Option<String>
+    {
+        let terrain = terrain.as_mut()?;
+        terrain.ground_profile(profile);
+
+        match terrain::impossible_altitudes(profile) {
+            0 => None,
+            n => Some(format!("{} points of the profile are below the ground or too high, check the altitude source", n)),
+        }
+    }
+
+    // The ground of every stored profile is recomputed with the new DEM
+    pub fn set_dem_folder(db_conn: &Connection, folder: &Path) -> Result<()>
+    {
+        let mut terrain = Some(Terrain::open(folder)?);
+
+        match folder.to_str() {
+            None => bail!("Invalid DEM folder {}", folder.display()),
+            Some(f) => SettingTable::set(db_conn, DEM_FOLDER, f)?,
+        }
+
+        for flight in FlightTable::select_all(db_conn)?
+        {
+            let stored = FlightTable::get(db_conn, flight.flight_id)?;
+            let mut profile: FlightProfile = match stored.profile.as_deref().map(|p| p.parse()) {
+                Some(Ok(p)) => p,
+                _ => continue,
+            };
+
+            Self::ground(&mut profile, &mut terrain);
+            FlightTable::set_profile(db_conn, flight.flight_id, &profile.to_string(), stored.alt_max)?;
+        }
 
         Ok(())
     }
 
-    pub fn load_and_store(path: &Path) -> Result<()>
+    // Site entered by hand, the altitude comes from the DEM when not given. Returns the site id and
+    // a warning when the DEM can't be read.
+    pub fn add_site(db_conn: &Connection, mut site: SiteTable) -> Result<(u32, Option<String>)>
     {
-        let paths: &mut Vec<String> = &mut Vec::new();
-        Self::search_igc(path, paths);
+        let mut warning: Option<String> = None;
 
-        for path in paths 
+        if site.alt == 0
         {
+            let (terrain, dem_warning) = Self::terrain(db_conn)?;
+            if let Some(alt) = terrain.and_then(|mut t| t.elevation(site.lat, site.long)) {
+                site.alt = alt.max(0) as u32;
+            }
+            warning = dem_warning;
+        }
+
+        SiteTable::store(db_conn, site)?;
 
-            let (mut flight,scorer) = Logbook::load(Path::new(path))?;
+        Ok((SiteTable::last_site_id(db_conn)?, warning))
+    }
 
-            let (track,score,code) = Logbook::get_score(scorer)?;
+    pub fn update_site(db_conn: &Connection, site: &SiteTable) -> Result<()>
+    {
+        SiteTable::get(db_conn, site.site_id)?;
 
-            flight.track = Some(track.into());
-            flight.score = score;
-            flight.code = code;
+        SiteTable::update(db_conn, &Changes::new()
+            .set("name", site.name.clone())
+            .set("lat", site.lat)
+            .set("long", site.long)
+            .set("alt", site.alt)
+            .set("info", site.info.clone()),
+            &Condition::all().eq("site_id", site.site_id))
+    }
 
-            FlightTable::store(flight)?;
+    // Sites still used as takeoff or landing are kept
+    pub fn delete_site(db_conn: &Connection, site_id: u32) -> Result<()>
+    {
+        let nb_flight = FlightTable::get_by_site(db_conn, &[site_id])?.len();
+        if nb_flight > 0
+        {
+            bail!("Site used by {} flights", nb_flight);
+        }
+
+        SiteTable::delete(db_conn, &Condition::all().eq("site_id", site_id))
+    }
+
+    pub fn add_wing(db_conn: &Connection, wing: WingTable) -> Result<u32>
+    {
+        if wing.name.trim().is_empty()
+        {
+            bail!("Wing name is empty");
+        }
+
+        WingTable::store(db_conn, wing)?;
+
+        WingTable::last_wing_id(db_conn)
+    }
+
+    pub fn update_wing(db_conn: &Connection, wing: &WingTable) -> Result<()>
+    {
+        WingTable::get(db_conn, wing.wing_id)?;
+
+        WingTable::update(db_conn, &Changes::new()
+            .set("name", wing.name.clone())
+            .set("info", wing.info.clone()),
+            &Condition::all().eq("wing_id", wing.wing_id))?;
+
+        if wing.def
+        {
+            WingTable::set_default_wing(db_conn, Some(wing.wing_id as i32), None)?;
         }
 
         Ok(())
     }
 
-    pub fn load(path: &Path) -> Result<(FlightTable,Child)>
+    // The default wing and the wings of stored flights are kept
+    pub fn delete_wing(db_conn: &Connection, wing_id: u32) -> Result<()>
     {
-        let raw_igc: String = fs::read_to_string(path)?;
-        let flight: Option<FlightTable>;
-
-        let scorer = Logbook::score(&raw_igc)?;
-
-        match FlightTrack::new(&raw_igc)
-        {
-            Ok(t) => {
-                let sites = SiteTable::site_detection(t.takeoff, t.landing)?;
-                let wing = WingTable::get_default_wing()?;
-
-                let takeoff = match sites.0 {
-                    None => 
-                    {
-                        let site = SiteTable { 
-                            site_id:0,
-                            name: "Unkown".to_string(),
-                            lat: t.takeoff.lat,
-                            long: t.takeoff.long,
-                            alt: t.takeoff.alt,
-                            info: "".to_string(),
-                        };
-                        SiteTable::store(site)?;
-        
-                        Some(SiteTable::last_site_id()?)
-                    },
-                    Some(s) => Some(s.site_id),
-                };
-
-                let landing = match sites.1 {
-                    None => 
-                    {
-                        let site = SiteTable { 
-                            site_id:0,
-                            name: "Unkown".to_string(),
-                            lat: t.landing.lat,
-                            long: t.landing.long,
-                            alt: t.landing.alt,
-                            info: "".to_string(),
-                        };
-                        SiteTable::store(site)?;
-        
-                        Some(SiteTable::last_site_id()?)
-                    },
-                    Some(s) => Some(s.site_id),
-                };
-
-                flight = Some(FlightTable { 
-                                    flight_id: 0, 
-                                    wing_id: wing.wing_id, 
-                                    takeoff_id: takeoff.unwrap_or(0), 
-                                    landing_id: landing.unwrap_or(0), 
-                                    hash: t.hash, 
-                                    date: t.date.format("%Y-%m-%d").to_string(), 
-                                    duration: t.duration, 
-                                    distance: t.distance, 
-                                    score: 0,
-                                    code: "".to_string(),
-                                    track: None, 
-                                    raw_igc: Some(raw_igc),
-                                    profile: Some(t.profile.to_string()),
-                                });
+        if WingTable::get(db_conn, wing_id)?.def
+        {
+            bail!("The default wing can't be deleted");
+        }
+
+        let nb_flight = FlightTable::filter(db_conn, &FlightFilter { wing_ids: vec![wing_id], ..Default::default() })?.len();
+        if nb_flight > 0
+        {
+            bail!("Wing used by {} flights", nb_flight);
+        }
+
+        WingTable::delete(db_conn, &Condition::all().eq("wing_id", wing_id))
+    }
+
+    pub fn add_tag(db_conn: &Connection, name: &str) -> Result<u32>
+    {
+        if name.trim().is_empty()
+        {
+            bail!("Tag name is empty");
+        }
+
+        TagTable::store(db_conn, TagTable { tag_id: 0, name: name.trim().to_string() })?;
+
+        TagTable::last_tag_id(db_conn)
+    }
+
+    pub fn update_tag(db_conn: &Connection, tag: &TagTable) -> Result<()>
+    {
+        if tag.name.trim().is_empty()
+        {
+            bail!("Tag name is empty");
+        }
+
+        TagTable::get(db_conn, tag.tag_id)?;
+
+        TagTable::update(db_conn, &Changes::new().set("name", tag.name.trim().to_string()), &Condition::all().eq("tag_id", tag.tag_id))
+    }
+
+    // The tag is removed from its flights too
+    pub fn delete_tag(db_conn: &mut Connection, tag_id: u32) -> Result<()>
+    {
+        let tx = db_conn.transaction()?;
+
+        TagTable::dissociate(&tx, &Condition::all().eq("asso_tag_id", tag_id))?;
+        TagTable::delete(&tx, &Condition::all().eq("tag_id", tag_id))?;
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    // Details of a flight corrected by the user, the track analysis is kept
+    pub fn update_flight(db_conn: &Connection, flight: &FlightTable) -> Result<()>
+    {
+        if NaiveDate::parse_from_str(&flight.date, "%Y-%m-%d").is_err()
+        {
+            bail!("Invalid date {}", flight.date);
+        }
+
+        FlightTable::get(db_conn, flight.flight_id)?;
+
+        FlightTable::update(db_conn, &Changes::new()
+            .set("wing_id", flight.wing_id)
+            .set("takeoff_id", flight.takeoff_id)
+            .set("landing_id", flight.landing_id)
+            .set("date", flight.date.clone())
+            .set("duration", flight.duration)
+            .set("distance", flight.distance),
+            &Condition::all().eq("flight_id", flight.flight_id))
+    }
+
+    // Flight without track, its sites are created with it when they are new. Returns the flight id and the warnings.
+    pub fn add_manual_flight(db_conn: &mut Connection, manual: ManualFlight) -> Result<(u32, Vec<String>)>
+    {
+        let date = match NaiveDate::parse_from_str(&manual.date, "%Y-%m-%d") {
+            Ok(d) => d,
+            Err(_) => bail!("Invalid date {}", manual.date),
+        };
+
+        if date > Utc::now().date_naive()
+        {
+            bail!("Date {} is in the future", manual.date);
+        }
+        if manual.duration == 0 || manual.duration > MAX_MANUAL_DURATION
+        {
+            bail!("Invalid duration {} min", manual.duration);
+        }
+        if manual.distance > MAX_MANUAL_DISTANCE
+        {
+            bail!("Invalid distance {} m", manual.distance);
+        }
 
+        let tx = db_conn.transaction()?;
+
+        let wing_id = match manual.wing_id {
+            Some(id) => WingTable::get(&tx, id)?.wing_id,
+            None => WingTable::get_default_wing(&tx)?.wing_id,
+        };
+        let (takeoff_id, mut warnings) = Self::choose_site(&tx, manual.takeoff)?;
+        let landing_id = match manual.landing {
+            None => takeoff_id,
+            Some(s) => {
+                let (site_id, landing_warnings) = Self::choose_site(&tx, s)?;
+                for warning in landing_warnings
+                {
+                    if !warnings.contains(&warning) {
+                        warnings.push(warning);
+                    }
+                }
+                site_id
             },
-            Err(e) => {
-                bail!(e.to_string());
+        };
+
+        FlightTable::store(&tx, FlightTable {
+            flight_id: 0,
+            wing_id,
+            takeoff_id,
+            landing_id,
+            hash: "".to_string(),
+            date: manual.date,
+            duration: manual.duration,
+            distance: manual.distance,
+            score: 0,
+            code: "".to_string(),
+            track: None,
+            raw_igc: None,
+            profile: None,
+            header: None,
+            validation: IgcValidation::Unsigned.to_string(),
+            igc_id: 0,
+            fix_start: 0,
+            fix_end: 0,
+            altitude_source: "".to_string(),
+            alt_max: 0,
+            takeoff_time: 0,
+            landing_time: 0,
+            has_track: false,
+        })?;
+        let flight_id = FlightTable::last_flight_id(&tx)?;
+
+        tx.commit()?;
+
+        Ok((flight_id, warnings))
+    }
+
+    fn choose_site(db_conn: &Connection, choice: SiteChoice) -> Result<(u32, Vec<String>)>
+    {
+        match choice {
+            SiteChoice::Existing(site_id) => Ok((SiteTable::get(db_conn, site_id)?.site_id, Vec::new())),
+            SiteChoice::New(site) => {
+                if site.name.trim().is_empty()
+                {
+                    bail!("Site name is empty");
+                }
+                if !(-90.0..=90.0).contains(&site.lat) || !(-180.0..=180.0).contains(&site.long)
+                {
+                    bail!("Invalid site position {} {}", site.lat, site.long);
+                }
+
+                let (site_id, warning) = Self::add_site(db_conn, site)?;
+                Ok((site_id, warning.into_iter().collect()))
             },
         }
+    }
+
+    pub fn delete_flight(db_conn: &mut Connection, flight_id: u32) -> Result<()>
+    {
+        let tx = db_conn.transaction()?;
+
+        Self::remove_flight(&tx, flight_id)?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    // Flight with its analysis and tags, the raw file goes with the last flight found in it
+    fn remove_flight(db_conn: &Connection, flight_id: u32) -> Result<()>
+    {
+        let flight = FlightTable::get(db_conn, flight_id)?;
+
+        TagTable::dissociate(db_conn, &Condition::all().eq("asso_flight_id", flight_id))?;
+        FlightTable::delete(db_conn, &Condition::all().eq("flight_id", flight_id))?;
+
+        if flight.igc_id > 0 && FlightTable::select(db_conn, &Condition::all().eq("igc_id", flight.igc_id))?.is_empty()
+        {
+            IgcTable::delete(db_conn, &Condition::all().eq("igc_id", flight.igc_id))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn statistic(db_conn: &Connection, filter: &FlightFilter) -> Result<FlightStatistic>
+    {
+        Ok(FlightTable::filter(db_conn, filter)?.statistic())
+    }
+
+    // Airspaces of the file chosen by the user, none when there is none
+    pub fn airspaces(db_conn: &Connection) -> Result<Vec<Airspace>>
+    {
+        match SettingTable::get(db_conn, AIRSPACE_FILE)? {
+            None => Ok(Vec::new()),
+            Some(file) => airspace::load(Path::new(&file)),
+        }
+    }
+
+    pub fn set_airspace_file(db_conn: &Connection, path: &Path) -> Result<()>
+    {
+        airspace::load(path)?;
+
+        match path.to_str() {
+            None => bail!("Invalid airspace file {}", path.display()),
+            Some(p) => SettingTable::set(db_conn, AIRSPACE_FILE, p),
+        }
+    }
+
+    // Violations and the airspaces that couldn't be checked
+    fn airspace_check(t: &FlightTrack, airspaces: &Vec<Airspace>, terrain: &mut Option<Terrain>) -> (Vec<ViolationTable>, Vec<String>)
+    {
+        let (violations, unchecked) = airspace::check(&t.track, airspaces, terrain);
+
+        (violations.iter().map(ViolationTable::from).collect(), unchecked)
+    }
+
+    // Every stored flight is checked again against the current airspace file, returns the number of violations
+    // and the warnings
+    pub fn check_airspace(db_conn: &Connection) -> Result<(u32, Vec<String>)>
+    {
+        let airspaces = Self::airspaces(db_conn)?;
+        let (mut terrain, dem_warning) = Self::terrain(db_conn)?;
+        let mut warnings: Vec<String> = dem_warning.into_iter().collect();
+        let mut nb_violation: u32 = 0;
+
+        if airspaces.is_empty()
+        {
+            bail!("No airspace file");
+        }
+
+        for flight in FlightTable::select_all(db_conn)?
+        {
+            let t = match Self::stored_track(db_conn, flight.flight_id)? {
+                None => continue,// Flight without track can't be checked
+                Some(t) => t,
+            };
+
+            ViolationTable::delete(db_conn, &Condition::all().eq("flight_id", flight.flight_id))?;
+            let (violations, unchecked) = Self::airspace_check(&t, &airspaces, &mut terrain);
+            for mut violation in violations
+            {
+                violation.flight_id = flight.flight_id;
+                ViolationTable::store(db_conn, violation)?;
+                nb_violation += 1;
+            }
 
-        match flight {
-            Some(f) => Ok((f,scorer)),
-            None => bail!("No flight"),
+            for name in unchecked
+            {
+                let warning = format!("Airspace with AGL limits not checked without DEM: {}", name);
+                if !warnings.contains(&warning) {
+                    warnings.push(warning);
+                }
+            }
         }
+
+        Ok((nb_violation, warnings))
     }
 
-    pub fn get_score(scorer: Child) -> Result<(String,u32,String)>
+    pub fn violations(db_conn: &Connection, flight_id: u32) -> Result<Vec<ViolationTable>>
     {
-        let output = scorer.wait_with_output()?;
-        let geojson: Value = serde_json::from_slice(&output.stdout)?;
+        ViolationTable::get_by_flight(db_conn, flight_id)
+    }
+
+    pub fn get_score(track: &Vec<FlightPoint>, rules: &dyn ScoringRules) -> Result<(String,u32,String)>
+    {
+        let flight_score = scoring::score(track, rules)?;
+        let geojson = flight_score.geojson;
 
         let code = geojson["properties"]["code"].to_string();
-        let score = geojson["properties"]["score"].as_f64().unwrap();
+        let score = flight_score.score;
 
         Ok((geojson.to_string(),(score*1000.0) as u32, code))
     }
 
-    fn score(raw_igc: &String) -> Result<Child>
+    // Every flight scored again under the rules, the flights that fail are reported and the others go on
+    pub fn rescore(db_conn: &Connection, rules: &dyn ScoringRules) -> Result<Vec<ScoreFailure>>
+    {
+        let mut failures: Vec<ScoreFailure> = Vec::new();
+
+        for flight in FlightTable::select_all(db_conn)?
+        {
+            if let Err(err) = Self::rescore_flight(db_conn, flight.flight_id, rules) {
+                failures.push(ScoreFailure { flight_id: flight.flight_id, reason: err.to_string() });
+            }
+        }
+
+        Ok(failures)
+    }
+
+    fn rescore_flight(db_conn: &Connection, flight_id: u32, rules: &dyn ScoringRules) -> Result<()>
+    {
+        let t = match Self::stored_track(db_conn, flight_id)? {
+            None => return Ok(()),// Flight without track can't be scored
+            Some(t) => t,
+        };
+        let (track,score,code) = Logbook::get_score(&t.track, rules)?;
+
+        ScoreTable::store(db_conn, ScoreTable {
+            score_id: 0,
+            flight_id,
+            rules: rules.id().to_string(),
+            score,
+            code,
+            track: Some(track),
+        })
+    }
+
+    pub fn validation(db_conn: &Connection, flight_id: u32) -> Result<IgcValidation>
     {
-        let mut igc_scorer = Command::new(IGC_SCORER_PATH)
-            .arg("pipe=true")
-            .arg("quiet=true")
-            .arg(SCORE_MAX_TIME)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .spawn()?;
+        let flight = FlightTable::get(db_conn, flight_id)?;
 
-        let temp = raw_igc.clone();
+        if !flight.validation.is_empty()
+        {
+            return Ok(IgcValidation::from(flight.validation.as_str()));
+        }
+
+        // Flight stored before validation existed
+        let validation = match FlightTable::get_igc(db_conn, flight_id)? {
+            None => IgcValidation::Unsigned,
+            Some(raw_igc) => IgcValidator::validate(&raw_igc),
+        };
+        FlightTable::set_validation(db_conn, flight_id, &validation)?;
+
+        Ok(validation)
+    }
+
+    // Thermals, glide and wind of a flight stored before they were computed at import, computed once.
+    // The flight is marked so that a flight without thermal or wind isn't parsed again on every read.
+    fn backfill_analysis(db_conn: &Connection, flight_id: u32) -> Result<()>
+    {
+        if FlightTable::is_analysed(db_conn, flight_id)?
+        {
+            return Ok(());
+        }
+
+        if let Some(t) = Self::stored_track(db_conn, flight_id)?
+        {
+            // Part of them may come from an older backfill
+            ThermalTable::delete(db_conn, &Condition::all().eq("flight_id", flight_id))?;
+            WindTable::delete(db_conn, &Condition::all().eq("flight_id", flight_id))?;
+
+            for thermal in &t.thermals
+            {
+                let mut thermal = ThermalTable::from(thermal);
+                thermal.flight_id = flight_id;
+                ThermalTable::store(db_conn, thermal)?;
+            }
+
+            let mut glide = GlideTable::from(&t.glide);
+            glide.flight_id = flight_id;
+            GlideTable::store(db_conn, glide)?;
+
+            for wind in &t.wind
+            {
+                let mut wind = WindTable::from(wind);
+                wind.flight_id = flight_id;
+                WindTable::store(db_conn, wind)?;
+            }
+        }
+
+        FlightTable::set_analysed(db_conn, flight_id)
+    }
+
+    pub fn thermals(db_conn: &Connection, flight_id: u32) -> Result<Vec<ThermalTable>>
+    {
+        Self::backfill_analysis(db_conn, flight_id)?;
 
-        let mut stdin = igc_scorer.stdin.take().expect("Failed to open stdin");
-        std::thread::spawn(move || {
-                stdin.write_all(temp.as_bytes()).expect("failed to write to stdin");
-        });
+        ThermalTable::get_by_flight(db_conn, flight_id)
+    }
+
+    pub fn glide(db_conn: &Connection, flight_id: u32) -> Result<Option<GlideTable>>
+    {
+        Self::backfill_analysis(db_conn, flight_id)?;
+
+        GlideTable::get(db_conn, flight_id)
+    }
+
+    // Glide summaries of every flight made with the same wing as this one
+    pub fn glide_comparison(db_conn: &Connection, flight_id: u32) -> Result<Vec<GlideTable>>
+    {
+        let flight = FlightTable::get(db_conn, flight_id)?;
+
+        GlideTable::get_by_wing(db_conn, flight.wing_id)
+    }
+
+    pub fn wind(db_conn: &Connection, flight_id: u32) -> Result<Vec<WindTable>>
+    {
+        Self::backfill_analysis(db_conn, flight_id)?;
 
-        Ok(igc_scorer)
+        WindTable::get_by_flight(db_conn, flight_id)
+    }
+
+    pub fn wind_by_altitude(db_conn: &Connection, flight_id: u32) -> Result<Vec<WindLayer>>
+    {
+        Ok(WindTable::layers(&Self::wind(db_conn, flight_id)?))
+    }
+
+    // Date to use when the IGC header has none : from the file name, else from the file modification date
+    fn fallback_date(path: &Path) -> Option<NaiveDate>
+    {
+        let from_name = path.file_name()
+            .and_then(|n| n.to_str())
+            .and_then(igc_reader::date_from_file_name);
+
+        if from_name.is_some()
+        {
+            return from_name;
+        }
+
+        match fs::metadata(path).and_then(|md| md.modified()) {
+            Ok(t) => Some(DateTime::<Utc>::from(t).date_naive()),
+            Err(_) => None,
+        }
     }
 
-    fn search_igc(path: &Path, output: &mut Vec<String>)
+    pub fn search_igc(path: &Path, output: &mut Vec<String>)
     {
         if match fs::metadata(path) {
             Ok(md) => md.is_dir(),
@@ -250,7 +1090,10 @@ impl Logbook {
         } else if match fs::metadata(path) {
             Ok(md) => md.is_file(),
             Err(_) => false,
-        } && path.extension().map(|s| s == "igc").unwrap_or(false)
+        } && path.extension()
+            .and_then(|s| s.to_str())
+            .map(|s| TRACK_EXTENSIONS.contains(&s.to_lowercase().as_str()))
+            .unwrap_or(false)
         {
             match path.to_str() {
                 Some(s) => output.push(s.to_string()),
@@ -0,0 +1,48 @@
+use std::{sync::{Arc, Mutex, MutexGuard}, time::Duration};
+use rusqlite::Connection;
+use anyhow::{Result, anyhow};
+
+use super::migration;
+
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Connection shared by all the commands, managed as Tauri state.
+// Clones share the connection so background jobs can use it too. Commands wait for each other on the mutex,
+// so writers keep it for short transactions : the import job parses outside of it and stores one file at a time.
+#[derive(Clone)]
+pub struct Database
+{
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl Database
+{
+    // Database brought to the latest schema then opened once for the whole session
+    pub fn open(path: &str) -> Result<Database>
+    {
+        migration::migrate(path)?;
+
+        let db_conn = Connection::open(path)?;
+        // WAL lets other processes (backups, database viewers) read while the application writes
+        db_conn.pragma_update(None, "journal_mode", "WAL")?;
+        db_conn.pragma_update(None, "foreign_keys", true)?;
+        db_conn.busy_timeout(BUSY_TIMEOUT)?;
+
+        Ok(Database { conn: Arc::new(Mutex::new(db_conn)) })
+    }
+
+    // Database of the tests, gone with the last clone
+    #[cfg(test)]
+    pub fn in_memory() -> Result<Database>
+    {
+        let mut db_conn = Connection::open_in_memory()?;
+        migration::upgrade(&mut db_conn, None)?;
+
+        Ok(Database { conn: Arc::new(Mutex::new(db_conn)) })
+    }
+
+    pub fn connection(&self) -> Result<MutexGuard<'_, Connection>>
+    {
+        self.conn.lock().map_err(|_| anyhow!("Database connection poisoned"))
+    }
+}
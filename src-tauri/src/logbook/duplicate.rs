@@ -0,0 +1,144 @@
+use geoutils::Location;
+use serde::{Serialize, Deserialize};
+
+// Two devices recording the same flight detect the takeoff a few fixes apart,
+// the stored flights only keep the position of their takeoff site
+const TAKEOFF_RADIUS: f64 = 1000.0;// m
+
+// What to do with a flight already in the logbook
+#[derive(Clone,Copy,Debug,Default,PartialEq,Serialize,Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicatePolicy {
+    #[default]
+    Skip,
+    Replace,// the flights of the logbook are deleted
+    KeepBoth,
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateReason {
+    SameFixes,// same content hash
+    SameTime,// same day, overlapping flights from the same takeoff
+}
+
+// Pair of stored flights that look like the same flight
+#[derive(Clone,Debug,Serialize)]
+pub struct SuspectedDuplicate {
+    pub flight_ids: (u32,u32),
+    pub reason: DuplicateReason,
+}
+
+// What tells two flights apart, flights without track have no hash and no times
+#[derive(Clone,Debug)]
+pub struct Fingerprint {
+    pub flight_id: u32,
+    pub hash: String,
+    pub date: String,
+    pub takeoff_time: i64,// s since epoch, 0 when unknown
+    pub landing_time: i64,
+    pub lat: f32,// takeoff
+    pub long: f32,
+}
+
+impl Fingerprint {
+    pub fn has_track(&self) -> bool
+    {
+        !self.hash.is_empty()
+    }
+
+    pub fn matches(&self, other: &Fingerprint) -> Option<DuplicateReason>
+    {
+        if !self.has_track() || !other.has_track()
+        {
+            return None;
+        }
+
+        if self.hash == other.hash
+        {
+            return Some(DuplicateReason::SameFixes);
+        }
+
+        // A pilot can't fly twice at the same time
+        let overlap = self.landing_time.min(other.landing_time) > self.takeoff_time.max(other.takeoff_time);
+        let known_times = self.takeoff_time > 0 && other.takeoff_time > 0;
+
+        if self.date == other.date && known_times && overlap && self.takeoff_distance(other) < TAKEOFF_RADIUS
+        {
+            return Some(DuplicateReason::SameTime);
+        }
+
+        None
+    }
+
+    fn takeoff_distance(&self, other: &Fingerprint) -> f64
+    {
+        Location::new(self.lat, self.long)
+            .haversine_distance_to(&Location::new(other.lat, other.long))
+            .meters()
+    }
+}
+
+// Pairs of matching flights, the fingerprints are sorted by date
+pub fn scan(fingerprints: &[Fingerprint]) -> Vec<SuspectedDuplicate>
+{
+    let mut duplicates: Vec<SuspectedDuplicate> = Vec::new();
+
+    for (i, first) in fingerprints.iter().enumerate()
+    {
+        for second in &fingerprints[i + 1..]
+        {
+            // Same hash means same fixes so same date too
+            if second.date != first.date
+            {
+                break;
+            }
+
+            if let Some(reason) = first.matches(second) {
+                duplicates.push(SuspectedDuplicate { flight_ids: (first.flight_id, second.flight_id), reason });
+            }
+        }
+    }
+
+    duplicates
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn fingerprint(flight_id: u32, hash: &str, takeoff_time: i64, landing_time: i64, lat: f32) -> Fingerprint
+    {
+        Fingerprint { flight_id, hash: hash.to_string(), date: "2024-08-12".to_string(), takeoff_time, landing_time, lat, long: 6.25 }
+    }
+
+    #[test]
+    fn same_flight_from_two_devices()
+    {
+        let vario = fingerprint(1, "A1", 1000, 5000, 45.850);
+        let phone = fingerprint(2, "B2", 1030, 4990, 45.852);
+        let copy = fingerprint(3, "A1", 1000, 5000, 45.850);
+
+        assert_eq!(vario.matches(&phone), Some(DuplicateReason::SameTime));
+        assert_eq!(vario.matches(&copy), Some(DuplicateReason::SameFixes));
+
+        // Flights without track are never duplicates
+        assert_eq!(vario.matches(&fingerprint(4, "", 1000, 5000, 45.850)), None);
+    }
+
+    #[test]
+    fn different_flights()
+    {
+        let morning = fingerprint(1, "A1", 1000, 5000, 45.850);
+        let afternoon = fingerprint(2, "B2", 5200, 8000, 45.850);
+        let other_site = fingerprint(3, "C3", 1000, 5000, 45.950);
+
+        assert_eq!(morning.matches(&afternoon), None);
+        assert_eq!(morning.matches(&other_site), None);
+
+        let duplicates = scan(&[morning.clone(), afternoon, fingerprint(4, "A1", 1000, 5000, 45.850)]);
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].flight_ids, (1, 4));
+    }
+}
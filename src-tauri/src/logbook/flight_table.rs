@@ -1,9 +1,10 @@
 use chrono::NaiveDate;
-use rusqlite::Connection;
+use rusqlite::{Connection, params};
 use anyhow::{Result, bail};
 use serde::{Serialize, Deserialize};
 
-use super::{FlightPoint, DATABASE_PATH, IDListe};
+use crate::flight_track::{igc_reader::IgcHeader, igc_validator::IgcValidation, altitude::AltitudeSource};
+use super::{duplicate::Fingerprint, igc_table::IgcTable, site_table::SiteTable, query::{Condition, Changes}};
 
 #[derive(Clone,Debug,Serialize,Deserialize)]
 pub struct FlightTable{
@@ -20,40 +21,53 @@ pub struct FlightTable{
     pub track       :Option<String>,
     pub raw_igc     :Option<String>,
     pub profile     :Option<String>,
+    pub header      :Option<IgcHeader>,
+    pub validation  :String,
+    pub igc_id      :u32,// 0 when the raw IGC is stored with the flight
+    pub fix_start   :u32,// fixes of the flight in the raw IGC
+    pub fix_end     :u32,
+    pub altitude_source :String,// baro, gps or fused, empty to detect it
+    pub alt_max     :u32,
+    pub takeoff_time:i64,// s since epoch, 0 for flights stored before duplicate detection
+    pub landing_time:i64,
+    pub has_track   :bool,// false for the flights entered by hand
 }
 
-impl FlightTable {
-    pub fn create() -> Result<()>{
-        let db_conn = Connection::open(DATABASE_PATH)?;
+// Takeoff or landing of a flight entered by hand, a new site is created with the flight
+#[derive(Clone,Debug,Serialize,Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SiteChoice {
+    Existing(u32),
+    New(SiteTable),
+}
 
-        db_conn.execute("PRAGMA foreign_keys = ON;",())?;
+// Flight entered by hand, it has no track, IGC nor profile
+#[derive(Clone,Debug,Serialize,Deserialize)]
+pub struct ManualFlight {
+    pub date        :String,// YYYY-MM-DD
+    pub duration    :u32,// min
+    pub distance    :u32,// m
+    pub takeoff     :SiteChoice,
+    pub landing     :Option<SiteChoice>,// same as the takeoff when not given
+    pub wing_id     :Option<u32>,// default wing when not given
+}
 
-        db_conn.execute(
-            "CREATE TABLE IF NOT EXISTS flights (
-                flight_id   INTEGER PRIMARY KEY,
-                wing_id     INTEGER REFERENCES wings(wing_id),
-                takeoff_id  INTEGER REFERENCES sites(site_id),
-                landing_id  INTEGER REFERENCES sites(site_id),
-                hash        BLOB UNIQUE,
-                date        DATE NOT NULL,
-                duration    INTEGER,
-                distance    INTEGER,
-                score       INTERGER,
-                code        TEXT,
-                track       BLOB,
-                igc         BLOB,
-                profile     BLOB
-            );",
-            (), // empty list of parameters.
-        )?;
-        db_conn.close().unwrap_or_default();
-        Ok(())
-    }
+// Criteria of the flight list, the empty ones are ignored
+#[derive(Clone,Debug,Default,Serialize,Deserialize)]
+#[serde(default)]
+pub struct FlightFilter {
+    pub date_range  :Option<(String,String)>,// first and last day, YYYY-MM-DD
+    pub site_ids    :Vec<u32>,// takeoff or landing
+    pub wing_ids    :Vec<u32>,
+    pub tag_ids     :Vec<u32>,
+    pub min_distance:Option<u32>,
+    pub min_score   :Option<u32>,
+    pub text        :Option<String>,
+}
 
-    pub fn store(flight: FlightTable) -> Result<()>
+impl FlightTable {
+    pub fn store(db_conn: &Connection, flight: FlightTable) -> Result<()>
     {
-        let db_conn = Connection::open(DATABASE_PATH)?;
-
         let track: Option<Vec<u8>> = match flight.track {
             None => None,
             Some(t) => Some(zstd::encode_all(t.as_bytes(), 5)?)
@@ -69,11 +83,16 @@ impl FlightTable {
             Some(p) => Some(zstd::encode_all(p.as_bytes(), 5)?)
         };
 
+        let header: Option<String> = match flight.header {
+            None => None,
+            Some(h) => Some(serde_json::to_string(&h)?)
+        };
+
         db_conn.execute(
-            "INSERT INTO flights (hash, date, duration, distance, takeoff_id, landing_id, wing_id, score, code, track, igc, profile)
-                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
-                (
-                    flight.hash,
+            "INSERT INTO flights (hash, date, duration, distance, takeoff_id, landing_id, wing_id, score, code, track, igc, profile, header, validation, igc_id, fix_start, fix_end, altitude_source, alt_max, takeoff_time, landing_time)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21)",
+                params![
+                    if flight.hash.is_empty() { None } else { Some(flight.hash) },
                     flight.date,
                     flight.duration,
                     flight.distance,
@@ -85,17 +104,24 @@ impl FlightTable {
                     track,
                     igc,
                     profile,
-                ),
+                    header,
+                    flight.validation,
+                    if flight.igc_id == 0 { None } else { Some(flight.igc_id) },
+                    flight.fix_start,
+                    flight.fix_end,
+                    flight.altitude_source,
+                    flight.alt_max,
+                    if flight.takeoff_time == 0 { None } else { Some(flight.takeoff_time) },
+                    if flight.landing_time == 0 { None } else { Some(flight.landing_time) },
+                ],
             )?;
 
-        db_conn.close().unwrap_or_default();
         Ok(())
     }
 
-    pub fn get(id: u32) -> Result<FlightTable>
+    pub fn get(db_conn: &Connection, id: u32) -> Result<FlightTable>
     {
-        let db_conn = Connection::open(DATABASE_PATH)?;
-        let mut stmt: rusqlite::Statement<'_> = db_conn.prepare("SELECT flight_id, wing_id, takeoff_id, landing_id, date, duration, distance, score, code, track, profile FROM flights WHERE flight_id=?1")?;
+        let mut stmt: rusqlite::Statement<'_> = db_conn.prepare("SELECT flight_id, wing_id, takeoff_id, landing_id, date, duration, distance, score, code, track, profile, header, validation, igc_id, fix_start, fix_end, altitude_source, alt_max, takeoff_time, landing_time, igc IS NOT NULL OR igc_id IS NOT NULL FROM flights WHERE flight_id=?1")?;
 
         let flight = stmt
             .query_row([id], |row| {
@@ -111,6 +137,12 @@ impl FlightTable {
                     Some(p) => Some(String::from_utf8(zstd::decode_all(p.as_slice()).unwrap()).unwrap()),
                 };
 
+                let header: Option<String> = row.get(11)?;
+                let header: Option<IgcHeader> = match header {
+                    None => None,
+                    Some(h) => serde_json::from_str(&h).ok(),
+                };
+
                 Ok(FlightTable {
                     flight_id: row.get(0)?,
                     wing_id: row.get(1)?,
@@ -125,6 +157,16 @@ impl FlightTable {
                     track,
                     raw_igc: None,
                     profile,
+                    header,
+                    validation: row.get::<_, Option<String>>(12)?.unwrap_or_default(),
+                    igc_id: row.get::<_, Option<u32>>(13)?.unwrap_or(0),
+                    fix_start: row.get::<_, Option<u32>>(14)?.unwrap_or(0),
+                    fix_end: row.get::<_, Option<u32>>(15)?.unwrap_or(0),
+                    altitude_source: row.get::<_, Option<String>>(16)?.unwrap_or_default(),
+                    alt_max: row.get::<_, Option<u32>>(17)?.unwrap_or(0),
+                    takeoff_time: row.get::<_, Option<i64>>(18)?.unwrap_or(0),
+                    landing_time: row.get::<_, Option<i64>>(19)?.unwrap_or(0),
+                    has_track: row.get(20)?,
                 })
             })?;
 
@@ -150,86 +192,134 @@ impl FlightTable {
         Ok(flight)
     }
 
-    pub fn delete(condition: String) -> Result<()>
+    // Raw IGC of the whole file the flight comes from
+    pub fn get_igc(db_conn: &Connection, id: u32) -> Result<Option<String>>
     {
-        let mut sql = "DELETE FROM flights WHERE ".to_string();
-        sql.push_str(&condition);
-        let db_conn = Connection::open(DATABASE_PATH)?;
-        db_conn.execute(&sql,())?;
+        let mut stmt: rusqlite::Statement<'_> = db_conn.prepare("SELECT igc, igc_id FROM flights WHERE flight_id=?1")?;
 
-        db_conn.close().unwrap_or_default();
+        let (igc, igc_id): (Option<Vec<u8>>, Option<u32>) = stmt.query_row([id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        let igc = match (igc, igc_id) {
+            (_, Some(igc_id)) => Some(IgcTable::get(db_conn, igc_id)?.raw_igc),
+            (Some(i), None) => Some(String::from_utf8(zstd::decode_all(i.as_slice())?)?),
+            (None, None) => None,
+        };
+
+        Ok(igc)
+    }
+
+    pub fn set_validation(db_conn: &Connection, id: u32, validation: &IgcValidation) -> Result<()>
+    {
+        db_conn.execute("UPDATE flights SET validation=?1 WHERE flight_id=?2", (validation.to_string(), id))?;
 
         Ok(())
     }
 
-    pub fn update(set: String, condition: String) -> Result<()>
+    // Thermals, glide and wind of the flight are stored
+    pub fn is_analysed(db_conn: &Connection, id: u32) -> Result<bool>
     {
-        let mut sql = "UPDATE flights SET ".to_string();
-        sql.push_str(&set);
-        sql.push_str(" WHERE ");
-        sql.push_str(&condition);
-        let db_conn = Connection::open(DATABASE_PATH)?;
-        db_conn.execute(&sql,())?;
+        let analysed: Option<bool> = db_conn.query_row("SELECT analysed FROM flights WHERE flight_id=?1", [id], |row| row.get(0))?;
+
+        Ok(analysed.unwrap_or(false))
+    }
 
-        db_conn.close().unwrap_or_default();
+    pub fn set_analysed(db_conn: &Connection, id: u32) -> Result<()>
+    {
+        db_conn.execute("UPDATE flights SET analysed=1 WHERE flight_id=?1", [id])?;
 
         Ok(())
     }
 
-    pub fn select(condition: String) -> Result<Vec<FlightTable>>
+    pub fn set_altitude_source(db_conn: &Connection, id: u32, source: &AltitudeSource) -> Result<()>
     {
-        let db_conn = Connection::open(DATABASE_PATH)?;
-        let mut fligths: Vec<FlightTable> = Vec::new();
-        let mut sql = "SELECT flight_id, takeoff_id, landing_id, date, duration, distance, score, code FROM flights WHERE ".to_string();
-        sql.push_str(&condition);
+        db_conn.execute("UPDATE flights SET altitude_source=?1 WHERE flight_id=?2", (source.to_string(), id))?;
+
+        Ok(())
+    }
+
+    pub fn set_profile(db_conn: &Connection, id: u32, profile: &String, alt_max: u32) -> Result<()>
+    {
+        let profile: Vec<u8> = zstd::encode_all(profile.as_bytes(), 5)?;
+        db_conn.execute("UPDATE flights SET profile=?1, alt_max=?2 WHERE flight_id=?3", (profile, alt_max, id))?;
+
+        Ok(())
+    }
+
+    // Hash and times of a flight stored before duplicate detection
+    pub fn set_fingerprint(db_conn: &Connection, id: u32, hash: &str, takeoff_time: i64, landing_time: i64) -> Result<()>
+    {
+        db_conn.execute("UPDATE flights SET hash=?1, takeoff_time=?2, landing_time=?3 WHERE flight_id=?4", (hash, takeoff_time, landing_time, id))?;
+
+        Ok(())
+    }
+
+    // Fingerprints of the flights with the position of their takeoff site, the hash is NULL until computed
+    pub fn fingerprints(db_conn: &Connection, condition: &Condition) -> Result<Vec<Fingerprint>>
+    {
+        let mut sql = "SELECT flight_id, hash, date, takeoff_time, landing_time, lat, long FROM flights LEFT JOIN sites ON takeoff_id=site_id WHERE ".to_string();
+        sql.push_str(&condition.sql());
 
         let mut stmt = db_conn.prepare(&sql)?;
 
         let rows = stmt
-            .query_map([], |row| {
-                Ok(FlightTable {
+            .query_map(condition.params(), |row| {
+                Ok(Fingerprint {
                     flight_id: row.get(0)?,
-                    wing_id: 0,
-                    takeoff_id: row.get(1)?,
-                    landing_id: row.get(2)?,
-                    hash: "".to_string(),
-                    date: row.get(3)?,
-                    duration: row.get(4)?,
-                    distance: row.get(5)?,
-                    score: row.get(6)?,
-                    code: row.get(7)?,
-                    track: None,
-                    raw_igc: None,
-                    profile: None,
+                    hash: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                    date: row.get(2)?,
+                    takeoff_time: row.get::<_, Option<i64>>(3)?.unwrap_or(0),
+                    landing_time: row.get::<_, Option<i64>>(4)?.unwrap_or(0),
+                    lat: row.get::<_, Option<f32>>(5)?.unwrap_or(0.0),
+                    long: row.get::<_, Option<f32>>(6)?.unwrap_or(0.0),
                 })
             })?;
 
-        for flight in rows {
-            if let Ok(f) = flight {
-                fligths.push(f)
-            }
-        }
+        Ok(rows.collect::<rusqlite::Result<Vec<Fingerprint>>>()?)
+    }
 
-        Ok(fligths)
+    pub fn last_flight_id(db_conn: &Connection) -> Result<u32>
+    {
+        let mut stmt = db_conn.prepare("SELECT flight_id FROM flights ORDER BY flight_id DESC LIMIT 1;")?;
+
+        let id = stmt.query_row([], | row | Ok(row.get(0).unwrap_or(0)))?;
+
+        Ok(id)
+    }
+
+    pub fn delete(db_conn: &Connection, condition: &Condition) -> Result<()>
+    {
+        let mut sql = "DELETE FROM flights WHERE ".to_string();
+        sql.push_str(&condition.sql());
+        db_conn.execute(&sql, condition.params())?;
+
+        Ok(())
     }
 
-    pub fn select_all() -> Result<Vec<FlightTable>>
+    pub fn update(db_conn: &Connection, changes: &Changes, condition: &Condition) -> Result<()>
     {
-        FlightTable::select("1 ORDER BY date DESC".to_string())
+        if changes.is_empty()
+        {
+            return Ok(());
+        }
+
+        let (sql, params) = changes.update("flights", condition);
+        db_conn.execute(&sql, params)?;
+
+        Ok(())
     }
 
-    pub fn get_by_tag(tag_ids: IDListe) -> Result<Vec<FlightTable>>
+    pub fn select(db_conn: &Connection, condition: &Condition) -> Result<Vec<FlightTable>>
     {
-        let db_conn = Connection::open(DATABASE_PATH)?;
         let mut fligths: Vec<FlightTable> = Vec::new();
-        let sql = format!("SELECT flight_id, takeoff_id, landing_id, date, duration, distance, score, code FROM flights INNER JOIN tag_asso WHERE flights.flight_id=asso_flight_id AND asso_tag_id IN {}",tag_ids.to_string());
+        let mut sql = "SELECT flight_id, takeoff_id, landing_id, date, duration, distance, score, code, validation, alt_max, takeoff_time, landing_time, igc IS NOT NULL OR igc_id IS NOT NULL, wing_id FROM flights WHERE ".to_string();
+        sql.push_str(&condition.sql());
+
         let mut stmt = db_conn.prepare(&sql)?;
 
         let rows = stmt
-            .query_map([], |row| {
+            .query_map(condition.params(), |row| {
                 Ok(FlightTable {
                     flight_id: row.get(0)?,
-                    wing_id: 0,
+                    wing_id: row.get::<_, Option<u32>>(13)?.unwrap_or(0),
                     takeoff_id: row.get(1)?,
                     landing_id: row.get(2)?,
                     hash: "".to_string(),
@@ -241,12 +331,20 @@ impl FlightTable {
                     track: None,
                     raw_igc: None,
                     profile: None,
+                    header: None,
+                    validation: row.get::<_, Option<String>>(8)?.unwrap_or_default(),
+                    igc_id: 0,
+                    fix_start: 0,
+                    fix_end: 0,
+                    altitude_source: "".to_string(),
+                    alt_max: row.get::<_, Option<u32>>(9)?.unwrap_or(0),
+                    takeoff_time: row.get::<_, Option<i64>>(10)?.unwrap_or(0),
+                    landing_time: row.get::<_, Option<i64>>(11)?.unwrap_or(0),
+                    has_track: row.get(12)?,
                 })
             })?;
 
-
         for flight in rows {
-            // flight.unwrap();
             if let Ok(f) = flight {
                 fligths.push(f)
             }
@@ -255,8 +353,79 @@ impl FlightTable {
         Ok(fligths)
     }
 
-    pub fn get_by_site(site_ids: IDListe) -> Result<Vec<FlightTable>>
+    pub fn select_all(db_conn: &Connection) -> Result<Vec<FlightTable>>
+    {
+        FlightTable::select(db_conn, &Condition::all().order_by("date DESC"))
+    }
+
+    pub fn filter(db_conn: &Connection, filter: &FlightFilter) -> Result<Vec<FlightTable>>
     {
-        FlightTable::select(format!("takeoff_id IN {} OR landing_id IN {}",site_ids.to_string(),site_ids.to_string(),))
+        FlightTable::select(db_conn, &filter.condition().order_by("date DESC"))
     }
-}
\ No newline at end of file
+
+    pub fn get_by_tag(db_conn: &Connection, tag_ids: &[u32]) -> Result<Vec<FlightTable>>
+    {
+        FlightTable::filter(db_conn, &FlightFilter { tag_ids: tag_ids.to_vec(), ..Default::default() })
+    }
+
+    pub fn get_by_site(db_conn: &Connection, site_ids: &[u32]) -> Result<Vec<FlightTable>>
+    {
+        FlightTable::filter(db_conn, &FlightFilter { site_ids: site_ids.to_vec(), ..Default::default() })
+    }
+}
+
+impl FlightFilter {
+    pub fn condition(&self) -> Condition
+    {
+        let mut condition = Condition::all();
+
+        if let Some((first, last)) = &self.date_range
+        {
+            condition = condition.ge("date", first.clone()).le("date", last.clone());
+        }
+
+        if !self.site_ids.is_empty()
+        {
+            condition = condition.any(vec![
+                Condition::all().any_of("takeoff_id", &self.site_ids),
+                Condition::all().any_of("landing_id", &self.site_ids),
+            ]);
+        }
+
+        if !self.wing_ids.is_empty()
+        {
+            condition = condition.any_of("wing_id", &self.wing_ids);
+        }
+
+        if !self.tag_ids.is_empty()
+        {
+            condition = condition.within("flight_id", "asso_flight_id", "tag_asso", Condition::all().any_of("asso_tag_id", &self.tag_ids));
+        }
+
+        if let Some(distance) = self.min_distance
+        {
+            condition = condition.ge("distance", distance);
+        }
+
+        if let Some(score) = self.min_score
+        {
+            condition = condition.ge("score", score);
+        }
+
+        // Name of a site, the wing or a tag of the flight
+        if let Some(text) = self.text.as_ref().filter(|t| !t.trim().is_empty())
+        {
+            let text = text.trim();
+
+            condition = condition.any(vec![
+                Condition::all().within("takeoff_id", "site_id", "sites", Condition::all().like("name", text)),
+                Condition::all().within("landing_id", "site_id", "sites", Condition::all().like("name", text)),
+                Condition::all().within("wing_id", "wing_id", "wings", Condition::all().like("name", text)),
+                Condition::all().within("flight_id", "asso_flight_id", "tag_asso",
+                    Condition::all().within("asso_tag_id", "tag_id", "tags", Condition::all().like("name", text))),
+            ]);
+        }
+
+        condition
+    }
+}
@@ -0,0 +1,155 @@
+use rusqlite::Connection;
+use anyhow::Result;
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+
+use crate::flight_track::glide::GlideAnalysis;
+use super::query::Condition;
+
+#[derive(Clone,Debug,Serialize,Deserialize)]
+pub struct GlideTable
+{
+    pub glide_id: u32,
+    pub flight_id: u32,
+    pub circling: f32,// % of flight time
+    pub glide_ratio: f32,
+    pub glide_speed: f32,// m/s
+    pub sink: f32,// m/s
+    pub climb_rate: f32,// m/s
+    pub phases: Option<String>,// JSON list of phase segments
+}
+
+impl From<&GlideAnalysis> for GlideTable {
+    fn from(analysis: &GlideAnalysis) -> Self {
+        let phases: Vec<serde_json::Value> = analysis.segments.iter()
+            .map(|s| json!({
+                "phase": s.phase.to_string(),
+                "start": s.start.timestamp(),
+                "end": s.end.timestamp(),
+                "start_alt": s.start_alt,
+                "end_alt": s.end_alt,
+                "distance": s.distance,
+                "glide_ratio": s.glide_ratio,
+                "speed": s.speed,
+                "sink": s.sink,
+            }))
+            .collect();
+
+        GlideTable {
+            glide_id: 0,
+            flight_id: 0,
+            circling: analysis.circling,
+            glide_ratio: analysis.glide_ratio,
+            glide_speed: analysis.glide_speed,
+            sink: analysis.sink,
+            climb_rate: analysis.climb_rate,
+            phases: Some(serde_json::Value::from(phases).to_string()),
+        }
+    }
+}
+
+impl GlideTable
+{
+    pub fn store(db_conn: &Connection, glide: GlideTable) -> Result<()>
+    {
+        let phases: Option<Vec<u8>> = match glide.phases {
+            None => None,
+            Some(p) => Some(zstd::encode_all(p.as_bytes(), 5)?)
+        };
+
+        // One analysis per flight, analysing again replaces it
+        db_conn.execute(
+            "INSERT OR REPLACE INTO glides (flight_id, circling, glide_ratio, glide_speed, sink, climb_rate, phases)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                (
+                    glide.flight_id,
+                    glide.circling,
+                    glide.glide_ratio,
+                    glide.glide_speed,
+                    glide.sink,
+                    glide.climb_rate,
+                    phases,
+                ),
+            )?;
+
+        Ok(())
+    }
+
+    pub fn get(db_conn: &Connection, flight_id: u32) -> Result<Option<GlideTable>>
+    {
+        let mut stmt: rusqlite::Statement<'_> = db_conn.prepare("SELECT glide_id, flight_id, circling, glide_ratio, glide_speed, sink, climb_rate, phases FROM glides WHERE flight_id=?1")?;
+
+        let mut rows = stmt
+            .query_map([flight_id], |row| {
+                let phases: Option<Vec<u8>> = row.get(7)?;
+                let phases = match phases {
+                    None => None,
+                    Some(p) => Some(String::from_utf8(zstd::decode_all(p.as_slice()).unwrap()).unwrap()),
+                };
+
+                Ok(GlideTable {
+                    glide_id: row.get(0)?,
+                    flight_id: row.get(1)?,
+                    circling: row.get(2)?,
+                    glide_ratio: row.get(3)?,
+                    glide_speed: row.get(4)?,
+                    sink: row.get(5)?,
+                    climb_rate: row.get(6)?,
+                    phases,
+                })
+            })?;
+
+        match rows.next() {
+            None => Ok(None),
+            Some(g) => Ok(Some(g?)),
+        }
+    }
+
+    pub fn delete(db_conn: &Connection, condition: &Condition) -> Result<()>
+    {
+        let mut sql = "DELETE FROM glides WHERE ".to_string();
+        sql.push_str(&condition.sql());
+        db_conn.execute(&sql, condition.params())?;
+
+        Ok(())
+    }
+
+    pub fn select(db_conn: &Connection, condition: &Condition) -> Result<Vec<GlideTable>>
+    {
+        let mut glides: Vec<GlideTable> = Vec::new();
+        let mut sql = "SELECT glide_id, flight_id, circling, glide_ratio, glide_speed, sink, climb_rate FROM glides WHERE ".to_string();
+        sql.push_str(&condition.sql());
+
+        let mut stmt = db_conn.prepare(&sql)?;
+
+        let rows = stmt
+            .query_map(condition.params(), |row| {
+                Ok(GlideTable {
+                    glide_id: row.get(0)?,
+                    flight_id: row.get(1)?,
+                    circling: row.get(2)?,
+                    glide_ratio: row.get(3)?,
+                    glide_speed: row.get(4)?,
+                    sink: row.get(5)?,
+                    climb_rate: row.get(6)?,
+                    phases: None,
+                })
+            })?;
+
+        for glide in rows {
+            if let Ok(g) = glide {
+                glides.push(g)
+            }
+        }
+
+        Ok(glides)
+    }
+
+    // Summaries of all the flights made with a wing, oldest first
+    pub fn get_by_wing(db_conn: &Connection, wing_id: u32) -> Result<Vec<GlideTable>>
+    {
+        GlideTable::select(db_conn, &Condition::all()
+            .within("flight_id", "flight_id", "flights", Condition::all().eq("wing_id", wing_id))
+            .order_by("(SELECT date FROM flights WHERE flights.flight_id=glides.flight_id)"))
+    }
+}
@@ -0,0 +1,54 @@
+use rusqlite::Connection;
+use anyhow::Result;
+
+use super::query::Condition;
+
+// Raw track file as imported, shared by all the flights found in it
+#[derive(Clone,Debug)]
+pub struct IgcTable
+{
+    pub igc_id: u32,
+    pub raw_igc: String,
+}
+
+impl IgcTable
+{
+    pub fn store(db_conn: &Connection, igc: IgcTable) -> Result<()>
+    {
+        let raw: Vec<u8> = zstd::encode_all(igc.raw_igc.as_bytes(), 5)?;
+
+        db_conn.execute("INSERT INTO igc_files (igc) VALUES (?1)", [raw])?;
+
+        Ok(())
+    }
+
+    pub fn get(db_conn: &Connection, id: u32) -> Result<IgcTable>
+    {
+        let mut stmt: rusqlite::Statement<'_> = db_conn.prepare("SELECT igc_id, igc FROM igc_files WHERE igc_id=?1")?;
+
+        let (igc_id, raw): (u32, Vec<u8>) = stmt.query_row([id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        Ok(IgcTable {
+            igc_id,
+            raw_igc: String::from_utf8(zstd::decode_all(raw.as_slice())?)?,
+        })
+    }
+
+    pub fn delete(db_conn: &Connection, condition: &Condition) -> Result<()>
+    {
+        let mut sql = "DELETE FROM igc_files WHERE ".to_string();
+        sql.push_str(&condition.sql());
+        db_conn.execute(&sql, condition.params())?;
+
+        Ok(())
+    }
+
+    pub fn last_igc_id(db_conn: &Connection) -> Result<u32>
+    {
+        let mut stmt = db_conn.prepare("SELECT igc_id FROM igc_files ORDER BY igc_id DESC LIMIT 1;")?;
+
+        let id = stmt.query_row([], | row | Ok(row.get(0).unwrap_or(0)))?;
+
+        Ok(id)
+    }
+}
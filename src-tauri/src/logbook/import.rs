@@ -0,0 +1,353 @@
+use std::{collections::{HashMap, VecDeque}, path::Path, sync::{Arc, Mutex, atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering}, mpsc}, thread};
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+
+use crate::airspace::Airspace;
+use super::{Logbook, ParsedFile, database::Database, duplicate::DuplicatePolicy, setting_table::{SettingTable, DEM_FOLDER}};
+
+// Files parsed at the same time, the database is written by a single thread
+const MAX_WORKERS: usize = 4;
+
+#[derive(Clone,Copy,Debug,PartialEq,Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStatus {
+    Parsed,
+    Scored,
+    Stored,
+    Duplicate,
+    Failed,
+    Cancelled,
+}
+
+// Outcome of one file of an import
+#[derive(Clone,Debug,Serialize)]
+pub struct ImportedFile {
+    pub path: String,
+    pub status: ImportStatus,
+    pub flight_ids: Vec<u32>,
+    pub duplicates: Vec<u32>,// flights of the logbook matching the file, deleted when replaced
+    pub warnings: Vec<String>,
+    pub reason: Option<String>,// why the file failed
+}
+
+#[derive(Clone,Debug,Serialize)]
+pub struct ImportReport {
+    pub job_id: u32,// 0 when the import didn't run as a job
+    pub files: Vec<ImportedFile>,
+    pub cancelled: bool,
+}
+
+// Sent each time a file goes to the next step
+#[derive(Clone,Debug,Serialize)]
+pub struct ImportProgress {
+    pub job_id: u32,
+    pub path: String,
+    pub status: ImportStatus,
+    pub reason: Option<String>,
+    pub done: usize,// files stored, duplicate or failed
+    pub total: usize,
+}
+
+impl ImportedFile {
+    pub fn failed(path: &str, err: &anyhow::Error) -> ImportedFile
+    {
+        ImportedFile { path: path.to_string(), status: ImportStatus::Failed, flight_ids: Vec::new(), duplicates: Vec::new(), warnings: Vec::new(), reason: Some(err.to_string()) }
+    }
+
+    fn cancelled(path: &str) -> ImportedFile
+    {
+        ImportedFile { path: path.to_string(), status: ImportStatus::Cancelled, flight_ids: Vec::new(), duplicates: Vec::new(), warnings: Vec::new(), reason: None }
+    }
+}
+
+// Imports running in the background, managed as Tauri state
+#[derive(Default)]
+pub struct ImportJobs {
+    last_id: AtomicU32,
+    running: Arc<Mutex<HashMap<u32, Arc<AtomicBool>>>>,
+}
+
+impl ImportJobs {
+    // Track files of the path imported in the background, returns the job id at once.
+    // Each file is stored in its own transaction so a cancelled job keeps the files already stored.
+    pub fn start<P, F>(&self, db: Database, path: &Path, policy: DuplicatePolicy, progress: P, finished: F) -> Result<u32>
+    where
+        P: Fn(ImportProgress) + Send + Sync + 'static,
+        F: FnOnce(ImportReport) + Send + 'static,
+    {
+        let mut paths: Vec<String> = Vec::new();
+        Logbook::search_igc(path, &mut paths);
+
+        let (dem_folder, airspaces) = {
+            let db_conn = db.connection()?;
+            (SettingTable::get(&db_conn, DEM_FOLDER)?, Logbook::airspaces(&db_conn)?)
+        };
+
+        let job_id = self.last_id.fetch_add(1, Ordering::SeqCst) + 1;
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.running.lock().map_err(|_| anyhow!("Import jobs poisoned"))?.insert(job_id, cancel.clone());
+
+        let job = ImportJob {
+            job_id,
+            db,
+            policy,
+            total: paths.len(),
+            queue: Arc::new(Mutex::new(VecDeque::from(paths))),
+            dem_folder,
+            airspaces: Arc::new(airspaces),
+            done: Arc::new(AtomicUsize::new(0)),
+            cancel,
+            progress: Arc::new(progress),
+        };
+        let running = self.running.clone();
+
+        thread::spawn(move || {
+            let report = job.run();
+
+            if let Ok(mut running) = running.lock() {
+                running.remove(&job_id);
+            }
+            finished(report);
+        });
+
+        Ok(job_id)
+    }
+
+    // False when the job is already finished
+    pub fn cancel(&self, job_id: u32) -> bool
+    {
+        match self.running.lock().ok().and_then(|running| running.get(&job_id).cloned()) {
+            None => false,
+            Some(cancel) => {
+                cancel.store(true, Ordering::SeqCst);
+                true
+            },
+        }
+    }
+}
+
+struct ImportJob<P> {
+    job_id: u32,
+    db: Database,
+    policy: DuplicatePolicy,
+    total: usize,
+    queue: Arc<Mutex<VecDeque<String>>>,
+    dem_folder: Option<String>,
+    airspaces: Arc<Vec<Airspace>>,
+    done: Arc<AtomicUsize>,
+    cancel: Arc<AtomicBool>,
+    progress: Arc<P>,
+}
+
+impl<P: Fn(ImportProgress) + Send + Sync + 'static> ImportJob<P> {
+    fn run(self) -> ImportReport
+    {
+        let nb_worker = thread::available_parallelism().map(|n| n.get()).unwrap_or(1).min(MAX_WORKERS).min(self.total.max(1));
+        // Bounded so the workers don't get far ahead of the database
+        let (sender, receiver) = mpsc::sync_channel::<(String, Result<ParsedFile>)>(nb_worker);
+        let mut workers = Vec::new();
+
+        for _ in 0..nb_worker
+        {
+            let worker = self.worker(sender.clone());
+            workers.push(thread::spawn(worker));
+        }
+        drop(sender);
+
+        let mut files: Vec<ImportedFile> = Vec::new();
+        for (path, parsed) in receiver
+        {
+            let file = if self.cancelled() {
+                ImportedFile::cancelled(&path)
+            } else {
+                match parsed.and_then(|p| self.store(&path, p)) {
+                    Ok(f) => f,
+                    Err(err) => ImportedFile::failed(&path, &err),
+                }
+            };
+
+            if file.status != ImportStatus::Cancelled
+            {
+                let done = self.done.fetch_add(1, Ordering::SeqCst) + 1;
+                (self.progress)(ImportProgress { job_id: self.job_id, path: path.clone(), status: file.status, reason: file.reason.clone(), done, total: self.total });
+            }
+            files.push(file);
+        }
+
+        for worker in workers
+        {
+            worker.join().unwrap_or_default();
+        }
+
+        // Files no worker started before the cancellation
+        if let Ok(mut queue) = self.queue.lock() {
+            files.extend(queue.drain(..).map(|path| ImportedFile::cancelled(&path)));
+        }
+
+        ImportReport { job_id: self.job_id, files, cancelled: self.cancelled() }
+    }
+
+    fn worker(&self, sender: mpsc::SyncSender<(String, Result<ParsedFile>)>) -> impl FnOnce() + Send + 'static
+    {
+        let job_id = self.job_id;
+        let total = self.total;
+        let queue = self.queue.clone();
+        let dem_folder = self.dem_folder.clone();
+        let airspaces = self.airspaces.clone();
+        let done = self.done.clone();
+        let cancel = self.cancel.clone();
+        let progress = self.progress.clone();
+
+        move || {
+            let (mut terrain, dem_warning) = Logbook::open_terrain(dem_folder);
+
+            while !cancel.load(Ordering::SeqCst)
+            {
+                let path = match queue.lock().ok().and_then(|mut q| q.pop_front()) {
+                    None => break,
+                    Some(p) => p,
+                };
+                let send = |status: ImportStatus| progress(ImportProgress { job_id, path: path.clone(), status, reason: None, done: done.load(Ordering::SeqCst), total });
+
+                let parsed = Logbook::read(Path::new(&path)).and_then(|(raw_igc, tracks)| {
+                    send(ImportStatus::Parsed);
+                    Logbook::parse(raw_igc, tracks, &mut terrain, &airspaces)
+                }).map(|mut p| {
+                    p.warnings.extend(dem_warning.clone());
+                    p
+                });
+                if parsed.is_ok()
+                {
+                    send(ImportStatus::Scored);
+                }
+
+                if sender.send((path, parsed)).is_err()
+                {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn store(&self, path: &str, parsed: ParsedFile) -> Result<ImportedFile>
+    {
+        let mut db_conn = self.db.connection()?;
+        let tx = db_conn.transaction()?;
+
+        let file = Logbook::store(&tx, path, parsed, self.policy)?;
+        tx.commit()?;
+
+        Ok(file)
+    }
+
+    fn cancelled(&self) -> bool
+    {
+        self.cancel.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf, time::Duration};
+    use super::*;
+
+    const FIXTURES: [&str; 5] = ["two_flights.igc", "ground_handling.igc", "slow_launch.igc", "top_landing.igc", "drive_away.igc"];
+
+    // Folder of its own for each test, in the temp folder
+    fn folder(name: &str, files: &[&str]) -> PathBuf
+    {
+        let folder = std::env::temp_dir().join(format!("flight_log_{}_{}", name, std::process::id()));
+        fs::remove_dir_all(&folder).unwrap_or_default();
+        fs::create_dir_all(&folder).unwrap();
+
+        for file in files
+        {
+            fs::copy(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(file), folder.join(file)).unwrap();
+        }
+
+        folder
+    }
+
+    // Runs a job to the end, with the progress events sent on the way
+    fn run(jobs: Arc<ImportJobs>, db: &Database, folder: &Path, policy: DuplicatePolicy, cancel_on_store: bool) -> (ImportReport, Vec<ImportProgress>)
+    {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let (sender, receiver) = mpsc::channel();
+
+        let progress_events = events.clone();
+        let progress_jobs = jobs.clone();
+        jobs.start(db.clone(), folder, policy, move |progress: ImportProgress| {
+            if cancel_on_store && progress.status == ImportStatus::Stored
+            {
+                progress_jobs.cancel(progress.job_id);
+            }
+            progress_events.lock().unwrap().push(progress);
+        }, move |report| sender.send(report).unwrap()).unwrap();
+
+        let report = receiver.recv_timeout(Duration::from_secs(60)).unwrap();
+        let events = events.lock().unwrap().clone();
+        (report, events)
+    }
+
+    fn count(db: &Database, table: &str) -> u32
+    {
+        db.connection().unwrap().query_row(&format!("SELECT count(*) FROM {}", table), [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn statuses()
+    {
+        let folder = folder("import_statuses", &["top_landing.igc"]);
+        fs::copy(folder.join("top_landing.igc"), folder.join("top_landing_copy.igc")).unwrap();
+        fs::write(folder.join("broken.igc"), "AXXXFIX\r\nHFDTE120824\r\n").unwrap();
+
+        let db = Database::in_memory().unwrap();
+        let (report, events) = run(Arc::new(ImportJobs::default()), &db, &folder, DuplicatePolicy::Skip, false);
+        let last = |file: &str| events.iter().filter(|e| e.path.ends_with(file)).last().unwrap().status;
+        let parsed = |file: &str| events.iter().any(|e| e.path.ends_with(file) && e.status == ImportStatus::Parsed);
+
+        assert!(!report.cancelled);
+        assert_eq!(report.files.len(), 3);
+        assert!(events.iter().all(|e| e.job_id == report.job_id && e.total == 3));
+        assert_eq!(events.iter().map(|e| e.done).max(), Some(3));
+
+        // The copy stored first is the original, the other one is its duplicate
+        assert!(parsed("/top_landing.igc") && parsed("top_landing_copy.igc"));
+        let mut copies = vec![last("/top_landing.igc"), last("top_landing_copy.igc")];
+        copies.sort_by_key(|s| *s as u8);
+        assert_eq!(copies, vec![ImportStatus::Stored, ImportStatus::Duplicate]);
+
+        assert!(!parsed("broken.igc"));
+        assert_eq!(last("broken.igc"), ImportStatus::Failed);
+        assert!(events.iter().any(|e| e.path.ends_with("broken.igc") && e.reason.is_some()));
+
+        assert_eq!(count(&db, "flights"), 1);
+        assert_eq!(count(&db, "igc_files"), 1);
+
+        fs::remove_dir_all(&folder).unwrap_or_default();
+    }
+
+    #[test]
+    fn cancel()
+    {
+        let folder = folder("import_cancel", &FIXTURES);
+        let db = Database::in_memory().unwrap();
+        let (report, events) = run(Arc::new(ImportJobs::default()), &db, &folder, DuplicatePolicy::KeepBoth, true);
+
+        // Cancelled on the first file stored, the other ones would all have been kept
+        assert!(report.cancelled);
+        assert_eq!(report.files.len(), FIXTURES.len());
+        assert_eq!(events.iter().filter(|e| e.status == ImportStatus::Stored).count(), 1);
+
+        let stored: Vec<&ImportedFile> = report.files.iter().filter(|f| f.status == ImportStatus::Stored).collect();
+        assert_eq!(stored.len(), 1);
+        assert!(report.files.iter().all(|f| f.status == ImportStatus::Stored || f.status == ImportStatus::Cancelled));
+
+        // Only the flights of the stored file are in the logbook, each with its track
+        assert_eq!(count(&db, "flights"), stored[0].flight_ids.len() as u32);
+        assert_eq!(count(&db, "igc_files"), 1);
+        assert_eq!(db.connection().unwrap().query_row("SELECT count(*) FROM flights WHERE track IS NULL OR igc_id IS NULL", [], |row| row.get::<_, u32>(0)).unwrap(), 0);
+
+        fs::remove_dir_all(&folder).unwrap_or_default();
+    }
+}
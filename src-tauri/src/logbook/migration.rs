@@ -0,0 +1,467 @@
+use std::{fs, path::Path};
+use rusqlite::{Connection, Transaction};
+use anyhow::{Result, bail};
+
+// Schema steps in order, the database user_version is the number of steps applied.
+// A released step must never change, new schema changes go in a new step at the end.
+const MIGRATIONS: [fn(&Transaction) -> Result<()>; 6] = [
+    baseline,
+    flight_columns,
+    analysis_tables,
+    score_type,
+    content_hash,
+    analysed,
+];
+
+pub fn latest_version() -> u32
+{
+    MIGRATIONS.len() as u32
+}
+
+pub fn version(db_conn: &Connection) -> Result<u32>
+{
+    Ok(db_conn.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+}
+
+// Brings the database to the latest schema, returns the version it had
+pub fn migrate(path: &str) -> Result<u32>
+{
+    let mut db_conn = Connection::open(path)?;
+    let from = upgrade(&mut db_conn, Some(path))?;
+
+    db_conn.close().unwrap_or_default();
+    Ok(from)
+}
+
+// Same on an open connection, backed up next to the path when there is one
+pub fn upgrade(db_conn: &mut Connection, path: Option<&str>) -> Result<u32>
+{
+    let from = version(db_conn)?;
+
+    if from > latest_version()
+    {
+        bail!("Database version {} is newer than this application (version {})", from, latest_version());
+    }
+
+    // One copy of the database as it was opened, before the first step
+    if let Some(path) = path
+    {
+        if from < latest_version()
+        {
+            backup(db_conn, path, from)?;
+        }
+    }
+
+    // Rebuilding a table drops it while others still reference it
+    db_conn.pragma_update(None, "foreign_keys", false)?;
+
+    for step in from..latest_version()
+    {
+        let tx = db_conn.transaction()?;
+        MIGRATIONS[step as usize](&tx)?;
+        tx.pragma_update(None, "user_version", step + 1)?;
+        tx.commit()?;
+    }
+
+    db_conn.pragma_update(None, "foreign_keys", true)?;
+    Ok(from)
+}
+
+// Copy of the database before it is migrated, next to it : flight_database.db.v<version>.bak
+fn backup(db_conn: &Connection, path: &str, version: u32) -> Result<()>
+{
+    let nb_table: u32 = db_conn.query_row("SELECT count(*) FROM sqlite_master WHERE type='table'", [], |row| row.get(0))?;
+
+    // Nothing to save in a new database, the first release had tables but no version
+    if nb_table == 0
+    {
+        return Ok(());
+    }
+
+    let backup = format!("{}.v{}.bak", path, version);
+    if Path::new(&backup).exists()
+    {
+        fs::remove_file(&backup)?;
+    }
+    db_conn.execute("VACUUM INTO ?1", [&backup])?;
+
+    Ok(())
+}
+
+fn has_column(tx: &Transaction, table: &str, column: &str) -> Result<bool>
+{
+    let mut stmt = tx.prepare(&format!("PRAGMA table_info({})", table))?;
+    let columns: Vec<String> = stmt.query_map([], |row| row.get(1))?.collect::<Result<_, _>>()?;
+
+    Ok(columns.iter().any(|c| c == column))
+}
+
+// Version 1 : tables of the first release, kept as they were so existing databases match them
+fn baseline(tx: &Transaction) -> Result<()>
+{
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS flights (
+            flight_id   INTEGER PRIMARY KEY,
+            wing_id     INTEGER REFERENCES wings(wing_id),
+            takeoff_id  INTEGER REFERENCES sites(site_id),
+            landing_id  INTEGER REFERENCES sites(site_id),
+            hash        BLOB UNIQUE,
+            date        DATE NOT NULL,
+            duration    INTEGER,
+            distance    INTEGER,
+            score       INTERGER,
+            code        TEXT,
+            track       BLOB,
+            igc         BLOB,
+            profile     BLOB
+        );
+        CREATE TABLE IF NOT EXISTS sites (
+            site_id     INTEGER PRIMARY KEY,
+            name        TEXT,
+            lat         FLOAT,
+            long        FLOAT,
+            alt         INTEGER,
+            info        TEXT
+        );
+        CREATE TABLE IF NOT EXISTS wings (
+            wing_id     INTEGER PRIMARY KEY,
+            name        TEXT UNIQUE,
+            info        TEXT,
+            def         BOOLEAN
+        );
+        CREATE TABLE IF NOT EXISTS tags (
+            tag_id      INTEGER PRIMARY KEY,
+            name        TEXT UNIQUE
+        );
+        CREATE TABLE IF NOT EXISTS tag_asso (
+            asso_id         INTEGER PRIMARY KEY,
+            asso_tag_id     INTEGER REFERENCES tags(tag_id),
+            asso_flight_id  INTEGER REFERENCES flights(flight_id)
+        );
+        INSERT OR IGNORE INTO wings (wing_id,name,info,def) VALUES (0,'default','',1);",
+    )?;
+
+    Ok(())
+}
+
+// Version 2 : flight columns added since, some databases already have part of them
+fn flight_columns(tx: &Transaction) -> Result<()>
+{
+    let columns = [
+        ("header", "TEXT"),
+        ("validation", "TEXT"),
+        ("igc_id", "INTEGER REFERENCES igc_files(igc_id)"),
+        ("fix_start", "INTEGER"),
+        ("fix_end", "INTEGER"),
+        ("altitude_source", "TEXT"),
+        ("alt_max", "INTEGER"),
+    ];
+
+    for (column, definition) in columns
+    {
+        if !has_column(tx, "flights", column)?
+        {
+            tx.execute(&format!("ALTER TABLE flights ADD COLUMN {} {}", column, definition), ())?;
+        }
+    }
+
+    Ok(())
+}
+
+// Version 3 : raw files, analysis results and settings
+fn analysis_tables(tx: &Transaction) -> Result<()>
+{
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS igc_files (
+            igc_id      INTEGER PRIMARY KEY,
+            igc         BLOB
+        );
+        CREATE TABLE IF NOT EXISTS scores (
+            score_id    INTEGER PRIMARY KEY,
+            flight_id   INTEGER REFERENCES flights(flight_id) ON DELETE CASCADE,
+            rules       TEXT NOT NULL,
+            score       INTEGER,
+            code        TEXT,
+            track       BLOB,
+            UNIQUE(flight_id, rules)
+        );
+        CREATE TABLE IF NOT EXISTS thermals (
+            thermal_id  INTEGER PRIMARY KEY,
+            flight_id   INTEGER REFERENCES flights(flight_id) ON DELETE CASCADE,
+            entry       INTEGER,
+            exit        INTEGER,
+            entry_alt   INTEGER,
+            exit_alt    INTEGER,
+            alt_gain    INTEGER,
+            avg_climb   REAL,
+            max_climb   REAL,
+            direction   TEXT,
+            lat         REAL,
+            long        REAL
+        );
+        CREATE TABLE IF NOT EXISTS glides (
+            glide_id    INTEGER PRIMARY KEY,
+            flight_id   INTEGER UNIQUE REFERENCES flights(flight_id) ON DELETE CASCADE,
+            circling    REAL,
+            glide_ratio REAL,
+            glide_speed REAL,
+            sink        REAL,
+            climb_rate  REAL,
+            phases      BLOB
+        );
+        CREATE TABLE IF NOT EXISTS winds (
+            wind_id     INTEGER PRIMARY KEY,
+            flight_id   INTEGER REFERENCES flights(flight_id) ON DELETE CASCADE,
+            time        INTEGER,
+            alt         INTEGER,
+            speed       REAL,
+            direction   REAL,
+            airspeed    REAL
+        );
+        CREATE TABLE IF NOT EXISTS violations (
+            violation_id INTEGER PRIMARY KEY,
+            flight_id   INTEGER REFERENCES flights(flight_id) ON DELETE CASCADE,
+            airspace    TEXT,
+            class       TEXT,
+            limits      TEXT,
+            start       INTEGER,
+            end         INTEGER,
+            alt         INTEGER,
+            lat         FLOAT,
+            long        FLOAT
+        );
+        CREATE TABLE IF NOT EXISTS settings (
+            key         TEXT PRIMARY KEY,
+            value       TEXT
+        );",
+    )?;
+
+    Ok(())
+}
+
+// Version 4 : flights.score was declared INTERGER, SQLite can't change a column type so the table is rebuilt
+fn score_type(tx: &Transaction) -> Result<()>
+{
+    tx.execute_batch(
+        "CREATE TABLE flights_new (
+            flight_id   INTEGER PRIMARY KEY,
+            wing_id     INTEGER REFERENCES wings(wing_id),
+            takeoff_id  INTEGER REFERENCES sites(site_id),
+            landing_id  INTEGER REFERENCES sites(site_id),
+            hash        BLOB UNIQUE,
+            date        DATE NOT NULL,
+            duration    INTEGER,
+            distance    INTEGER,
+            score       INTEGER,
+            code        TEXT,
+            track       BLOB,
+            igc         BLOB,
+            profile     BLOB,
+            header      TEXT,
+            validation  TEXT,
+            igc_id      INTEGER REFERENCES igc_files(igc_id),
+            fix_start   INTEGER,
+            fix_end     INTEGER,
+            altitude_source TEXT,
+            alt_max     INTEGER
+        );
+        INSERT INTO flights_new (flight_id, wing_id, takeoff_id, landing_id, hash, date, duration, distance, score, code, track, igc, profile, header, validation, igc_id, fix_start, fix_end, altitude_source, alt_max)
+            SELECT flight_id, wing_id, takeoff_id, landing_id, hash, date, duration, distance, score, code, track, igc, profile, header, validation, igc_id, fix_start, fix_end, altitude_source, alt_max FROM flights;
+        DROP TABLE flights;
+        ALTER TABLE flights_new RENAME TO flights;",
+    )?;
+
+    Ok(())
+}
+
+// Version 5 : flights.hash becomes the hash of the fixes instead of the G record, it is no longer unique so two
+// flights found identical can both be kept. Hashes of the G record are cleared, they are computed again from the stored IGC.
+fn content_hash(tx: &Transaction) -> Result<()>
+{
+    tx.execute_batch(
+        "CREATE TABLE flights_new (
+            flight_id   INTEGER PRIMARY KEY,
+            wing_id     INTEGER REFERENCES wings(wing_id),
+            takeoff_id  INTEGER REFERENCES sites(site_id),
+            landing_id  INTEGER REFERENCES sites(site_id),
+            hash        TEXT,
+            date        DATE NOT NULL,
+            duration    INTEGER,
+            distance    INTEGER,
+            score       INTEGER,
+            code        TEXT,
+            track       BLOB,
+            igc         BLOB,
+            profile     BLOB,
+            header      TEXT,
+            validation  TEXT,
+            igc_id      INTEGER REFERENCES igc_files(igc_id),
+            fix_start   INTEGER,
+            fix_end     INTEGER,
+            altitude_source TEXT,
+            alt_max     INTEGER,
+            takeoff_time INTEGER,
+            landing_time INTEGER
+        );
+        INSERT INTO flights_new (flight_id, wing_id, takeoff_id, landing_id, date, duration, distance, score, code, track, igc, profile, header, validation, igc_id, fix_start, fix_end, altitude_source, alt_max)
+            SELECT flight_id, wing_id, takeoff_id, landing_id, date, duration, distance, score, code, track, igc, profile, header, validation, igc_id, fix_start, fix_end, altitude_source, alt_max FROM flights;
+        DROP TABLE flights;
+        ALTER TABLE flights_new RENAME TO flights;
+        CREATE INDEX flights_hash ON flights (hash);
+        CREATE INDEX flights_date ON flights (date);",
+    )?;
+
+    Ok(())
+}
+
+// Version 6 : marker of the flights whose thermals, glide and wind are stored, an empty result is then a real one.
+// Flights stored before are left unmarked, their analysis may be partial and is computed again once.
+fn analysed(tx: &Transaction) -> Result<()>
+{
+    tx.execute("ALTER TABLE flights ADD COLUMN analysed BOOLEAN", ())?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Database file of its own for each test, in the temp folder
+    fn database(name: &str) -> String
+    {
+        let path = std::env::temp_dir().join(format!("flight_log_{}_{}.db", name, std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+
+        fs::remove_file(&path).unwrap_or_default();
+        for version in 0..latest_version()
+        {
+            fs::remove_file(format!("{}.v{}.bak", path, version)).unwrap_or_default();
+        }
+
+        path
+    }
+
+    fn columns(db_conn: &Connection, table: &str) -> Vec<(String, String)>
+    {
+        let mut stmt = db_conn.prepare(&format!("PRAGMA table_info({})", table)).unwrap();
+        let columns = stmt.query_map([], |row| Ok((row.get(1)?, row.get(2)?))).unwrap();
+
+        columns.map(|c| c.unwrap()).collect()
+    }
+
+    // Database as created by the first release, before any user_version
+    fn baseline_database(path: &str)
+    {
+        let db_conn = Connection::open(path).unwrap();
+        db_conn.execute_batch(
+            "CREATE TABLE flights (
+                flight_id   INTEGER PRIMARY KEY,
+                wing_id     INTEGER REFERENCES wings(wing_id),
+                takeoff_id  INTEGER REFERENCES sites(site_id),
+                landing_id  INTEGER REFERENCES sites(site_id),
+                hash        BLOB UNIQUE,
+                date        DATE NOT NULL,
+                duration    INTEGER,
+                distance    INTEGER,
+                score       INTERGER,
+                code        TEXT,
+                track       BLOB,
+                igc         BLOB,
+                profile     BLOB
+            );
+            CREATE TABLE sites (site_id INTEGER PRIMARY KEY, name TEXT, lat FLOAT, long FLOAT, alt INTEGER, info TEXT);
+            CREATE TABLE wings (wing_id INTEGER PRIMARY KEY, name TEXT UNIQUE, info TEXT, def BOOLEAN);
+            CREATE TABLE tags (tag_id INTEGER PRIMARY KEY, name TEXT UNIQUE);
+            CREATE TABLE tag_asso (asso_id INTEGER PRIMARY KEY, asso_tag_id INTEGER REFERENCES tags(tag_id), asso_flight_id INTEGER REFERENCES flights(flight_id));
+            INSERT INTO wings (wing_id,name,info,def) VALUES (0,'default','',1);
+            INSERT INTO wings (name,info) VALUES ('Ozone Rush 6','');
+            INSERT INTO sites (name,lat,long,alt,info) VALUES ('Planfait',45.85,6.25,1515,'');
+            INSERT INTO tags (name) VALUES ('cross');
+            INSERT INTO flights (wing_id,takeoff_id,landing_id,hash,date,duration,distance,score,code)
+                VALUES (1,1,1,'abc','2024-08-12',95,42000,51000,'od');
+            INSERT INTO tag_asso (asso_tag_id,asso_flight_id) VALUES (1,1);",
+        ).unwrap();
+    }
+
+    #[test]
+    fn upgrade_baseline_database()
+    {
+        let path = database("baseline");
+        baseline_database(&path);
+
+        assert_eq!(migrate(&path).unwrap(), 0);
+
+        let db_conn = Connection::open(&path).unwrap();
+        assert_eq!(version(&db_conn).unwrap(), latest_version());
+
+        // Flight kept with its new columns empty, score typo fixed
+        let flights = columns(&db_conn, "flights");
+        assert!(flights.contains(&("score".to_string(), "INTEGER".to_string())));
+        for column in ["header", "validation", "igc_id", "fix_start", "fix_end", "altitude_source", "alt_max", "takeoff_time", "landing_time", "analysed"]
+        {
+            assert!(flights.iter().any(|(c, _)| c == column), "missing column {}", column);
+        }
+
+        let (date, score, alt_max): (String, u32, Option<u32>) = db_conn
+            .query_row("SELECT date, score, alt_max FROM flights WHERE flight_id=1", [], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap();
+        assert_eq!((date.as_str(), score, alt_max), ("2024-08-12", 51000, None));
+
+        // G record hash cleared, the same hash can be stored twice
+        let hash: Option<String> = db_conn.query_row("SELECT hash FROM flights WHERE flight_id=1", [], |row| row.get(0)).unwrap();
+        assert_eq!(hash, None);
+        db_conn.execute_batch("INSERT INTO flights (hash,date) VALUES ('abc','2024-08-12'); INSERT INTO flights (hash,date) VALUES ('abc','2024-08-12');").unwrap();
+
+        let nb_asso: u32 = db_conn.query_row("SELECT count(*) FROM tag_asso INNER JOIN flights ON asso_flight_id=flight_id", [], |row| row.get(0)).unwrap();
+        assert_eq!(nb_asso, 1);
+
+        for table in ["igc_files", "scores", "thermals", "glides", "winds", "violations", "settings"]
+        {
+            assert!(!columns(&db_conn, table).is_empty(), "missing table {}", table);
+        }
+
+        // A single backup, the database as it was
+        let backup = Connection::open(format!("{}.v0.bak", path)).unwrap();
+        assert_eq!(version(&backup).unwrap(), 0);
+        assert!(!columns(&backup, "flights").iter().any(|(c, _)| c == "alt_max"));
+        for version in 1..latest_version()
+        {
+            assert!(!Path::new(&format!("{}.v{}.bak", path, version)).exists());
+        }
+    }
+
+    #[test]
+    fn new_database()
+    {
+        let path = database("new");
+
+        assert_eq!(migrate(&path).unwrap(), 0);
+
+        let db_conn = Connection::open(&path).unwrap();
+        assert_eq!(version(&db_conn).unwrap(), latest_version());
+
+        let default_wing: String = db_conn.query_row("SELECT name FROM wings WHERE def=1", [], |row| row.get(0)).unwrap();
+        assert_eq!(default_wing, "default");
+
+        // Nothing to back up, before or after the baseline step
+        for version in 0..latest_version()
+        {
+            assert!(!Path::new(&format!("{}.v{}.bak", path, version)).exists());
+        }
+    }
+
+    #[test]
+    fn up_to_date_database()
+    {
+        let path = database("up_to_date");
+
+        migrate(&path).unwrap();
+        assert_eq!(migrate(&path).unwrap(), latest_version());
+        assert!(!Path::new(&format!("{}.v{}.bak", path, latest_version())).exists());
+
+        // A database from a newer version is left alone
+        Connection::open(&path).unwrap().pragma_update(None, "user_version", latest_version() + 1).unwrap();
+        assert!(migrate(&path).is_err());
+    }
+}
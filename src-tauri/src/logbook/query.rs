@@ -0,0 +1,210 @@
+use rusqlite::{ParamsFromIter, params_from_iter, types::Value};
+
+// WHERE clause of a statement. Column names are static strings of the code,
+// every value coming from the user is bound as a parameter.
+#[derive(Clone,Debug,Default)]
+pub struct Condition
+{
+    clauses: Vec<String>,
+    params: Vec<Value>,
+    order: Option<&'static str>,
+}
+
+impl Condition
+{
+    // Matches every row
+    pub fn all() -> Condition
+    {
+        Condition::default()
+    }
+
+    pub fn eq<V: Into<Value>>(self, column: &'static str, value: V) -> Condition
+    {
+        self.compare(column, "=", value.into())
+    }
+
+    pub fn ge<V: Into<Value>>(self, column: &'static str, value: V) -> Condition
+    {
+        self.compare(column, ">=", value.into())
+    }
+
+    pub fn le<V: Into<Value>>(self, column: &'static str, value: V) -> Condition
+    {
+        self.compare(column, "<=", value.into())
+    }
+
+    // Column containing the text, case insensitive
+    pub fn like(mut self, column: &'static str, text: &str) -> Condition
+    {
+        let pattern = text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+
+        self.clauses.push(format!("{} LIKE ? ESCAPE '\\'", column));
+        self.params.push(Value::Text(format!("%{}%", pattern)));
+        self
+    }
+
+    // Column equal to one of the ids, an empty list matches nothing
+    pub fn any_of(mut self, column: &'static str, ids: &[u32]) -> Condition
+    {
+        if ids.is_empty()
+        {
+            self.clauses.push("0".to_string());
+            return self;
+        }
+
+        self.clauses.push(format!("{} IN ({})", column, vec!["?"; ids.len()].join(",")));
+        self.params.extend(ids.iter().map(|id| Value::from(*id)));
+        self
+    }
+
+    // Column found in the rows of another table matching the condition
+    pub fn within(mut self, column: &'static str, other_column: &'static str, table: &'static str, condition: Condition) -> Condition
+    {
+        self.clauses.push(format!("{} IN (SELECT {} FROM {} WHERE {})", column, other_column, table, condition.clause()));
+        self.params.extend(condition.params);
+        self
+    }
+
+    // At least one of the conditions matches
+    pub fn any(mut self, conditions: Vec<Condition>) -> Condition
+    {
+        let clauses: Vec<String> = conditions.iter().map(|c| format!("({})", c.clause())).collect();
+
+        self.clauses.push(format!("({})", clauses.join(" OR ")));
+        for condition in conditions
+        {
+            self.params.extend(condition.params);
+        }
+        self
+    }
+
+    // Only used by SELECT statements
+    pub fn order_by(mut self, order: &'static str) -> Condition
+    {
+        self.order = Some(order);
+        self
+    }
+
+    fn compare(mut self, column: &'static str, operator: &str, value: Value) -> Condition
+    {
+        self.clauses.push(format!("{}{}?", column, operator));
+        self.params.push(value);
+        self
+    }
+
+    fn clause(&self) -> String
+    {
+        if self.clauses.is_empty()
+        {
+            return "1".to_string();
+        }
+
+        self.clauses.join(" AND ")
+    }
+
+    // Text following WHERE
+    pub fn sql(&self) -> String
+    {
+        match self.order {
+            None => self.clause(),
+            Some(order) => format!("{} ORDER BY {}", self.clause(), order),
+        }
+    }
+
+    pub fn params(&self) -> ParamsFromIter<&Vec<Value>>
+    {
+        params_from_iter(&self.params)
+    }
+}
+
+// SET part of an UPDATE statement
+#[derive(Clone,Debug,Default)]
+pub struct Changes
+{
+    columns: Vec<&'static str>,
+    params: Vec<Value>,
+}
+
+impl Changes
+{
+    pub fn new() -> Changes
+    {
+        Changes::default()
+    }
+
+    pub fn set<V: Into<Value>>(mut self, column: &'static str, value: V) -> Changes
+    {
+        self.columns.push(column);
+        self.params.push(value.into());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.columns.is_empty()
+    }
+
+    // Statement with the changes bound first then the condition
+    pub fn update(&self, table: &'static str, condition: &Condition) -> (String, ParamsFromIter<Vec<Value>>)
+    {
+        let set: Vec<String> = self.columns.iter().map(|c| format!("{}=?", c)).collect();
+        let sql = format!("UPDATE {} SET {} WHERE {}", table, set.join(", "), condition.clause());
+        let params: Vec<Value> = self.params.iter().chain(condition.params.iter()).cloned().collect();
+
+        (sql, params_from_iter(params))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use rusqlite::Connection;
+    use super::*;
+
+    fn names(db_conn: &Connection, condition: &Condition) -> Vec<String>
+    {
+        let sql = format!("SELECT name FROM sites WHERE {}", condition.sql());
+        let mut stmt = db_conn.prepare(&sql).unwrap();
+        let rows = stmt.query_map(condition.params(), |row| row.get(0)).unwrap();
+
+        rows.map(|r| r.unwrap()).collect()
+    }
+
+    fn database() -> Connection
+    {
+        let db_conn = Connection::open_in_memory().unwrap();
+        db_conn.execute_batch(
+            "CREATE TABLE sites (site_id INTEGER PRIMARY KEY, name TEXT, alt INTEGER);
+            INSERT INTO sites (name, alt) VALUES ('Planfait', 1515), ('Col de la Forclaz', 1250), ('100%_sud', 800);",
+        ).unwrap();
+
+        db_conn
+    }
+
+    #[test]
+    fn values_are_bound()
+    {
+        let db_conn = database();
+
+        assert_eq!(names(&db_conn, &Condition::all().like("name", "' OR 1=1 --")), Vec::<String>::new());
+        assert_eq!(names(&db_conn, &Condition::all().like("name", "%")), vec!["100%_sud"]);
+        assert_eq!(names(&db_conn, &Condition::all().like("name", "FORCLAZ")), vec!["Col de la Forclaz"]);
+        assert_eq!(names(&db_conn, &Condition::all().ge("alt", 1000).order_by("alt DESC")), vec!["Planfait", "Col de la Forclaz"]);
+        assert_eq!(names(&db_conn, &Condition::all().any_of("site_id", &[])), Vec::<String>::new());
+    }
+
+    #[test]
+    fn combined_conditions()
+    {
+        let db_conn = database();
+        let condition = Condition::all()
+            .any(vec![Condition::all().eq("name", "Planfait".to_string()), Condition::all().le("alt", 900)])
+            .within("site_id", "site_id", "sites", Condition::all().any_of("site_id", &[1, 3]));
+
+        assert_eq!(names(&db_conn, &condition.order_by("site_id")), vec!["Planfait", "100%_sud"]);
+
+        let (sql, params) = Changes::new().set("alt", 1520).update("sites", &Condition::all().eq("name", "Planfait".to_string()));
+        db_conn.execute(&sql, params).unwrap();
+        assert_eq!(names(&db_conn, &Condition::all().eq("alt", 1520)), vec!["Planfait"]);
+    }
+}
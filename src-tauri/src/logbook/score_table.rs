@@ -0,0 +1,110 @@
+use rusqlite::Connection;
+use anyhow::Result;
+use serde::{Serialize, Deserialize};
+
+use super::query::Condition;
+
+#[derive(Clone,Debug,Serialize,Deserialize)]
+pub struct ScoreTable
+{
+    pub score_id: u32,
+    pub flight_id: u32,
+    pub rules: String,
+    pub score: u32,
+    pub code: String,
+    pub track: Option<String>,
+}
+
+impl ScoreTable
+{
+    pub fn store(db_conn: &Connection, score: ScoreTable) -> Result<()>
+    {
+        let track: Option<Vec<u8>> = match score.track {
+            None => None,
+            Some(t) => Some(zstd::encode_all(t.as_bytes(), 5)?)
+        };
+
+        // A flight has only one score per rule set, re-scoring replaces it
+        db_conn.execute(
+            "INSERT OR REPLACE INTO scores (flight_id, rules, score, code, track)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+                (
+                    score.flight_id,
+                    score.rules,
+                    score.score,
+                    score.code,
+                    track,
+                ),
+            )?;
+
+        Ok(())
+    }
+
+    pub fn get(db_conn: &Connection, flight_id: u32, rules: &str) -> Result<ScoreTable>
+    {
+        let mut stmt: rusqlite::Statement<'_> = db_conn.prepare("SELECT score_id, flight_id, rules, score, code, track FROM scores WHERE flight_id=?1 AND rules=?2")?;
+
+        let score = stmt
+            .query_row((flight_id, rules), |row| {
+                let track: Option<Vec<u8>> = row.get(5)?;
+                let track = match track {
+                    None => None,
+                    Some(t) => Some(String::from_utf8(zstd::decode_all(t.as_slice()).unwrap()).unwrap()),
+                };
+
+                Ok(ScoreTable {
+                    score_id: row.get(0)?,
+                    flight_id: row.get(1)?,
+                    rules: row.get(2)?,
+                    score: row.get(3)?,
+                    code: row.get(4)?,
+                    track,
+                })
+            })?;
+
+        Ok(score)
+    }
+
+    pub fn delete(db_conn: &Connection, condition: &Condition) -> Result<()>
+    {
+        let mut sql = "DELETE FROM scores WHERE ".to_string();
+        sql.push_str(&condition.sql());
+        db_conn.execute(&sql, condition.params())?;
+
+        Ok(())
+    }
+
+    pub fn select(db_conn: &Connection, condition: &Condition) -> Result<Vec<ScoreTable>>
+    {
+        let mut scores: Vec<ScoreTable> = Vec::new();
+        let mut sql = "SELECT score_id, flight_id, rules, score, code FROM scores WHERE ".to_string();
+        sql.push_str(&condition.sql());
+
+        let mut stmt = db_conn.prepare(&sql)?;
+
+        let rows = stmt
+            .query_map(condition.params(), |row| {
+                Ok(ScoreTable {
+                    score_id: row.get(0)?,
+                    flight_id: row.get(1)?,
+                    rules: row.get(2)?,
+                    score: row.get(3)?,
+                    code: row.get(4)?,
+                    track: None,
+                })
+            })?;
+
+        for score in rows {
+            if let Ok(s) = score {
+                scores.push(s)
+            }
+        }
+
+        Ok(scores)
+    }
+
+    pub fn get_by_flight(db_conn: &Connection, flight_id: u32) -> Result<Vec<ScoreTable>>
+    {
+        ScoreTable::select(db_conn, &Condition::all().eq("flight_id", flight_id))
+    }
+}
@@ -0,0 +1,27 @@
+use rusqlite::{Connection, OptionalExtension};
+use anyhow::Result;
+
+pub const DEM_FOLDER: &str = "dem_folder";
+pub const AIRSPACE_FILE: &str = "airspace_file";
+
+// User settings, one value per key
+pub struct SettingTable;
+
+impl SettingTable
+{
+    pub fn get(db_conn: &Connection, key: &str) -> Result<Option<String>>
+    {
+        let mut stmt = db_conn.prepare("SELECT value FROM settings WHERE key=?1")?;
+
+        let value: Option<String> = stmt.query_row([key], |row| row.get(0)).optional()?;
+
+        Ok(value)
+    }
+
+    pub fn set(db_conn: &Connection, key: &str, value: &str) -> Result<()>
+    {
+        db_conn.execute("INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)", (key, value))?;
+
+        Ok(())
+    }
+}
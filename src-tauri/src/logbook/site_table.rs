@@ -1,12 +1,13 @@
 use geoutils::Location;
 use rusqlite::Connection;
 use anyhow::{Result, bail};
+use serde::{Serialize, Deserialize};
 
-use super::{DATABASE_PATH, IDListe, FlightPoint};
+use super::{FlightPoint, query::{Condition, Changes}};
 
 const DISTANCE_DETECTION: f64 = 200.0;
 
-#[derive(Clone)]
+#[derive(Clone,Debug,Serialize,Deserialize)]
 pub struct SiteTable
 {
     pub site_id: u32,
@@ -19,27 +20,8 @@ pub struct SiteTable
 
 impl SiteTable
 {
-    pub fn create() -> Result<()>{
-        let db_conn = Connection::open(DATABASE_PATH)?;
-
-        db_conn.execute(
-            "CREATE TABLE IF NOT EXISTS sites (
-                site_id     INTEGER PRIMARY KEY,
-                name        TEXT,
-                lat         FLOAT,
-                long        FLOAT,
-                alt         INTEGER,
-                info        TEXT
-            );",
-            (), // empty list of parameters.
-        )?;
-        db_conn.close().unwrap_or_default();
-        Ok(())
-    }
-
-    pub fn store(site: SiteTable) -> Result<()>
+    pub fn store(db_conn: &Connection, site: SiteTable) -> Result<()>
     {
-        let db_conn = Connection::open(DATABASE_PATH)?;
         db_conn.execute(
             "INSERT INTO sites (name, lat, long, alt, info)
                 VALUES (?1, ?2, ?3, ?4, ?5)",
@@ -52,13 +34,11 @@ impl SiteTable
                 ),
             )?;
 
-        db_conn.close().unwrap_or_default();
         Ok(())
     }
 
-    pub fn get(id: u32) -> Result<SiteTable>
+    pub fn get(db_conn: &Connection, id: u32) -> Result<SiteTable>
     {
-        let db_conn = Connection::open(DATABASE_PATH)?;
         let mut stmt: rusqlite::Statement<'_> = db_conn.prepare("SELECT * FROM sites WHERE site_id=?1")?;
 
         let site = stmt
@@ -76,43 +56,38 @@ impl SiteTable
         Ok(site)
     }
 
-    pub fn delete(condition: String) -> Result<()>
+    pub fn delete(db_conn: &Connection, condition: &Condition) -> Result<()>
     {
         let mut sql = "DELETE FROM sites WHERE ".to_string();
-        sql.push_str(&condition);
-        let db_conn = Connection::open(DATABASE_PATH)?;
-        db_conn.execute(&sql,())?;
-
-        db_conn.close().unwrap_or_default();
+        sql.push_str(&condition.sql());
+        db_conn.execute(&sql, condition.params())?;
 
         Ok(())
     }
 
-    pub fn update(set: String, condition: String) -> Result<()>
+    pub fn update(db_conn: &Connection, changes: &Changes, condition: &Condition) -> Result<()>
     {
-        let mut sql = "UPDATE sites SET ".to_string();
-        sql.push_str(&set);
-        sql.push_str(" WHERE ");
-        sql.push_str(&condition);
-        let db_conn = Connection::open(DATABASE_PATH)?;
-        db_conn.execute(&sql,())?;
+        if changes.is_empty()
+        {
+            return Ok(());
+        }
 
-        db_conn.close().unwrap_or_default();
+        let (sql, params) = changes.update("sites", condition);
+        db_conn.execute(&sql, params)?;
 
         Ok(())
     }
 
-    pub fn select(condition: String) -> Result<Vec<SiteTable>>
+    pub fn select(db_conn: &Connection, condition: &Condition) -> Result<Vec<SiteTable>>
     {
-        let db_conn = Connection::open(DATABASE_PATH)?;
         let mut sites: Vec<SiteTable> = Vec::new();
         let mut sql = "SELECT * FROM sites WHERE ".to_string();
-        sql.push_str(&condition);
+        sql.push_str(&condition.sql());
 
         let mut stmt = db_conn.prepare(&sql)?;
 
         let rows = stmt
-            .query_map([], |row| {
+            .query_map(condition.params(), |row| {
                 Ok(SiteTable {
                     site_id: row.get(0)?,
                     name: row.get(1)?,
@@ -132,30 +107,21 @@ impl SiteTable
         Ok(sites)
     }
 
-    pub fn select_all() -> Result<Vec<SiteTable>>
+    pub fn select_all(db_conn: &Connection) -> Result<Vec<SiteTable>>
     {
-        SiteTable::select("1".to_string())
+        SiteTable::select(db_conn, &Condition::all())
     }
 
-    pub fn search(search: String) -> Result<IDListe>
+    pub fn search(db_conn: &Connection, search: &str) -> Result<Vec<u32>>
     {
-        let sites = SiteTable::select_all()?;
-        let mut site_ids:IDListe = IDListe { list: Vec::new() };
-
-        for site in sites
-        {
-            if site.name.to_lowercase().contains(&search.to_lowercase())
-            {
-                site_ids.list.push(site.site_id);
-            }
-        }
+        let sites = SiteTable::select(db_conn, &Condition::all().like("name", search))?;
 
-        Ok(site_ids)
+        Ok(sites.iter().map(|s| s.site_id).collect())
     }
 
-    pub fn site_detection(takeoff: FlightPoint, landing: FlightPoint) -> Result<(Option<SiteTable>, Option<SiteTable>)>
+    pub fn site_detection(db_conn: &Connection, takeoff: FlightPoint, landing: FlightPoint) -> Result<(Option<SiteTable>, Option<SiteTable>)>
     {
-        let sites = Self::select_all()?;
+        let sites = Self::select_all(db_conn)?;
         let mut res_takeoff: Option<SiteTable> = None;
         let mut res_landing: Option<SiteTable> = None;
 
@@ -187,9 +153,8 @@ impl SiteTable
         return Ok((res_takeoff,res_landing));
     }
 
-    pub fn last_site_id() -> Result<u32>
+    pub fn last_site_id(db_conn: &Connection) -> Result<u32>
     {
-        let db_conn = Connection::open(DATABASE_PATH)?;
         let mut stmt = db_conn.prepare("SELECT site_id FROM sites ORDER BY site_id DESC LIMIT 1;")?;
 
         let id = stmt.query_row([], | row | Ok(row.get(0).unwrap_or(0)))?;
@@ -1,9 +1,10 @@
 use rusqlite::Connection;
 use anyhow::{Result, bail};
+use serde::{Serialize, Deserialize};
 
-use super::{DATABASE_PATH, IDListe};
-
+use super::query::{Condition, Changes};
 
+#[derive(Clone,Debug,Serialize,Deserialize)]
 pub struct TagTable
 {
     pub tag_id: u32,
@@ -12,34 +13,8 @@ pub struct TagTable
 
 impl TagTable
 {
-    pub fn create() -> Result<()>{
-        let db_conn = Connection::open(DATABASE_PATH)?;
-
-        db_conn.execute("PRAGMA foreign_keys = ON;",())?;
-
-        db_conn.execute(
-            "CREATE TABLE IF NOT EXISTS tags (
-                tag_id      INTEGER PRIMARY KEY,
-                name        TEXT UNIQUE
-            );",
-            (), // empty list of parameters.
-        )?;
-
-        db_conn.execute(
-            "CREATE TABLE IF NOT EXISTS tag_asso (
-                asso_id         INTEGER PRIMARY KEY,
-                asso_tag_id     INTEGER REFERENCES tags(tag_id),
-                asso_flight_id  INTEGER REFERENCES flights(flight_id) 
-            );",
-            (), // empty list of parameters.
-        )?;
-        db_conn.close().unwrap_or_default();
-        Ok(())
-    }
-
-    pub fn store(tag: TagTable) -> Result<()>
+    pub fn store(db_conn: &Connection, tag: TagTable) -> Result<()>
     {
-        let db_conn = Connection::open(DATABASE_PATH)?;
         db_conn.execute(
             "INSERT INTO tags (name)
                 VALUES (?1)",
@@ -48,13 +23,11 @@ impl TagTable
                 ),
             )?;
 
-        db_conn.close().unwrap_or_default();
         Ok(())
     }
 
-    pub fn get(id: u32) -> Result<TagTable>
+    pub fn get(db_conn: &Connection, id: u32) -> Result<TagTable>
     {
-        let db_conn = Connection::open(DATABASE_PATH)?;
         let mut stmt: rusqlite::Statement<'_> = db_conn.prepare("SELECT * FROM tags WHERE tag_id=?1")?;
 
         let tag = stmt
@@ -68,43 +41,38 @@ impl TagTable
         Ok(tag)
     }
 
-    pub fn delete(condition: String) -> Result<()>
+    pub fn delete(db_conn: &Connection, condition: &Condition) -> Result<()>
     {
         let mut sql = "DELETE FROM tags WHERE ".to_string();
-        sql.push_str(&condition);
-        let db_conn = Connection::open(DATABASE_PATH)?;
-        db_conn.execute(&sql,())?;
-
-        db_conn.close().unwrap_or_default();
+        sql.push_str(&condition.sql());
+        db_conn.execute(&sql, condition.params())?;
 
         Ok(())
     }
 
-    pub fn update(set: String, condition: String) -> Result<()>
+    pub fn update(db_conn: &Connection, changes: &Changes, condition: &Condition) -> Result<()>
     {
-        let mut sql = "UPDATE tags SET ".to_string();
-        sql.push_str(&set);
-        sql.push_str(" WHERE ");
-        sql.push_str(&condition);
-        let db_conn = Connection::open(DATABASE_PATH)?;
-        db_conn.execute(&sql,())?;
+        if changes.is_empty()
+        {
+            return Ok(());
+        }
 
-        db_conn.close().unwrap_or_default();
+        let (sql, params) = changes.update("tags", condition);
+        db_conn.execute(&sql, params)?;
 
         Ok(())
     }
 
-    pub fn select(condition: String) -> Result<Vec<TagTable>>
+    pub fn select(db_conn: &Connection, condition: &Condition) -> Result<Vec<TagTable>>
     {
-        let db_conn = Connection::open(DATABASE_PATH)?;
         let mut tags: Vec<TagTable> = Vec::new();
         let mut sql = "SELECT * FROM tags WHERE ".to_string();
-        sql.push_str(&condition);
+        sql.push_str(&condition.sql());
 
         let mut stmt = db_conn.prepare(&sql)?;
 
         let rows = stmt
-            .query_map([], |row| {
+            .query_map(condition.params(), |row| {
                 Ok(TagTable {
                     tag_id: row.get(0)?,
                     name: row.get(1)?,
@@ -120,9 +88,8 @@ impl TagTable
         Ok(tags)
     }
 
-    pub fn associate(flight_id: u32, tag_id: u32) -> Result<()>
+    pub fn associate(db_conn: &Connection, flight_id: u32, tag_id: u32) -> Result<()>
     {
-        let db_conn = Connection::open(DATABASE_PATH)?;
         db_conn.execute(
             "INSERT OR IGNORE INTO tag_asso (asso_tag_id, asso_flight_id)
                 VALUES (?1, ?2)",
@@ -135,24 +102,41 @@ impl TagTable
         Ok(())
     }
 
-    pub fn select_all() -> Result<Vec<TagTable>>
+    // Associations matching the condition on asso_tag_id and asso_flight_id
+    pub fn dissociate(db_conn: &Connection, condition: &Condition) -> Result<()>
     {
-        TagTable::select("1".to_string())
+        let mut sql = "DELETE FROM tag_asso WHERE ".to_string();
+        sql.push_str(&condition.sql());
+        db_conn.execute(&sql, condition.params())?;
+
+        Ok(())
     }
 
-    pub fn search(search: String) -> Result<IDListe>
+    pub fn get_by_flight(db_conn: &Connection, flight_id: u32) -> Result<Vec<TagTable>>
     {
-        let tags = TagTable::select("1".to_string())?;
-        let mut tag_ids:IDListe = IDListe { list: Vec::new() };
+        TagTable::select(db_conn, &Condition::all()
+            .within("tag_id", "asso_tag_id", "tag_asso", Condition::all().eq("asso_flight_id", flight_id))
+            .order_by("name"))
+    }
 
-        for tag in tags
-        {
-            if tag.name.to_lowercase().contains(&search.to_lowercase())
-            {
-                tag_ids.list.push(tag.tag_id);
-            }
-        }
+    pub fn select_all(db_conn: &Connection) -> Result<Vec<TagTable>>
+    {
+        TagTable::select(db_conn, &Condition::all())
+    }
+
+    pub fn search(db_conn: &Connection, search: &str) -> Result<Vec<u32>>
+    {
+        let tags = TagTable::select(db_conn, &Condition::all().like("name", search))?;
+
+        Ok(tags.iter().map(|t| t.tag_id).collect())
+    }
+
+    pub fn last_tag_id(db_conn: &Connection) -> Result<u32>
+    {
+        let mut stmt = db_conn.prepare("SELECT tag_id FROM tags ORDER BY tag_id DESC LIMIT 1;")?;
+
+        let id = stmt.query_row([], | row | Ok(row.get(0).unwrap_or(0)))?;
 
-        Ok(tag_ids)
+        Ok(id)
     }
 }
\ No newline at end of file
@@ -0,0 +1,117 @@
+use rusqlite::Connection;
+use anyhow::Result;
+use serde::{Serialize, Deserialize};
+
+use crate::flight_track::thermal::Thermal;
+use super::query::Condition;
+
+#[derive(Clone,Debug,Serialize,Deserialize)]
+pub struct ThermalTable
+{
+    pub thermal_id: u32,
+    pub flight_id: u32,
+    pub entry: i64,// timestamp
+    pub exit: i64,// timestamp
+    pub entry_alt: u32,
+    pub exit_alt: u32,
+    pub alt_gain: i32,
+    pub avg_climb: f32,
+    pub max_climb: f32,
+    pub direction: String,
+    pub lat: f32,
+    pub long: f32,
+}
+
+impl From<&Thermal> for ThermalTable {
+    fn from(thermal: &Thermal) -> Self {
+        ThermalTable {
+            thermal_id: 0,
+            flight_id: 0,
+            entry: thermal.entry.timestamp(),
+            exit: thermal.exit.timestamp(),
+            entry_alt: thermal.entry_alt,
+            exit_alt: thermal.exit_alt,
+            alt_gain: thermal.alt_gain,
+            avg_climb: thermal.avg_climb,
+            max_climb: thermal.max_climb,
+            direction: thermal.direction.to_string(),
+            lat: thermal.lat,
+            long: thermal.long,
+        }
+    }
+}
+
+impl ThermalTable
+{
+    pub fn store(db_conn: &Connection, thermal: ThermalTable) -> Result<()>
+    {
+        db_conn.execute(
+            "INSERT INTO thermals (flight_id, entry, exit, entry_alt, exit_alt, alt_gain, avg_climb, max_climb, direction, lat, long)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                (
+                    thermal.flight_id,
+                    thermal.entry,
+                    thermal.exit,
+                    thermal.entry_alt,
+                    thermal.exit_alt,
+                    thermal.alt_gain,
+                    thermal.avg_climb,
+                    thermal.max_climb,
+                    thermal.direction,
+                    thermal.lat,
+                    thermal.long,
+                ),
+            )?;
+
+        Ok(())
+    }
+
+    pub fn delete(db_conn: &Connection, condition: &Condition) -> Result<()>
+    {
+        let mut sql = "DELETE FROM thermals WHERE ".to_string();
+        sql.push_str(&condition.sql());
+        db_conn.execute(&sql, condition.params())?;
+
+        Ok(())
+    }
+
+    pub fn select(db_conn: &Connection, condition: &Condition) -> Result<Vec<ThermalTable>>
+    {
+        let mut thermals: Vec<ThermalTable> = Vec::new();
+        let mut sql = "SELECT thermal_id, flight_id, entry, exit, entry_alt, exit_alt, alt_gain, avg_climb, max_climb, direction, lat, long FROM thermals WHERE ".to_string();
+        sql.push_str(&condition.sql());
+
+        let mut stmt = db_conn.prepare(&sql)?;
+
+        let rows = stmt
+            .query_map(condition.params(), |row| {
+                Ok(ThermalTable {
+                    thermal_id: row.get(0)?,
+                    flight_id: row.get(1)?,
+                    entry: row.get(2)?,
+                    exit: row.get(3)?,
+                    entry_alt: row.get(4)?,
+                    exit_alt: row.get(5)?,
+                    alt_gain: row.get(6)?,
+                    avg_climb: row.get(7)?,
+                    max_climb: row.get(8)?,
+                    direction: row.get(9)?,
+                    lat: row.get(10)?,
+                    long: row.get(11)?,
+                })
+            })?;
+
+        for thermal in rows {
+            if let Ok(t) = thermal {
+                thermals.push(t)
+            }
+        }
+
+        Ok(thermals)
+    }
+
+    pub fn get_by_flight(db_conn: &Connection, flight_id: u32) -> Result<Vec<ThermalTable>>
+    {
+        ThermalTable::select(db_conn, &Condition::all().eq("flight_id", flight_id).order_by("entry"))
+    }
+}
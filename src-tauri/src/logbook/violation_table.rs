@@ -0,0 +1,109 @@
+use rusqlite::Connection;
+use anyhow::Result;
+use serde::{Serialize, Deserialize};
+
+use crate::airspace::Violation;
+use super::query::Condition;
+
+#[derive(Clone,Debug,Serialize,Deserialize)]
+pub struct ViolationTable
+{
+    pub violation_id: u32,
+    pub flight_id: u32,
+    pub airspace: String,
+    pub class: String,
+    pub limits: String,// floor - ceiling
+    pub start: i64,// timestamp
+    pub end: i64,// timestamp
+    pub alt: u32,// highest altitude inside
+    pub lat: f32,
+    pub long: f32,
+}
+
+impl From<&Violation> for ViolationTable {
+    fn from(violation: &Violation) -> Self {
+        ViolationTable {
+            violation_id: 0,
+            flight_id: 0,
+            airspace: violation.airspace.clone(),
+            class: violation.class.clone(),
+            limits: violation.limits.clone(),
+            start: violation.start.timestamp(),
+            end: violation.end.timestamp(),
+            alt: violation.alt,
+            lat: violation.lat,
+            long: violation.long,
+        }
+    }
+}
+
+impl ViolationTable
+{
+    pub fn store(db_conn: &Connection, violation: ViolationTable) -> Result<()>
+    {
+        db_conn.execute(
+            "INSERT INTO violations (flight_id, airspace, class, limits, start, end, alt, lat, long)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                (
+                    violation.flight_id,
+                    violation.airspace,
+                    violation.class,
+                    violation.limits,
+                    violation.start,
+                    violation.end,
+                    violation.alt,
+                    violation.lat,
+                    violation.long,
+                ),
+            )?;
+
+        Ok(())
+    }
+
+    pub fn delete(db_conn: &Connection, condition: &Condition) -> Result<()>
+    {
+        let mut sql = "DELETE FROM violations WHERE ".to_string();
+        sql.push_str(&condition.sql());
+        db_conn.execute(&sql, condition.params())?;
+
+        Ok(())
+    }
+
+    pub fn select(db_conn: &Connection, condition: &Condition) -> Result<Vec<ViolationTable>>
+    {
+        let mut violations: Vec<ViolationTable> = Vec::new();
+        let mut sql = "SELECT violation_id, flight_id, airspace, class, limits, start, end, alt, lat, long FROM violations WHERE ".to_string();
+        sql.push_str(&condition.sql());
+
+        let mut stmt = db_conn.prepare(&sql)?;
+
+        let rows = stmt
+            .query_map(condition.params(), |row| {
+                Ok(ViolationTable {
+                    violation_id: row.get(0)?,
+                    flight_id: row.get(1)?,
+                    airspace: row.get(2)?,
+                    class: row.get(3)?,
+                    limits: row.get(4)?,
+                    start: row.get(5)?,
+                    end: row.get(6)?,
+                    alt: row.get(7)?,
+                    lat: row.get(8)?,
+                    long: row.get(9)?,
+                })
+            })?;
+
+        for violation in rows {
+            if let Ok(v) = violation {
+                violations.push(v)
+            }
+        }
+
+        Ok(violations)
+    }
+
+    pub fn get_by_flight(db_conn: &Connection, flight_id: u32) -> Result<Vec<ViolationTable>>
+    {
+        ViolationTable::select(db_conn, &Condition::all().eq("flight_id", flight_id).order_by("start"))
+    }
+}
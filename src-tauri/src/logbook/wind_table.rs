@@ -0,0 +1,144 @@
+use rusqlite::Connection;
+use anyhow::Result;
+use serde::{Serialize, Deserialize};
+
+use crate::flight_track::wind::WindEstimate;
+use super::query::Condition;
+
+const LAYER_HEIGHT: u32 = 250;// m
+
+#[derive(Clone,Debug,Serialize,Deserialize)]
+pub struct WindTable
+{
+    pub wind_id: u32,
+    pub flight_id: u32,
+    pub time: i64,// timestamp
+    pub alt: u32,
+    pub speed: f32,// m/s
+    pub direction: f32,// deg, where the wind comes from
+    pub airspeed: f32,// m/s
+}
+
+// Mean wind of an altitude band, for the wind by altitude chart
+#[derive(Clone,Debug,Serialize,Deserialize)]
+pub struct WindLayer
+{
+    pub alt_min: u32,
+    pub alt_max: u32,
+    pub speed: f32,
+    pub direction: f32,
+    pub nb_estimate: u32,
+}
+
+impl From<&WindEstimate> for WindTable {
+    fn from(wind: &WindEstimate) -> Self {
+        WindTable {
+            wind_id: 0,
+            flight_id: 0,
+            time: wind.time.timestamp(),
+            alt: wind.alt,
+            speed: wind.speed,
+            direction: wind.direction,
+            airspeed: wind.airspeed,
+        }
+    }
+}
+
+impl WindTable
+{
+    pub fn store(db_conn: &Connection, wind: WindTable) -> Result<()>
+    {
+        db_conn.execute(
+            "INSERT INTO winds (flight_id, time, alt, speed, direction, airspeed)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                (
+                    wind.flight_id,
+                    wind.time,
+                    wind.alt,
+                    wind.speed,
+                    wind.direction,
+                    wind.airspeed,
+                ),
+            )?;
+
+        Ok(())
+    }
+
+    pub fn delete(db_conn: &Connection, condition: &Condition) -> Result<()>
+    {
+        let mut sql = "DELETE FROM winds WHERE ".to_string();
+        sql.push_str(&condition.sql());
+        db_conn.execute(&sql, condition.params())?;
+
+        Ok(())
+    }
+
+    pub fn select(db_conn: &Connection, condition: &Condition) -> Result<Vec<WindTable>>
+    {
+        let mut winds: Vec<WindTable> = Vec::new();
+        let mut sql = "SELECT wind_id, flight_id, time, alt, speed, direction, airspeed FROM winds WHERE ".to_string();
+        sql.push_str(&condition.sql());
+
+        let mut stmt = db_conn.prepare(&sql)?;
+
+        let rows = stmt
+            .query_map(condition.params(), |row| {
+                Ok(WindTable {
+                    wind_id: row.get(0)?,
+                    flight_id: row.get(1)?,
+                    time: row.get(2)?,
+                    alt: row.get(3)?,
+                    speed: row.get(4)?,
+                    direction: row.get(5)?,
+                    airspeed: row.get(6)?,
+                })
+            })?;
+
+        for wind in rows {
+            if let Ok(w) = wind {
+                winds.push(w)
+            }
+        }
+
+        Ok(winds)
+    }
+
+    pub fn get_by_flight(db_conn: &Connection, flight_id: u32) -> Result<Vec<WindTable>>
+    {
+        WindTable::select(db_conn, &Condition::all().eq("flight_id", flight_id).order_by("time"))
+    }
+
+    // Estimates grouped by altitude band, directions are averaged as vectors
+    pub fn layers(winds: &Vec<WindTable>) -> Vec<WindLayer>
+    {
+        let mut layers: Vec<WindLayer> = Vec::new();
+        let mut bands: Vec<u32> = winds.iter().map(|w| w.alt / LAYER_HEIGHT).collect();
+        bands.sort();
+        bands.dedup();
+
+        for band in bands
+        {
+            let (mut east, mut north, mut speed) = (0.0f64, 0.0f64, 0.0f64);
+            let mut nb_estimate: u32 = 0;
+
+            for w in winds.iter().filter(|w| w.alt / LAYER_HEIGHT == band)
+            {
+                let from = (w.direction as f64).to_radians();
+                east += w.speed as f64 * from.sin();
+                north += w.speed as f64 * from.cos();
+                speed += w.speed as f64;
+                nb_estimate += 1;
+            }
+
+            layers.push(WindLayer {
+                alt_min: band * LAYER_HEIGHT,
+                alt_max: (band + 1) * LAYER_HEIGHT,
+                speed: (speed / nb_estimate as f64) as f32,
+                direction: ((east.atan2(north).to_degrees() + 360.0) % 360.0) as f32,
+                nb_estimate,
+            });
+        }
+
+        layers
+    }
+}
@@ -1,9 +1,10 @@
 use rusqlite::Connection;
 use anyhow::{Result, bail};
+use serde::{Serialize, Deserialize};
 
-use super::DATABASE_PATH;
+use super::query::{Condition, Changes};
 
-#[derive(Clone)]
+#[derive(Clone,Debug,Serialize,Deserialize)]
 pub struct WingTable
 {
     pub wing_id: u32,
@@ -14,55 +15,28 @@ pub struct WingTable
 
 impl WingTable
 {
-    pub fn create() -> Result<()>{
-        let db_conn = Connection::open(DATABASE_PATH)?;
-
-        match db_conn.execute(
-            "CREATE TABLE wings (
-                wing_id     INTEGER PRIMARY KEY,
-                name        TEXT UNIQUE,
-                info        TEXT,
-                def         BOOLEAN
-            );",
-            (), // empty list of parameters.
-        ){
-            Ok(_) => {
-                // First creation of table, insert default wing
-                db_conn.execute("INSERT INTO wings (wing_id,name,info,def) VALUES (0,'default','',1)",())?;
-            },
-            Err(e) => {
-                //Table already exist don't pannic
-                dbg!(e.to_string());
-            }
-        }
-        db_conn.close().unwrap_or_default();
-        Ok(())
-    }
-
-    pub fn store(wing: WingTable) -> Result<()>
+    pub fn store(db_conn: &Connection, wing: WingTable) -> Result<()>
     {
-        let db_conn = Connection::open(DATABASE_PATH)?;
         db_conn.execute(
-            "INSERT INTO wings (name, info)
-                VALUES (?1, ?2)",
+            "INSERT INTO wings (name, info, def)
+                VALUES (?1, ?2, ?3)",
                 (
                     wing.name.clone(),
                     wing.info,
+                    false,
                 ),
             )?;
 
             //TODO switch default
             if wing.def {
-                WingTable::set_default_wing(None,Some(wing.name))?;
+                WingTable::set_default_wing(db_conn, None,Some(wing.name))?;
             }
 
-        db_conn.close().unwrap_or_default();
         Ok(())
     }
 
-    pub fn get(id: u32) -> Result<WingTable>
+    pub fn get(db_conn: &Connection, id: u32) -> Result<WingTable>
     {
-        let db_conn = Connection::open(DATABASE_PATH)?;
         let mut stmt: rusqlite::Statement<'_> = db_conn.prepare("SELECT * FROM wings WHERE wing_id=?1")?;
 
         let wing = stmt
@@ -78,43 +52,38 @@ impl WingTable
         Ok(wing)
     }
 
-    pub fn delete(condition: String) -> Result<()>
+    pub fn delete(db_conn: &Connection, condition: &Condition) -> Result<()>
     {
         let mut sql = "DELETE FROM wings WHERE ".to_string();
-        sql.push_str(&condition);
-        let db_conn = Connection::open(DATABASE_PATH)?;
-        db_conn.execute(&sql,())?;
-
-        db_conn.close().unwrap_or_default();
+        sql.push_str(&condition.sql());
+        db_conn.execute(&sql, condition.params())?;
 
         Ok(())
     }
 
-    pub fn update(set: String, condition: String) -> Result<()>
+    pub fn update(db_conn: &Connection, changes: &Changes, condition: &Condition) -> Result<()>
     {
-        let mut sql = "UPDATE wings SET ".to_string();
-        sql.push_str(&set);
-        sql.push_str(" WHERE ");
-        sql.push_str(&condition);
-        let db_conn = Connection::open(DATABASE_PATH)?;
-        db_conn.execute(&sql,())?;
+        if changes.is_empty()
+        {
+            return Ok(());
+        }
 
-        db_conn.close().unwrap_or_default();
+        let (sql, params) = changes.update("wings", condition);
+        db_conn.execute(&sql, params)?;
 
         Ok(())
     }
 
-    pub fn select(condition: String) -> Result<Vec<WingTable>>
+    pub fn select(db_conn: &Connection, condition: &Condition) -> Result<Vec<WingTable>>
     {
-        let db_conn = Connection::open(DATABASE_PATH)?;
         let mut wings: Vec<WingTable> = Vec::new();
         let mut sql = "SELECT * FROM wings WHERE ".to_string();
-        sql.push_str(&condition);
+        sql.push_str(&condition.sql());
 
         let mut stmt = db_conn.prepare(&sql)?;
 
         let rows = stmt
-            .query_map([], |row| {
+            .query_map(condition.params(), |row| {
                 Ok(WingTable {
                     wing_id: row.get(0)?,
                     name: row.get(1)?,
@@ -132,14 +101,46 @@ impl WingTable
         Ok(wings)
     }
 
-    pub fn select_all() -> Result<Vec<WingTable>>
+    pub fn select_all(db_conn: &Connection) -> Result<Vec<WingTable>>
     {
-        WingTable::select("1".to_string())
+        WingTable::select(db_conn, &Condition::all())
     }
 
-    pub fn set_default_wing(id:Option<i32>, name: Option<String>) -> Result<()>
+    pub fn match_wing(db_conn: &Connection, glider_type: &String) -> Result<Option<WingTable>>
+    {
+        // Compare names without case, spaces or dashes : "Enzo 3" matches "ENZO-3"
+        let normalize = |name: &String| -> String {
+            name.to_lowercase().chars().filter(|c| c.is_alphanumeric()).collect()
+        };
+
+        let glider = normalize(glider_type);
+        if glider.is_empty()
+        {
+            return Ok(None);
+        }
+
+        let wings = WingTable::select_all(db_conn)?;
+        let mut res: Option<WingTable> = None;
+
+        for wing in wings
+        {
+            let name = normalize(&wing.name);
+            if name == glider
+            {
+                return Ok(Some(wing));
+            }
+
+            if res.is_none() && !name.is_empty() && (name.contains(&glider) || glider.contains(&name))
+            {
+                res = Some(wing);
+            }
+        }
+
+        Ok(res)
+    }
+
+    pub fn set_default_wing(db_conn: &Connection, id:Option<i32>, name: Option<String>) -> Result<()>
     {
-        let db_conn = Connection::open(DATABASE_PATH)?;
         db_conn.execute("UPDATE wings SET def=?1 WHERE def=?2", [0,1])?;
 
         if let Some(i) = id
@@ -152,9 +153,18 @@ impl WingTable
         Ok(())
     }
 
-    pub fn get_default_wing() -> Result<WingTable>
+    pub fn get_default_wing(db_conn: &Connection) -> Result<WingTable>
     {
-        let wing = WingTable::select("def=1".to_string())?;
+        let wing = WingTable::select(db_conn, &Condition::all().eq("def", true))?;
         Ok(wing[0].clone())
     }
+
+    pub fn last_wing_id(db_conn: &Connection) -> Result<u32>
+    {
+        let mut stmt = db_conn.prepare("SELECT wing_id FROM wings ORDER BY wing_id DESC LIMIT 1;")?;
+
+        let id = stmt.query_row([], | row | Ok(row.get(0).unwrap_or(0)))?;
+
+        Ok(id)
+    }
 }
\ No newline at end of file
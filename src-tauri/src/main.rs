@@ -1,26 +1,347 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use logbook::flight_table::FlightTable;
+use std::path::Path;
+
+use error::CommandError;
+use export::{kml::KmlExport, gpx::GpxExport, geojson::GeoJsonExport};
+use anyhow::anyhow;
+use flight_track::{igc_validator::IgcValidation, scoring};
+use logbook::{Logbook, FlightStatistic, ScoreFailure, DATABASE_PATH, database::Database, duplicate::{DuplicatePolicy, SuspectedDuplicate}, import::{ImportJobs, ImportReport}, query::Condition, flight_table::{FlightTable, FlightFilter, ManualFlight}, glide_table::GlideTable, site_table::SiteTable, tag_table::TagTable, thermal_table::ThermalTable, wind_table::WindLayer, wing_table::WingTable, violation_table::ViolationTable};
+use tauri::{State, Window, api::dialog::blocking::FileDialogBuilder};
 
 mod logbook;
 mod flight_track;
+mod export;
+mod terrain;
+mod airspace;
+mod error;
+
+#[tauri::command]
+fn history(db: State<Database>) -> Result<Vec<FlightTable>,CommandError>{
+    let db_conn = db.connection()?;
+    let flights = FlightTable::select_all(&db_conn)?;
+    Ok(flights)
+}
 
 #[tauri::command]
-fn history() -> Result<Vec<FlightTable>, String>{
-    let flights = FlightTable::select_all().map_err(|err| err.to_string())?;
+fn filter(db: State<Database>, filter: FlightFilter) -> Result<Vec<FlightTable>,CommandError>{
+    let db_conn = db.connection()?;
+    let flights = FlightTable::filter(&db_conn, &filter)?;
     Ok(flights)
 }
 
 #[tauri::command]
-fn select(id: u32) -> Result<FlightTable,String>{
-    let flight = FlightTable::get(id).map_err(|err| err.to_string())?;
+fn select(db: State<Database>, id: u32) -> Result<FlightTable,CommandError>{
+    let db_conn = db.connection()?;
+    let flight = FlightTable::get(&db_conn, id)?;
     Ok(flight)
 }
 
+#[tauri::command]
+fn update_flight(db: State<Database>, flight: FlightTable) -> Result<(),CommandError>{
+    let db_conn = db.connection()?;
+    Logbook::update_flight(&db_conn, &flight)?;
+    Ok(())
+}
+
+// Flight entered by hand, returns its id
+#[tauri::command]
+fn add_manual_flight(db: State<Database>, flight: ManualFlight) -> Result<(u32,Vec<String>),CommandError>{
+    let mut db_conn = db.connection()?;
+    let added = Logbook::add_manual_flight(&mut db_conn, flight)?;
+    Ok(added)
+}
+
+#[tauri::command]
+fn delete_flight(db: State<Database>, id: u32) -> Result<(),CommandError>{
+    let mut db_conn = db.connection()?;
+    Logbook::delete_flight(&mut db_conn, id)?;
+    Ok(())
+}
+
+// Async so the blocking open dialog doesn't run on the main thread.
+// Returns the id of the background job, None when the dialog is cancelled.
+// The job sends import-progress events for each file then import-finished with the report.
+#[tauri::command]
+async fn import(window: Window, db: State<'_, Database>, jobs: State<'_, ImportJobs>, folder: bool, duplicates: Option<DuplicatePolicy>) -> Result<Option<u32>,CommandError>{
+    let dialog = FileDialogBuilder::new().add_filter("Track", &["igc", "gpx"]);
+    let path = match if folder { dialog.pick_folder() } else { dialog.pick_file() } {
+        None => return Ok(None),
+        Some(p) => p,
+    };
+    let progress_window = window.clone();
+    let job_id = jobs.start(
+        db.inner().clone(),
+        &path,
+        duplicates.unwrap_or_default(),
+        move |progress| progress_window.emit("import-progress", progress).unwrap_or_default(),
+        move |report| window.emit("import-finished", report).unwrap_or_default(),
+    )?;
+    Ok(Some(job_id))
+}
+
+// Every flight scored again under a rule set, returns the flights that failed
+#[tauri::command]
+fn rescore(db: State<Database>, rules: String) -> Result<Vec<ScoreFailure>,CommandError>{
+    let rules = match scoring::rules(&rules) {
+        None => return Err(anyhow!("Unknown rule set {}", rules).into()),
+        Some(r) => r,
+    };
+    let db_conn = db.connection()?;
+    let failures = Logbook::rescore(&db_conn, rules.as_ref())?;
+    Ok(failures)
+}
+
+#[tauri::command]
+fn cancel_import(jobs: State<ImportJobs>, id: u32) -> bool{
+    jobs.cancel(id)
+}
+
+// Choice made for a duplicate file of the import report
+#[tauri::command]
+fn import_duplicate(db: State<Database>, path: String, policy: DuplicatePolicy) -> Result<ImportReport,CommandError>{
+    let mut db_conn = db.connection()?;
+    let report = Logbook::load_and_store(&mut db_conn, Path::new(&path), policy)?;
+    Ok(report)
+}
+
+#[tauri::command]
+fn scan_duplicates(db: State<Database>) -> Result<Vec<SuspectedDuplicate>,CommandError>{
+    let db_conn = db.connection()?;
+    let duplicates = Logbook::scan_duplicates(&db_conn)?;
+    Ok(duplicates)
+}
+
+#[tauri::command]
+fn statistic(db: State<Database>, filter: FlightFilter) -> Result<FlightStatistic,CommandError>{
+    let db_conn = db.connection()?;
+    let statistic = Logbook::statistic(&db_conn, &filter)?;
+    Ok(statistic)
+}
+
+#[tauri::command]
+fn sites(db: State<Database>) -> Result<Vec<SiteTable>,CommandError>{
+    let db_conn = db.connection()?;
+    let sites = SiteTable::select_all(&db_conn)?;
+    Ok(sites)
+}
+
+#[tauri::command]
+fn add_site(db: State<Database>, site: SiteTable) -> Result<(u32,Option<String>),CommandError>{
+    let db_conn = db.connection()?;
+    let added = Logbook::add_site(&db_conn, site)?;
+    Ok(added)
+}
+
+#[tauri::command]
+fn update_site(db: State<Database>, site: SiteTable) -> Result<(),CommandError>{
+    let db_conn = db.connection()?;
+    Logbook::update_site(&db_conn, &site)?;
+    Ok(())
+}
+
+#[tauri::command]
+fn delete_site(db: State<Database>, id: u32) -> Result<(),CommandError>{
+    let db_conn = db.connection()?;
+    Logbook::delete_site(&db_conn, id)?;
+    Ok(())
+}
+
+#[tauri::command]
+fn wings(db: State<Database>) -> Result<Vec<WingTable>,CommandError>{
+    let db_conn = db.connection()?;
+    let wings = WingTable::select_all(&db_conn)?;
+    Ok(wings)
+}
+
+#[tauri::command]
+fn add_wing(db: State<Database>, wing: WingTable) -> Result<u32,CommandError>{
+    let db_conn = db.connection()?;
+    let id = Logbook::add_wing(&db_conn, wing)?;
+    Ok(id)
+}
+
+#[tauri::command]
+fn update_wing(db: State<Database>, wing: WingTable) -> Result<(),CommandError>{
+    let db_conn = db.connection()?;
+    Logbook::update_wing(&db_conn, &wing)?;
+    Ok(())
+}
+
+#[tauri::command]
+fn delete_wing(db: State<Database>, id: u32) -> Result<(),CommandError>{
+    let db_conn = db.connection()?;
+    Logbook::delete_wing(&db_conn, id)?;
+    Ok(())
+}
+
+#[tauri::command]
+fn tags(db: State<Database>) -> Result<Vec<TagTable>,CommandError>{
+    let db_conn = db.connection()?;
+    let tags = TagTable::select_all(&db_conn)?;
+    Ok(tags)
+}
+
+#[tauri::command]
+fn add_tag(db: State<Database>, name: String) -> Result<u32,CommandError>{
+    let db_conn = db.connection()?;
+    let id = Logbook::add_tag(&db_conn, &name)?;
+    Ok(id)
+}
+
+#[tauri::command]
+fn update_tag(db: State<Database>, tag: TagTable) -> Result<(),CommandError>{
+    let db_conn = db.connection()?;
+    Logbook::update_tag(&db_conn, &tag)?;
+    Ok(())
+}
+
+#[tauri::command]
+fn delete_tag(db: State<Database>, id: u32) -> Result<(),CommandError>{
+    let mut db_conn = db.connection()?;
+    Logbook::delete_tag(&mut db_conn, id)?;
+    Ok(())
+}
+
+#[tauri::command]
+fn flight_tags(db: State<Database>, id: u32) -> Result<Vec<TagTable>,CommandError>{
+    let db_conn = db.connection()?;
+    let tags = TagTable::get_by_flight(&db_conn, id)?;
+    Ok(tags)
+}
+
+#[tauri::command]
+fn tag_flight(db: State<Database>, flight_id: u32, tag_id: u32) -> Result<(),CommandError>{
+    let db_conn = db.connection()?;
+    TagTable::associate(&db_conn, flight_id, tag_id)?;
+    Ok(())
+}
+
+#[tauri::command]
+fn untag_flight(db: State<Database>, flight_id: u32, tag_id: u32) -> Result<(),CommandError>{
+    let db_conn = db.connection()?;
+    TagTable::dissociate(&db_conn, &Condition::all().eq("asso_flight_id", flight_id).eq("asso_tag_id", tag_id))?;
+    Ok(())
+}
+
+#[tauri::command]
+fn validation(db: State<Database>, id: u32) -> Result<IgcValidation,CommandError>{
+    let db_conn = db.connection()?;
+    let validation = Logbook::validation(&db_conn, id)?;
+    Ok(validation)
+}
+
+#[tauri::command]
+fn thermals(db: State<Database>, id: u32) -> Result<Vec<ThermalTable>,CommandError>{
+    let db_conn = db.connection()?;
+    let thermals = Logbook::thermals(&db_conn, id)?;
+    Ok(thermals)
+}
+
+#[tauri::command]
+fn glide(db: State<Database>, id: u32) -> Result<Option<GlideTable>,CommandError>{
+    let db_conn = db.connection()?;
+    let glide = Logbook::glide(&db_conn, id)?;
+    Ok(glide)
+}
+
+#[tauri::command]
+fn glide_comparison(db: State<Database>, id: u32) -> Result<Vec<GlideTable>,CommandError>{
+    let db_conn = db.connection()?;
+    let glides = Logbook::glide_comparison(&db_conn, id)?;
+    Ok(glides)
+}
+
+#[tauri::command]
+fn wind_profile(db: State<Database>, id: u32) -> Result<Vec<WindLayer>,CommandError>{
+    let db_conn = db.connection()?;
+    let layers = Logbook::wind_by_altitude(&db_conn, id)?;
+    Ok(layers)
+}
+
+#[tauri::command]
+fn set_altitude_source(db: State<Database>, id: u32, source: String) -> Result<Vec<String>,CommandError>{
+    let source = source.parse()?;
+    let db_conn = db.connection()?;
+    let warnings = Logbook::set_altitude_source(&db_conn, id, source)?;
+    Ok(warnings)
+}
+
+#[tauri::command]
+fn set_dem_folder(db: State<Database>, path: String) -> Result<(),CommandError>{
+    let db_conn = db.connection()?;
+    Logbook::set_dem_folder(&db_conn, Path::new(&path))?;
+    Ok(())
+}
+
+#[tauri::command]
+fn set_airspace_file(db: State<Database>, path: String) -> Result<(),CommandError>{
+    let db_conn = db.connection()?;
+    Logbook::set_airspace_file(&db_conn, Path::new(&path))?;
+    Ok(())
+}
+
+#[tauri::command]
+fn check_airspace(db: State<Database>) -> Result<(u32,Vec<String>),CommandError>{
+    let db_conn = db.connection()?;
+    let checked = Logbook::check_airspace(&db_conn)?;
+    Ok(checked)
+}
+
+#[tauri::command]
+fn violations(db: State<Database>, id: u32) -> Result<Vec<ViolationTable>,CommandError>{
+    let db_conn = db.connection()?;
+    let violations = Logbook::violations(&db_conn, id)?;
+    Ok(violations)
+}
+
+// Async so the blocking save dialog doesn't run on the main thread
+#[tauri::command]
+async fn export_kml(db: State<'_, Database>, id: u32) -> Result<bool,CommandError>{
+    let path = match FileDialogBuilder::new().add_filter("KML", &["kml"]).add_filter("KMZ", &["kmz"]).save_file() {
+        None => return Ok(false),
+        Some(p) => p,
+    };
+    let db_conn = db.connection()?;
+    KmlExport::export(&db_conn, id, &path)?;
+    Ok(true)
+}
+
+#[tauri::command]
+async fn export_gpx(db: State<'_, Database>, ids: Vec<u32>) -> Result<bool,CommandError>{
+    let path = match FileDialogBuilder::new().add_filter("GPX", &["gpx"]).save_file() {
+        None => return Ok(false),
+        Some(p) => p,
+    };
+    let db_conn = db.connection()?;
+    GpxExport::export(&db_conn, &ids, &path)?;
+    Ok(true)
+}
+
+#[tauri::command]
+async fn export_geojson(db: State<'_, Database>, ids: Vec<u32>) -> Result<bool,CommandError>{
+    let path = match FileDialogBuilder::new().add_filter("GeoJSON", &["geojson", "json"]).save_file() {
+        None => return Ok(false),
+        Some(p) => p,
+    };
+    let db_conn = db.connection()?;
+    GeoJsonExport::export(&db_conn, &ids, &path)?;
+    Ok(true)
+}
+
 fn main() {
     tauri::Builder::default()
-        .invoke_handler(tauri::generate_handler![history,select])
+        .manage(Database::open(DATABASE_PATH).expect("error while opening the flight database"))
+        .manage(ImportJobs::default())
+        .invoke_handler(tauri::generate_handler![
+            history,filter,select,add_manual_flight,update_flight,delete_flight,import,cancel_import,import_duplicate,scan_duplicates,rescore,statistic,
+            sites,add_site,update_site,delete_site,
+            wings,add_wing,update_wing,delete_wing,
+            tags,add_tag,update_tag,delete_tag,flight_tags,tag_flight,untag_flight,
+            validation,thermals,glide,glide_comparison,wind_profile,set_altitude_source,set_dem_folder,set_airspace_file,check_airspace,violations,
+            export_kml,export_gpx,export_geojson
+        ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
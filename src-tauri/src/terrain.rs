@@ -0,0 +1,110 @@
+use std::{collections::HashMap, fs, path::{Path, PathBuf}};
+use anyhow::Result;
+
+use crate::flight_track::FlightProfile;
+use self::geotiff::Bounds;
+
+pub mod hgt;
+pub mod geotiff;
+
+const MAX_BELOW_GROUND: i32 = 100;// m - DEM and GPS errors included
+const MAX_ALTITUDE: u32 = 10000;// m
+
+pub trait Tile {
+    // Ground elevation (m), None outside the tile or on a void
+    fn elevation(&self, lat: f64, long: f64) -> Option<f64>;
+}
+
+// DEM tiles found in a folder, each one is read on first use
+pub struct Terrain {
+    hgt: HashMap<(i32, i32), PathBuf>,
+    geotiff: Vec<(Bounds, PathBuf)>,
+    tiles: HashMap<PathBuf, Option<Box<dyn Tile>>>,
+}
+
+impl Terrain {
+    pub fn open(folder: &Path) -> Result<Self>
+    {
+        let mut terrain = Terrain {
+            hgt: HashMap::new(),
+            geotiff: Vec::new(),
+            tiles: HashMap::new(),
+        };
+
+        for entry in fs::read_dir(folder)?
+        {
+            let path = entry?.path();
+            let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+
+            match extension.as_deref() {
+                Some("hgt") => {
+                    if let Some(corner) = hgt::corner(&path) {
+                        terrain.hgt.insert(corner, path);
+                    }
+                },
+                Some("tif") | Some("tiff") => {
+                    if let Ok(bounds) = geotiff::bounds(&path) {
+                        terrain.geotiff.push((bounds, path));
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        Ok(terrain)
+    }
+
+    pub fn elevation(&mut self, lat: f32, long: f32) -> Option<i32>
+    {
+        let (lat, long) = (lat as f64, long as f64);
+        let mut paths: Vec<PathBuf> = Vec::new();
+
+        if let Some(path) = self.hgt.get(&(lat.floor() as i32, long.floor() as i32)) {
+            paths.push(path.clone());
+        }
+        paths.extend(self.geotiff.iter().filter(|(b, _)| b.contains(lat, long)).map(|(_, p)| p.clone()));
+
+        for path in paths
+        {
+            let tile = self.tiles.entry(path.clone()).or_insert_with(|| Self::load(&path));
+
+            if let Some(h) = tile.as_ref().and_then(|t| t.elevation(lat, long)) {
+                return Some(h.round() as i32);
+            }
+        }
+
+        None
+    }
+
+    // Unreadable tiles are skipped, the next one covering the point is used
+    fn load(path: &Path) -> Option<Box<dyn Tile>>
+    {
+        let is_hgt = path.extension().and_then(|e| e.to_str()).map(|e| e.eq_ignore_ascii_case("hgt")).unwrap_or(false);
+
+        if is_hgt
+        {
+            hgt::read(path).ok().map(|t| Box::new(t) as Box<dyn Tile>)
+        }
+        else
+        {
+            geotiff::read(path).ok().map(|t| Box::new(t) as Box<dyn Tile>)
+        }
+    }
+
+    // Ground under every point of the profile
+    pub fn ground_profile(&mut self, profile: &mut FlightProfile)
+    {
+        for pt in profile.points.iter_mut()
+        {
+            pt.ground = self.elevation(pt.lat, pt.lng);
+        }
+    }
+}
+
+// Points under the ground or above anything a paraglider can reach, most likely altitude errors
+pub fn impossible_altitudes(profile: &FlightProfile) -> usize
+{
+    profile.points.iter()
+        .filter(|pt| pt.alt > MAX_ALTITUDE || pt.agl().map(|agl| agl < -MAX_BELOW_GROUND).unwrap_or(false))
+        .count()
+}
@@ -0,0 +1,187 @@
+use std::{fs::File, path::Path};
+use anyhow::{Result, bail};
+use tiff::{decoder::{Decoder, DecodingResult}, tags::Tag};
+
+use super::Tile;
+
+// Single band elevation GeoTIFF in geographic coordinates (WGS84 degrees), as distributed for SRTM or Copernicus DEM
+pub struct GeoTiffTile {
+    pub bounds: Bounds,
+    width: usize,
+    height: usize,
+    nodata: Option<f64>,
+    data: Vec<f64>,
+}
+
+#[derive(Clone,Copy,Debug)]
+pub struct Bounds {
+    pub west: f64,
+    pub north: f64,
+    pub east: f64,
+    pub south: f64,
+}
+
+impl Bounds {
+    pub fn contains(&self, lat: f64, long: f64) -> bool
+    {
+        self.south <= lat && lat <= self.north && self.west <= long && long <= self.east
+    }
+}
+
+// Area covered, from the tags only so the folder can be indexed without decoding every image
+pub fn bounds(path: &Path) -> Result<Bounds>
+{
+    let mut decoder = Decoder::new(File::open(path)?)?;
+
+    georeference(&mut decoder)
+}
+
+pub fn read(path: &Path) -> Result<GeoTiffTile>
+{
+    let mut decoder = Decoder::new(File::open(path)?)?;
+    let bounds = georeference(&mut decoder)?;
+    let (width, height) = decoder.dimensions()?;
+    let nodata = decoder.get_tag_ascii_string(Tag::GdalNodata).ok().and_then(|n| n.trim_matches(char::from(0)).trim().parse().ok());
+
+    let data: Vec<f64> = match decoder.read_image()? {
+        DecodingResult::I16(d) => d.into_iter().map(|h| h as f64).collect(),
+        DecodingResult::U16(d) => d.into_iter().map(|h| h as f64).collect(),
+        DecodingResult::I32(d) => d.into_iter().map(|h| h as f64).collect(),
+        DecodingResult::F32(d) => d.into_iter().map(|h| h as f64).collect(),
+        DecodingResult::F64(d) => d,
+        _ => bail!("Unsupported GeoTIFF sample format in {}", path.display()),
+    };
+
+    if data.len() != width as usize * height as usize
+    {
+        bail!("Only single band GeoTIFF are supported ({})", path.display());
+    }
+
+    Ok(GeoTiffTile {
+        bounds,
+        width: width as usize,
+        height: height as usize,
+        nodata,
+        data,
+    })
+}
+
+fn georeference(decoder: &mut Decoder<File>) -> Result<Bounds>
+{
+    let (width, height) = decoder.dimensions()?;
+    let scale = decoder.get_tag_f64_vec(Tag::ModelPixelScaleTag)?;
+    let tiepoint = decoder.get_tag_f64_vec(Tag::ModelTiepointTag)?;
+
+    if scale.len() < 2 || tiepoint.len() < 6
+    {
+        bail!("Invalid GeoTIFF georeference");
+    }
+
+    // Tie point : raster (i, j) is at model (x, y)
+    let west = tiepoint[3] - tiepoint[0] * scale[0];
+    let north = tiepoint[4] + tiepoint[1] * scale[1];
+    let bounds = Bounds {
+        west,
+        north,
+        east: west + width as f64 * scale[0],
+        south: north - height as f64 * scale[1],
+    };
+
+    if bounds.west < -180.0 || bounds.east > 180.0 || bounds.south < -90.0 || bounds.north > 90.0
+    {
+        bail!("Only GeoTIFF in latitude/longitude are supported");
+    }
+
+    Ok(bounds)
+}
+
+impl GeoTiffTile {
+    fn sample(&self, row: usize, col: usize) -> Option<f64>
+    {
+        let h = self.data[row * self.width + col];
+
+        if Some(h) == self.nodata || h.is_nan()
+        {
+            return None;
+        }
+
+        Some(h)
+    }
+}
+
+impl Tile for GeoTiffTile {
+    // Bilinear interpolation between pixel centres
+    fn elevation(&self, lat: f64, long: f64) -> Option<f64>
+    {
+        if !self.bounds.contains(lat, long) || self.width < 2 || self.height < 2
+        {
+            return None;
+        }
+
+        let x = ((long - self.bounds.west) / (self.bounds.east - self.bounds.west) * self.width as f64 - 0.5)
+            .clamp(0.0, (self.width - 1) as f64);
+        let y = ((self.bounds.north - lat) / (self.bounds.north - self.bounds.south) * self.height as f64 - 0.5)
+            .clamp(0.0, (self.height - 1) as f64);
+
+        let (row, col) = ((y as usize).min(self.height - 2), (x as usize).min(self.width - 2));
+        let (dy, dx) = (y - row as f64, x - col as f64);
+
+        let top = self.sample(row, col)? * (1.0 - dx) + self.sample(row, col + 1)? * dx;
+        let bottom = self.sample(row + 1, col)? * (1.0 - dx) + self.sample(row + 1, col + 1)? * dx;
+
+        Some(top * (1.0 - dy) + bottom * dy)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // 3x3 pixels covering 45°N-46°N 6°E-7°E, the first row is the north edge
+    fn tile(nodata: Option<f64>) -> GeoTiffTile
+    {
+        GeoTiffTile {
+            bounds: Bounds { west: 6.0, north: 46.0, east: 7.0, south: 45.0 },
+            width: 3,
+            height: 3,
+            nodata,
+            data: vec![100.0, 200.0, 300.0, 400.0, 500.0, 600.0, 700.0, 800.0, 900.0],
+        }
+    }
+
+    #[test]
+    fn elevation()
+    {
+        let tile = tile(None);
+
+        // Corners are in the outer half pixels, the value of the corner pixel
+        assert_eq!(tile.elevation(46.0, 6.0), Some(100.0));
+        assert_eq!(tile.elevation(45.0, 7.0), Some(900.0));
+
+        // Edge, middle of the north row
+        assert_eq!(tile.elevation(46.0, 6.5), Some(200.0));
+
+        // Centre pixel and halfway between pixel centres
+        assert_eq!(tile.elevation(45.5, 6.5), Some(500.0));
+        assert_eq!(tile.elevation(45.75, 6.25), Some(200.0));
+
+        assert_eq!(tile.elevation(44.9, 6.5), None);
+    }
+
+    #[test]
+    fn nodata()
+    {
+        let mut tile = tile(Some(-9999.0));
+        tile.data[4] = -9999.0;
+        assert_eq!(tile.elevation(45.5, 6.5), None);
+
+        tile.data[4] = f64::NAN;
+        assert_eq!(tile.elevation(45.5, 6.5), None);
+
+        // Cells away from the missing pixel
+        tile.data[4] = 500.0;
+        tile.data[8] = -9999.0;
+        assert_eq!(tile.elevation(45.75, 6.25), Some(200.0));
+    }
+}
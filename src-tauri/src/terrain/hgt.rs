@@ -0,0 +1,144 @@
+use std::{fs, path::Path};
+use anyhow::{Result, bail};
+
+use super::Tile;
+
+const VOID: i16 = -32768;
+
+// SRTM tile : 1°x1° grid of big endian i16, north to south, 1201 (3") or 3601 (1") samples a side
+pub struct HgtTile {
+    lat: i32,// south west corner
+    long: i32,
+    size: usize,
+    data: Vec<i16>,
+}
+
+// South west corner from the file name, N45E006.hgt covers 45°N-46°N 6°E-7°E
+pub fn corner(path: &Path) -> Option<(i32, i32)>
+{
+    let name = path.file_stem()?.to_str()?.to_uppercase();
+    if name.len() != 7
+    {
+        return None;
+    }
+
+    let lat: i32 = name[1..3].parse().ok()?;
+    let long: i32 = name[4..7].parse().ok()?;
+
+    let lat = match &name[0..1] {
+        "N" => lat,
+        "S" => -lat,
+        _ => return None,
+    };
+    let long = match &name[3..4] {
+        "E" => long,
+        "W" => -long,
+        _ => return None,
+    };
+
+    Some((lat, long))
+}
+
+pub fn read(path: &Path) -> Result<HgtTile>
+{
+    let (lat, long) = match corner(path) {
+        Some(c) => c,
+        None => bail!("Invalid HGT file name {}", path.display()),
+    };
+    let bytes = fs::read(path)?;
+
+    let size = match bytes.len() {
+        2884802 => 1201,
+        25934402 => 3601,
+        l => bail!("Invalid HGT file size {} for {}", l, path.display()),
+    };
+
+    Ok(HgtTile {
+        lat,
+        long,
+        size,
+        data: bytes.chunks_exact(2).map(|b| i16::from_be_bytes([b[0], b[1]])).collect(),
+    })
+}
+
+impl HgtTile {
+    fn sample(&self, row: usize, col: usize) -> Option<f64>
+    {
+        match self.data[row * self.size + col] {
+            VOID => None,
+            h => Some(h as f64),
+        }
+    }
+}
+
+impl Tile for HgtTile {
+    // Bilinear interpolation of the four samples around the point
+    fn elevation(&self, lat: f64, long: f64) -> Option<f64>
+    {
+        let cells = (self.size - 1) as f64;
+        let y = (self.lat as f64 + 1.0 - lat) * cells;
+        let x = (long - self.long as f64) * cells;
+
+        if !(0.0..=cells).contains(&y) || !(0.0..=cells).contains(&x)
+        {
+            return None;
+        }
+
+        let (row, col) = ((y as usize).min(self.size - 2), (x as usize).min(self.size - 2));
+        let (dy, dx) = (y - row as f64, x - col as f64);
+
+        let top = self.sample(row, col)? * (1.0 - dx) + self.sample(row, col + 1)? * dx;
+        let bottom = self.sample(row + 1, col)? * (1.0 - dx) + self.sample(row + 1, col + 1)? * dx;
+
+        Some(top * (1.0 - dy) + bottom * dy)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // 3x3 samples covering 45°N-46°N 6°E-7°E, the first row is the north edge
+    fn tile() -> HgtTile
+    {
+        HgtTile { lat: 45, long: 6, size: 3, data: vec![100, 200, 300, 400, 500, 600, 700, 800, 900] }
+    }
+
+    #[test]
+    fn corner_from_file_name()
+    {
+        assert_eq!(corner(Path::new("N45E006.hgt")), Some((45, 6)));
+        assert_eq!(corner(Path::new("S12W077.hgt")), Some((-12, -77)));
+        assert_eq!(corner(Path::new("/dem/s12w077.HGT")), Some((-12, -77)));
+        assert_eq!(corner(Path::new("X45E006.hgt")), None);
+        assert_eq!(corner(Path::new("N45E06.hgt")), None);
+    }
+
+    #[test]
+    fn elevation()
+    {
+        let mut tile = tile();
+
+        // Corners, the south west one is the last row
+        assert_eq!(tile.elevation(45.0, 6.0), Some(700.0));
+        assert_eq!(tile.elevation(46.0, 7.0), Some(300.0));
+
+        // Edges, on the last row and column of cells
+        assert_eq!(tile.elevation(45.0, 6.25), Some(750.0));
+        assert_eq!(tile.elevation(45.5, 7.0), Some(600.0));
+        assert_eq!(tile.elevation(45.75, 6.0), Some(250.0));
+
+        // Centre of the tile and of a cell
+        assert_eq!(tile.elevation(45.5, 6.5), Some(500.0));
+        assert_eq!(tile.elevation(45.75, 6.25), Some(300.0));
+
+        assert_eq!(tile.elevation(46.1, 6.5), None);
+        assert_eq!(tile.elevation(45.5, 5.9), None);
+
+        // A void sample only hides the cells around it
+        tile.data[0] = VOID;
+        assert_eq!(tile.elevation(45.75, 6.25), None);
+        assert_eq!(tile.elevation(45.25, 6.75), Some(700.0));
+    }
+}
@@ -3,7 +3,7 @@ const { invoke } = window.__TAURI__.tauri;
 class FlightList{
     constructor(select_callback)
     {
-        invoke('history').then((history) => this.build_tree(history,select_callback)).catch((error) => alert(error));
+        invoke('history').then((history) => this.build_tree(history,select_callback)).catch((error) => alert(error.message));
     }
 
     build_tree(history,select_callback)
@@ -50,14 +50,23 @@ class FlightList{
             
                 fl.innerHTML = "<img class=\"icon\" src=\"assets/Icons_calendar.svg\"><div>"+ flight.date +"</div>";
                 fl.innerHTML += "<img class=\"icon\" src=\"assets/Icons_clock.svg\"><div>"+ format_duration(flight.duration)+"</div>";
-                if(flight.code == '"tri"' || flight.code == '"fai"')
+                if(!flight.has_track)
                 {
-                    fl.innerHTML += "<img class=\"icon\" src=\"assets/Icons_tri.svg\">"
+                    // Flight entered by hand, no score to show
+                    fl.innerHTML += "<img class=\"icon\" src=\"assets/Icons_op.svg\">"
+                    fl.innerHTML += "<div>"+ format_distance(flight.distance,'')+"</div>";
+                    fl.innerHTML += "<div class=\"no_track\">no track</div>";
                 }else
                 {
-                    fl.innerHTML += "<img class=\"icon\" src=\"assets/Icons_op.svg\">"
+                    if(flight.code == '"tri"' || flight.code == '"fai"')
+                    {
+                        fl.innerHTML += "<img class=\"icon\" src=\"assets/Icons_tri.svg\">"
+                    }else
+                    {
+                        fl.innerHTML += "<img class=\"icon\" src=\"assets/Icons_op.svg\">"
+                    }
+                    fl.innerHTML += "<div>"+ format_distance(flight.score,flight.code)+"</div>";
                 }
-                fl.innerHTML += "<div>"+ format_distance(flight.score,flight.code)+"</div>";
 
                 fl.innerHTML = "<div>"+fl.innerHTML+"</div>";
             
@@ -75,7 +84,7 @@ class FlightList{
             }
             evt.currentTarget.setAttribute('id','selected');
 
-            invoke('select', {id:parseInt(evt.currentTarget.flight_id)}).then((flight) => select_callback(flight)).catch((error) => alert(error));
+            invoke('select', {id:parseInt(evt.currentTarget.flight_id)}).then((flight) => select_callback(flight)).catch((error) => alert(error.message));
         }
     }
 
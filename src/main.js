@@ -45,6 +45,11 @@ function load_flight(flight)
 {
   marker.setLatLng([0, 0]);
   trace.remove();
+  // Flight entered by hand, nothing to draw
+  if(!flight.has_track)
+  {
+    return;
+  }
   let geojson = JSON.parse(flight.track);
   trace = L.geoJSON(geojson, {
     style: function(feature) {
@@ -99,7 +104,7 @@ function load_flight(flight)
 
 // Tree list
 //let tree = new Tree();
-// invoke('history').then((history) => tree.build_tree(history,flight_select)).catch((error) => alert(error));
+// invoke('history').then((history) => tree.build_tree(history,flight_select)).catch((error) => alert(error.message));
 
 let flightlist = new FlightList(load_flight);
 
@@ -109,7 +114,7 @@ let flightlist = new FlightList(load_flight);
       prev.removeAttribute('id')
   }
   evt.currentTarget.setAttribute('id','selected');
-  invoke('select', {id:parseInt(evt.currentTarget.flight_id)}).then((flight) => load_flght(flight)).catch((error) => alert(error));;
+  invoke('select', {id:parseInt(evt.currentTarget.flight_id)}).then((flight) => load_flght(flight)).catch((error) => alert(error.message));;
 }*/
 
 window.addEventListener("DOMContentLoaded", () => {
@@ -15,9 +15,11 @@ class Profile
         this.fontSize = window.getComputedStyle(this.graph).fontSize;
         this.fontSize = parseInt(this.fontSize);
         this.data = CSVToArray(profile);  
+        // Ground row, only when a DEM covers the flight
+        this.ground = (this.data.length > 8) ? this.data[8] : [];
 
         let path = document.createElementNS('http://www.w3.org/2000/svg',"path");
-        let maxalt = Math.max(...this.data[1]);
+        let maxalt = Math.max(...this.data[1], ...this.ground.filter(g => g !== ""));
         // this.width_offset = this.graph.offsetWidth*0.06;
         // this.height_offset = this.graph.offsetHeight*0.3;
         this.width_offset = this.fontSize*6;
@@ -64,6 +66,7 @@ class Profile
         this.pointdata_text.appendChild(document.createElementNS('http://www.w3.org/2000/svg',"tspan"));
         this.pointdata_text.appendChild(document.createElementNS('http://www.w3.org/2000/svg',"tspan"));
         this.pointdata_text.appendChild(document.createElementNS('http://www.w3.org/2000/svg',"tspan"));
+        this.pointdata_text.appendChild(document.createElementNS('http://www.w3.org/2000/svg',"tspan"));
         axes.appendChild(this.pointdata_text);
       
         alt_line.setAttribute("d",altline_str);
@@ -84,6 +87,47 @@ class Profile
         path.setAttribute("stroke","#3A00E5");
         path.setAttribute("fill","none");
         path.setAttribute("stroke-width","3");
+
+        if(this.ground.some(g => g !== ""))
+        {
+            let terrain = document.createElementNS('http://www.w3.org/2000/svg',"path");
+            let bottom = this.graph.offsetHeight - this.height_offset/2;
+            let terrain_str = "M"+ (this.width_offset*0.8) +"," + bottom;
+
+            for (let index = 0; index < (this.data[1].length-1); index++) {
+              let ground = (this.ground[index] === "" || this.ground[index] === undefined) ? 0 : this.ground[index];
+              terrain_str += "L" + (index*this.xstep + this.width_offset*0.8) + "," + (bottom - ground*this.ystep);
+            }
+            terrain_str += "V" + bottom + "Z";
+
+            terrain.setAttribute("d",terrain_str);
+            terrain.setAttribute("fill","#A98B5F");
+            terrain.setAttribute("opacity","0.6");
+            this.svg.appendChild(terrain);
+
+            // Height above ground, on the altitude scale, broken where the DEM doesn't cover the flight
+            let agl = document.createElementNS('http://www.w3.org/2000/svg',"path");
+            let agl_str = "";
+            let move = true;
+
+            for (let index = 0; index < (this.data[1].length-1); index++) {
+              if(this.ground[index] === "" || this.ground[index] === undefined)
+              {
+                  move = true;
+                  continue;
+              }
+              let height = Math.max(this.data[1][index] - this.ground[index], 0);
+              agl_str += (move ? "M" : "L") + (index*this.xstep + this.width_offset*0.8) + "," + (bottom - height*this.ystep);
+              move = false;
+            }
+
+            agl.setAttribute("d",agl_str);
+            agl.setAttribute("stroke","#6B4F2A");
+            agl.setAttribute("fill","none");
+            agl.setAttribute("stroke-width","2");
+            this.svg.appendChild(agl);
+        }
+
         this.svg.appendChild(path);        
         this.svg.appendChild(axes);        
     }
@@ -126,6 +170,10 @@ class Profile
             this.pointdata_text.children[3].textContent = (this.data[3][i]*1).toFixed(1)  + "m/s";
             this.pointdata_text.children[3].setAttribute('dy',"1.2rem");
             this.pointdata_text.children[3].setAttribute('x',xpos);
+            let agl = (this.ground[i] === "" || this.ground[i] === undefined) ? "" : (this.data[1][i] - this.ground[i]) + "m AGL";
+            this.pointdata_text.children[4].textContent = agl;
+            this.pointdata_text.children[4].setAttribute('dy',"1.2rem");
+            this.pointdata_text.children[4].setAttribute('x',xpos);
             
             callback_move(this.data[4][i],this.data[5][i]);
         });